@@ -0,0 +1,268 @@
+// A depth limited negamax bot, with alpha-beta pruning
+// Like the rules, this doesnt know anything about bevy
+
+use crate::book::OpeningBook;
+use crate::rules::*;
+
+use std::time::Instant;
+
+pub const DEFAULT_DEPTH: u32 = 6;
+
+// Bigger than anything the heuristic can come up with
+const WIN_SCORE: i32 = 1_000_000;
+
+// How many positions get searched between looking at the clock, which is a lot slower than a move
+const CLOCK_CHECK_INTERVAL: u32 = 1024;
+
+// Stops a search once its time is up, see `best_move_until`
+struct Search {
+    deadline: Option<Instant>,
+    nodes: u32,
+    stopped: bool,
+}
+
+impl Search {
+    fn new(deadline: Option<Instant>) -> Self {
+        Search {
+            deadline,
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        self.nodes += 1;
+        if !self.stopped && self.nodes % CLOCK_CHECK_INTERVAL == 0 {
+            self.stopped = self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.stopped
+    }
+}
+
+// Columns in the middle take part in more lines, so they are usually better, and get searched first
+pub fn center_first_order(config: &BoardConfig) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..config.columns).collect();
+    order.sort_by_key(|&col| (2 * col).abs_diff(config.columns - 1));
+    order
+}
+
+// Every move the rules allow, in the order they should be searched
+// Normal chips come before pops and specials, since they are usually better
+fn move_order(position: &Position) -> Vec<Move> {
+    let columns = position.config().columns;
+    let mut moves = position.all_legal_moves();
+    moves.sort_by_key(|next| {
+        let normal = matches!(next, Move::Drop(_) | Move::Place { .. });
+        (!normal, (2 * next.col()).abs_diff(columns - 1))
+    });
+    moves
+}
+
+// The best move for whoever is to move, or None if the game is already over
+pub fn best_move(position: &Position, depth: u32) -> Option<Move> {
+    search_best_move(position, depth, &mut Search::new(None))
+}
+
+// Same as `best_move`, but gives up with None if it hasnt finished by `deadline`
+pub fn best_move_until(position: &Position, depth: u32, deadline: Instant) -> Option<Move> {
+    let mut search = Search::new(Some(deadline));
+    let best = search_best_move(position, depth, &mut search);
+
+    // Anything it found is only half searched
+    if search.stopped {
+        None
+    } else {
+        best
+    }
+}
+
+fn search_best_move(position: &Position, depth: u32, search: &mut Search) -> Option<Move> {
+    let mut best = None;
+    let mut alpha = -WIN_SCORE - 1;
+    let beta = WIN_SCORE + 1;
+
+    for next_move in move_order(position) {
+        let mut next = *position;
+        let Ok(outcome) = next.play_move(next_move) else {
+            continue;
+        };
+
+        let score = score_move(
+            search,
+            position,
+            &next,
+            outcome,
+            depth.saturating_sub(1),
+            alpha,
+            beta,
+        );
+
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some(next_move);
+        }
+    }
+
+    best
+}
+
+// Same as `best_move`, but the book knows the perfect move if the position is early enough
+pub fn best_move_with_book(
+    position: &Position,
+    depth: u32,
+    book: Option<&OpeningBook>,
+) -> Option<Move> {
+    book.and_then(|book| book.best_move(position))
+        .map(Move::Drop)
+        .or_else(|| best_move(position, depth))
+}
+
+// Searches one move deeper at a time, up to `max_depth`, and plays whatever the deepest search that finished by
+// `deadline` found (or the book's move, if it has one)
+pub fn best_move_in_time(
+    position: &Position,
+    max_depth: u32,
+    deadline: Instant,
+    book: Option<&OpeningBook>,
+) -> Option<Move> {
+    if let Some(col) = book.and_then(|book| book.best_move(position)) {
+        return Some(Move::Drop(col));
+    }
+
+    // One move ahead is quick, and means there is always something to play
+    let mut best = best_move(position, 1)?;
+    for depth in 2..=max_depth {
+        match best_move_until(position, depth, deadline) {
+            Some(next) => best = next,
+            None => break,
+        }
+    }
+
+    Some(best)
+}
+
+// Win sooner rather than later, so the bot doesnt play with its food
+// A pop can make a line for the other player, which is a loss instead
+fn win_score(position: &Position, won: bool) -> i32 {
+    let score = WIN_SCORE - position.moves_played() as i32;
+    if won {
+        score
+    } else {
+        -score
+    }
+}
+
+// The score of a move that turned `position` into `next`, from the point of view of whoever played it
+fn score_move(
+    search: &mut Search,
+    position: &Position,
+    next: &Position,
+    outcome: MoveOutcome,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+) -> i32 {
+    match outcome {
+        MoveOutcome::Won { winner, .. } => win_score(next, winner == position.to_move()),
+        MoveOutcome::Draw { .. } => 0,
+        // Some variants give out extra turns, and then its still our score
+        MoveOutcome::Placed { .. } if next.to_move() == position.to_move() => {
+            negamax(search, next, depth, alpha, beta)
+        }
+        MoveOutcome::Placed { .. } => -negamax(search, next, depth, -beta, -alpha),
+    }
+}
+
+// The score of a position, from the point of view of whoever is to move
+// Once the search is out of time the scores dont mean anything, and it just unwinds as quickly as it can
+fn negamax(search: &mut Search, position: &Position, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+    if search.out_of_time() {
+        return 0;
+    }
+
+    if depth == 0 {
+        return evaluate(position);
+    }
+
+    let mut best = -WIN_SCORE - 1;
+
+    for next_move in move_order(position) {
+        let mut next = *position;
+        let Ok(outcome) = next.play_move(next_move) else {
+            continue;
+        };
+
+        let score = score_move(search, position, &next, outcome, depth - 1, alpha, beta);
+
+        best = best.max(score);
+        alpha = alpha.max(score);
+
+        // The opponent would never let us get here
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    best
+}
+
+// A rough guess at how good a position is, for when we cant search any deeper
+pub fn evaluate(position: &Position) -> i32 {
+    let me = position.to_move();
+    let config = position.config();
+    let (rows, columns, connect) = (config.rows, config.columns, config.connect);
+    let mut score = position.variant().score_bonus(position, me);
+
+    // Chips in the center column are worth a little on their own
+    let center = columns / 2;
+    for row in 0..rows {
+        match position.get(row, center) {
+            Some(side) if side == me => score += 3,
+            Some(_) => score -= 3,
+            None => {}
+        }
+    }
+
+    // Every window of `connect` cells that only one player has chips in could still become a line
+    let directions: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+    for row in 0..rows as isize {
+        for col in 0..columns as isize {
+            for (d_row, d_col) in directions {
+                let end_row = row + d_row * (connect as isize - 1);
+                let end_col = col + d_col * (connect as isize - 1);
+                if end_row < 0
+                    || end_row >= rows as isize
+                    || end_col < 0
+                    || end_col >= columns as isize
+                {
+                    continue;
+                }
+
+                let mut mine = 0;
+                let mut theirs = 0;
+                for i in 0..connect as isize {
+                    match position.get((row + d_row * i) as usize, (col + d_col * i) as usize) {
+                        Some(side) if side == me => mine += 1,
+                        Some(_) => theirs += 1,
+                        None => {}
+                    }
+                }
+
+                // One or two chips short of a line
+                score += match (mine, theirs) {
+                    (0, 0) => 0,
+                    (mine, 0) if mine + 1 == connect => 5,
+                    (mine, 0) if mine + 2 == connect => 2,
+                    (0, theirs) if theirs + 1 == connect => -5,
+                    (0, theirs) if theirs + 2 == connect => -2,
+                    _ => 0,
+                };
+            }
+        }
+    }
+
+    score
+}
@@ -0,0 +1,119 @@
+use crate::ai::best_move_with_book;
+use crate::animation::{is_animating, FallingChip};
+use crate::board::{AppState, Board, PlayMoveEvent};
+use crate::book::{OpeningBook, DEFAULT_BOOK_PATH};
+use crate::players::{Player, PlayerKind, PlayerNum};
+use crate::rules::{BoardConfig, Position};
+use crate::solver::Solver;
+
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use std::sync::{Arc, Mutex};
+
+// How long the AI waits before making a move, so you can actually see what happened
+const AI_MOVE_DELAY: f32 = 0.5;
+
+#[derive(Resource, Deref, DerefMut)]
+struct AiMoveTimer(Timer);
+
+// The opening book, if there was one to load
+#[derive(Resource, Default, Deref)]
+pub struct LoadedBook(pub Option<Arc<OpeningBook>>);
+
+// The solver that hints and reviews think with, shared since its table is far too big to have two of
+// It gets made the first time its needed, and if both want it at once the second waits on its own thread
+#[derive(Resource, Clone, Default)]
+pub struct SharedSolver(Arc<Mutex<Option<Solver>>>);
+
+impl SharedSolver {
+    // Thinks about `position` on another thread, with the book if its a standard board (the only kind in it)
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        position: &Position,
+        book: &LoadedBook,
+        think: impl FnOnce(&mut Solver, Option<&OpeningBook>) -> T + Send + 'static,
+    ) -> Task<T> {
+        let shared = self.0.clone();
+        let book = if *position.config() == BoardConfig::STANDARD {
+            book.0.clone()
+        } else {
+            None
+        };
+
+        AsyncComputeTaskPool::get().spawn(async move {
+            let Ok(mut solver) = shared.lock() else {
+                panic!("Tried to use the solver after it panicked while thinking")
+            };
+            let solver = solver.get_or_insert_with(Solver::new);
+            solver.set_book(book.clone());
+            think(solver, book.as_deref())
+        })
+    }
+}
+
+pub fn plugin_ai_player(app: &mut App) {
+    app.insert_resource(AiMoveTimer(Timer::from_seconds(
+        AI_MOVE_DELAY,
+        TimerMode::Once,
+    )));
+
+    app.init_resource::<LoadedBook>();
+    app.init_resource::<SharedSolver>();
+
+    app.add_systems(Startup, load_opening_book);
+    // Nothing happens while paused, or in the menus
+    app.add_systems(Update, update_ai_move.run_if(in_state(AppState::InGame)));
+}
+
+fn load_opening_book(mut book: ResMut<LoadedBook>) {
+    match OpeningBook::load_if_present(DEFAULT_BOOK_PATH) {
+        Ok(Some(loaded)) => {
+            info!("Loaded {} positions from {DEFAULT_BOOK_PATH}", loaded.len());
+            book.0 = Some(Arc::new(loaded));
+        }
+        // Not having a book is fine, the AI just has to think for itself
+        Ok(None) => info!("No opening book found at {DEFAULT_BOOK_PATH}"),
+        // But a broken one should be looked at
+        Err(error) => warn!("Not using the opening book at {DEFAULT_BOOK_PATH}: {error}"),
+    }
+}
+
+fn update_ai_move(
+    time: Res<Time>,
+    board: Res<Board>,
+    players: Query<&Player>,
+    book: Res<LoadedBook>,
+    falling: Query<(), With<FallingChip>>,
+    mut timer: ResMut<AiMoveTimer>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
+) {
+    if board.is_over() {
+        return;
+    }
+
+    // Start waiting again after every move, and only once the last chip has landed
+    if board.is_changed() || is_animating(&falling) {
+        timer.reset();
+    }
+
+    if !timer.tick(time.delta()).finished() {
+        return;
+    }
+
+    // Go off the board rather than the current player, since the board is never out of date
+    let num: PlayerNum = board.to_move().into();
+    let Some(player) = players.iter().find(|p| p.num == num) else {
+        return;
+    };
+
+    let PlayerKind::Ai { depth } = player.kind else {
+        return;
+    };
+
+    if let Some(played) = best_move_with_book(&board, depth, book.0.as_deref()) {
+        play_move_event.send(PlayMoveEvent { played });
+
+        // Dont send the same move again while waiting for it to be played
+        timer.reset();
+    }
+}
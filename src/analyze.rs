@@ -0,0 +1,272 @@
+// `analyze <moves>`: what the solver thinks of a position, printed out without ever opening a window
+// Only for standard rules, since thats all the solver knows
+
+use crate::book::{OpeningBook, DEFAULT_BOOK_PATH};
+use crate::notation::Game;
+use crate::rules::*;
+use crate::solver::{Evaluation, Solver};
+use crate::tui::board_text;
+
+use serde::Serialize;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format '{s}', expected text or json")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+// Playing in one column, from the point of view of whoever is to move
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+struct ColumnScore {
+    // Counting from 1, like the notation
+    column: usize,
+    // The solver score, None if the column cant be played in
+    score: Option<i32>,
+    outcome: Option<Outcome>,
+    // How many more moves until the game ends, including this one
+    moves: Option<usize>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+struct Analysis {
+    moves: String,
+    // The same as the text board, top row first with the column numbers at the bottom
+    board: Vec<String>,
+    // 1 or 2, None once the game is over
+    to_move: Option<u8>,
+    game_over: bool,
+    columns: Vec<ColumnScore>,
+    best_move: Option<usize>,
+    // Who wins with perfect play from here (or who already won), None for a draw
+    winner: Option<u8>,
+    // How many more moves that takes
+    moves_left: usize,
+}
+
+fn player_number(side: Side) -> u8 {
+    match side {
+        Side::First => 1,
+        Side::Second => 2,
+    }
+}
+
+fn from_evaluation(evaluation: Evaluation) -> (Outcome, usize) {
+    match evaluation {
+        Evaluation::Win { moves } => (Outcome::Win, moves),
+        Evaluation::Loss { moves } => (Outcome::Loss, moves),
+        Evaluation::Draw { moves } => (Outcome::Draw, moves),
+    }
+}
+
+pub fn run_analyze(args: &[String]) -> Result<(), String> {
+    let mut moves = None;
+    let mut format = Format::Text;
+
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--format" | "--rules" | "--rows" | "--columns" | "--connect" => {
+                let Some(value) = options.next() else {
+                    return Err(format!("{arg} needs a value after it"));
+                };
+
+                match arg.as_str() {
+                    "--format" => format = value.parse()?,
+                    "--rules" if value.parse::<Rules>()? != Rules::Standard => {
+                        return Err("the solver only knows the standard rules".into())
+                    }
+                    // The board size is read by `BoardConfig::from_args` below
+                    _ => {}
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{arg}'")),
+            _ if moves.is_some() => {
+                return Err(format!(
+                    "only one move sequence can be analysed at a time, but got '{arg}' as well"
+                ))
+            }
+            _ => moves = Some(arg.clone()),
+        }
+    }
+
+    let Some(moves) = moves else {
+        return Err("analyze needs a move sequence, eg analyze 4453".into());
+    };
+
+    let config = BoardConfig::from_args(args, BoardConfig::STANDARD)?;
+    if !Solver::supports(&config) {
+        return Err(format!("a {config} board is too big for the solver"));
+    }
+
+    let game = Game::from_notation_with_config(config, &moves)
+        .map_err(|error| format!("invalid move sequence '{moves}': {error}"))?;
+
+    let mut solver = Solver::new();
+    // The book is only for standard boards, and saves a lot of time on early positions
+    if config == BoardConfig::STANDARD {
+        match OpeningBook::load_if_present(DEFAULT_BOOK_PATH) {
+            Ok(book) => solver.set_book(book.map(Arc::new)),
+            Err(error) => {
+                eprintln!("warning: not using the opening book at {DEFAULT_BOOK_PATH}: {error}")
+            }
+        }
+    }
+
+    let analysis = analyze(&mut solver, &game, moves.trim());
+    match format {
+        Format::Text => print!("{}", analysis_text(&analysis)),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&analysis).expect("Failed to turn an analysis into json")
+        ),
+    }
+
+    Ok(())
+}
+
+fn analyze(solver: &mut Solver, game: &Game, moves: &str) -> Analysis {
+    let position = game.position();
+    let config = position.config();
+    let moves_played = position.moves_played();
+
+    let board = board_text(position, false)
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+
+    if position.is_over() {
+        return Analysis {
+            moves: moves.to_string(),
+            board,
+            to_move: None,
+            game_over: true,
+            columns: Vec::new(),
+            best_move: None,
+            winner: position.winner().map(player_number),
+            moves_left: 0,
+        };
+    }
+
+    let columns: Vec<ColumnScore> = solver
+        .analyze(position)
+        .into_iter()
+        .enumerate()
+        .map(|(col, score)| {
+            let evaluation = score
+                .map(|score| from_evaluation(Evaluation::from_score(score, config, moves_played)));
+            ColumnScore {
+                column: col + 1,
+                score,
+                outcome: evaluation.map(|(outcome, _)| outcome),
+                moves: evaluation.map(|(_, moves)| moves),
+            }
+        })
+        .collect();
+
+    // The best column says how the whole game goes
+    let best_move = solver.best_move(position);
+    let best = best_move.and_then(|col| columns[col].score);
+    let (winner, moves_left) =
+        match best.map(|score| Evaluation::from_score(score, config, moves_played)) {
+            Some(Evaluation::Win { moves }) => (Some(player_number(position.to_move())), moves),
+            Some(Evaluation::Loss { moves }) => {
+                (Some(player_number(position.to_move().other())), moves)
+            }
+            Some(Evaluation::Draw { moves }) => (None, moves),
+            None => panic!("Tried to analyze a position with nothing to play"),
+        };
+
+    Analysis {
+        moves: moves.to_string(),
+        board,
+        to_move: Some(player_number(position.to_move())),
+        game_over: false,
+        columns,
+        best_move: best_move.map(|col| col + 1),
+        winner,
+        moves_left,
+    }
+}
+
+fn analysis_text(analysis: &Analysis) -> String {
+    let mut text = String::new();
+
+    for line in &analysis.board {
+        writeln!(text, "{line}").unwrap();
+    }
+    writeln!(text).unwrap();
+
+    let Some(to_move) = analysis.to_move else {
+        match analysis.winner {
+            Some(winner) => {
+                writeln!(text, "The game is already over, player {winner} won").unwrap()
+            }
+            None => writeln!(text, "The game is already over, it was a draw").unwrap(),
+        }
+        return text;
+    };
+
+    writeln!(text, "Player {to_move} to move").unwrap();
+    writeln!(text).unwrap();
+
+    writeln!(text, "Column  Score  With perfect play").unwrap();
+    for column in &analysis.columns {
+        let (score, result) = match (column.score, column.outcome, column.moves) {
+            (Some(score), Some(outcome), Some(moves)) => {
+                let outcome = match outcome {
+                    Outcome::Win => "win",
+                    Outcome::Loss => "loss",
+                    Outcome::Draw => "draw",
+                };
+                (score.to_string(), format!("{outcome} in {moves}"))
+            }
+            _ => ("-".to_string(), "full".to_string()),
+        };
+        writeln!(text, "{:>6}  {score:>5}  {result}", column.column).unwrap();
+    }
+    writeln!(text).unwrap();
+
+    if let Some(best_move) = analysis.best_move {
+        writeln!(text, "Best move: {best_move}").unwrap();
+    }
+
+    match analysis.winner {
+        Some(winner) => writeln!(
+            text,
+            "With perfect play player {winner} wins, in {} more moves",
+            analysis.moves_left
+        )
+        .unwrap(),
+        None => writeln!(
+            text,
+            "With perfect play its a draw, after {} more moves",
+            analysis.moves_left
+        )
+        .unwrap(),
+    }
+
+    text
+}
@@ -0,0 +1,188 @@
+use crate::board::{
+    board_size, hole_position, player_colour, Board, ChipPlacedEvent, ChipPoppedEvent,
+    CIRCLE_RADIUS,
+};
+use crate::players::PlayerNum;
+use crate::rules::BoardConfig;
+
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+use bevy_mod_picking::prelude::*;
+
+// How long a chip takes to fall one row, at normal speed
+const SECONDS_PER_ROW: f32 = 0.06;
+// Even a chip that lands on the top row gets some time to fall into it
+const MIN_FALL_SECONDS: f32 = 0.15;
+// The bounce at the end, after the chip has hit the bottom
+const BOUNCE_SECONDS: f32 = 0.12;
+const BOUNCE_HEIGHT: f32 = CIRCLE_RADIUS * 0.3;
+
+// From `--animation-speed <multiplier>` and `--no-animations`
+#[derive(Resource, Clone, Debug)]
+pub struct AnimationSettings {
+    pub enabled: bool,
+    // 2.0 is twice as fast, 0.5 is half as fast
+    pub speed: f32,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        AnimationSettings {
+            enabled: true,
+            speed: 1.0,
+        }
+    }
+}
+
+impl AnimationSettings {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut settings = AnimationSettings::default();
+
+        if args.iter().any(|arg| arg == "--no-animations") {
+            settings.enabled = false;
+        }
+
+        if let Some(index) = args.iter().position(|arg| arg == "--animation-speed") {
+            let Some(speed) = args.get(index + 1) else {
+                return Err("--animation-speed needs a number after it, eg 2".into());
+            };
+
+            settings.speed = match speed.parse::<f32>() {
+                Ok(speed) if speed > 0.0 && speed.is_finite() => speed,
+                _ => {
+                    return Err(format!(
+                        "invalid animation speed '{speed}', it has to be above 0"
+                    ))
+                }
+            };
+        }
+
+        Ok(settings)
+    }
+}
+
+// A chip on its way down, the hole it is going into stays empty until it lands
+#[derive(Component)]
+pub struct FallingChip {
+    pub row: usize,
+    pub col: usize,
+    start_y: f32,
+    end_y: f32,
+    elapsed: f32,
+    fall_seconds: f32,
+    bounce_seconds: f32,
+}
+
+impl FallingChip {
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.fall_seconds + self.bounce_seconds
+    }
+
+    fn y(&self) -> f32 {
+        if self.elapsed < self.fall_seconds {
+            // Speeding up as it falls, like gravity
+            let t = self.elapsed / self.fall_seconds;
+            return self.start_y + (self.end_y - self.start_y) * t * t;
+        }
+
+        // Then one small hop once it hits the bottom
+        let t = ((self.elapsed - self.fall_seconds) / self.bounce_seconds).min(1.0);
+        self.end_y + BOUNCE_HEIGHT * (t * std::f32::consts::PI).sin()
+    }
+}
+
+// Anything that would change the board should wait while this is true
+pub fn is_animating(falling: &Query<(), With<FallingChip>>) -> bool {
+    !falling.is_empty()
+}
+
+pub fn plugin_animation(app: &mut App) {
+    app.init_resource::<AnimationSettings>();
+
+    app.add_systems(Update, spawn_falling_chips);
+    app.add_systems(Update, update_falling_chips);
+}
+
+pub fn spawn_falling_chips(
+    mut command: Commands,
+    mut placed_events: EventReader<ChipPlacedEvent>,
+    mut popped_events: EventReader<ChipPoppedEvent>,
+    settings: Res<AnimationSettings>,
+    config: Res<BoardConfig>,
+    board: Res<Board>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // Read them either way, so they dont pile up while animations are off
+    let placed: Vec<_> = placed_events.read().collect();
+    let popped: Vec<_> = popped_events.read().collect();
+
+    if !settings.enabled {
+        return;
+    }
+
+    let mut spawn = |row: usize, col: usize, start_y: f32, player: &PlayerNum| {
+        let end = hole_position(row, col);
+
+        // Further to fall means longer falling
+        let rows_fallen = (start_y - end.y) / (hole_position(1, col).y - end.y);
+        let fall_seconds = (SECONDS_PER_ROW * rows_fallen).max(MIN_FALL_SECONDS) / settings.speed;
+
+        command.spawn((
+            MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(meshes.add(Circle::new(CIRCLE_RADIUS))),
+                material: materials.add(player_colour(player)),
+                // In front of the holes
+                transform: Transform::from_xyz(end.x, start_y, 2.),
+                ..default()
+            },
+            FallingChip {
+                row,
+                col,
+                start_y,
+                end_y: end.y,
+                elapsed: 0.,
+                fall_seconds,
+                bounce_seconds: BOUNCE_SECONDS / settings.speed,
+            },
+            Pickable::IGNORE,
+        ));
+    };
+
+    // New chips come in from above the board
+    for event in placed {
+        let start_y = board_size(&config).y + CIRCLE_RADIUS;
+        spawn(event.row, event.col, start_y, &event.player);
+    }
+
+    // After a pop, everything left in the column drops down a row
+    for event in popped {
+        for row in 0..config.rows {
+            let Some(side) = board.get(row, event.col) else {
+                break;
+            };
+            let start_y = hole_position(row + 1, event.col).y;
+            spawn(row, event.col, start_y, &side.into());
+        }
+    }
+}
+
+fn update_falling_chips(
+    mut command: Commands,
+    time: Res<Time>,
+    mut chips: Query<(Entity, &mut FallingChip, &mut Transform)>,
+) {
+    for (entity, mut chip, mut transform) in &mut chips {
+        chip.elapsed += time.delta_seconds();
+
+        // The hole gets coloured in by the board once this is gone
+        if chip.is_finished() {
+            command.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation.y = chip.y();
+    }
+}
@@ -0,0 +1,113 @@
+// `connect4-engine`: the built in AI on its own, talking the engine protocol over stdin and stdout (see `engine`)
+// So tournaments (and anything else that plays engines) dont have to start the whole game to play it
+
+// These are shared with the game, which uses more of them than the engine does
+#[allow(dead_code)]
+#[path = "../../ai.rs"]
+mod ai;
+#[allow(dead_code)]
+#[path = "../../book.rs"]
+mod book;
+#[allow(dead_code)]
+#[path = "../../engine.rs"]
+mod engine;
+#[allow(dead_code)]
+#[path = "../../notation.rs"]
+mod notation;
+#[allow(dead_code)]
+#[path = "../../rules.rs"]
+mod rules;
+#[allow(dead_code)]
+#[path = "../../solver.rs"]
+mod solver;
+#[allow(dead_code)]
+#[path = "../../variant.rs"]
+mod variant;
+
+use ai::best_move_in_time;
+use book::{OpeningBook, DEFAULT_BOOK_PATH};
+use engine::Command;
+use notation::{move_notation, Game};
+use rules::Move;
+
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if let Err(error) = run_engine(&args) {
+        eprintln!("error: {error}");
+        std::process::exit(1);
+    }
+}
+
+// The built in AI, searching one move deeper at a time until it runs out of time
+// `--depth <n>` stops it going any deeper than that, however much time it has
+fn run_engine(args: &[String]) -> Result<(), String> {
+    let mut max_depth = None;
+
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--depth" => {
+                let Some(value) = options.next() else {
+                    return Err("--depth needs a number after it".into());
+                };
+                max_depth = Some(
+                    value
+                        .parse::<u32>()
+                        .map_err(|error| format!("invalid depth '{value}': {error}"))?,
+                );
+            }
+            _ => return Err(format!("unknown option '{arg}'")),
+        }
+    }
+
+    // Anything printed that isnt part of the protocol has to be info, so warnings go to stderr
+    let book = OpeningBook::load_if_present(DEFAULT_BOOK_PATH).unwrap_or_else(|error| {
+        eprintln!("warning: not using the opening book at {DEFAULT_BOOK_PATH}: {error}");
+        None
+    });
+
+    let mut game = Game::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|error| format!("could not read stdin: {error}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match line.parse::<Command>() {
+            Ok(Command::IsReady) => println!("readyok"),
+            Ok(Command::Position {
+                rules,
+                config,
+                moves,
+            }) => match Game::from_notation_with_rules(config, rules, &moves) {
+                Ok(position) => game = position,
+                Err(error) => println!("info string invalid position: {error}"),
+            },
+            Ok(Command::Go { movetime }) => {
+                match think(&game, movetime, max_depth, book.as_ref()) {
+                    Some(next) => println!("bestmove {}", move_notation(next)),
+                    None => println!("info string there are no moves to play"),
+                }
+            }
+            Ok(Command::Quit) => return Ok(()),
+            Err(error) => println!("info string {error}"),
+        }
+    }
+
+    Ok(())
+}
+
+fn think(
+    game: &Game,
+    movetime: Duration,
+    max_depth: Option<u32>,
+    book: Option<&OpeningBook>,
+) -> Option<Move> {
+    // Looking further ahead than the board has room for doesnt find anything new
+    let max_depth = max_depth.unwrap_or(game.config().cells() as u32);
+    best_move_in_time(game.position(), max_depth, Instant::now() + movetime, book)
+}
@@ -0,0 +1,185 @@
+// One game on the server, with whoever is playing and watching it
+
+use crate::clock::Clocks;
+use crate::net::{GameSnapshot, Message};
+use crate::notation::{ForfeitReason, Game};
+use crate::rules::Move;
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub type ClientId = u64;
+
+// No I or O, so a code read out loud cant be mistaken for a number
+const CODE_LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+const CODE_LENGTH: usize = 4;
+
+pub struct Lobby {
+    pub code: String,
+    pub game: Game,
+    // Player 1 first, along with the name they said hello with
+    seats: [Option<(ClientId, String)>; 2],
+    spectators: Vec<ClientId>,
+    // Only for games with a time control
+    clocks: Option<Clocks>,
+    // How long the player to move has been thinking, which only counts while both players are here
+    used: Duration,
+    last_ticked: Instant,
+}
+
+impl Lobby {
+    pub fn new(code: String, game: Game, clocks: Option<Clocks>) -> Self {
+        Lobby {
+            code,
+            game,
+            seats: [None, None],
+            spectators: Vec::new(),
+            clocks,
+            used: Duration::ZERO,
+            last_ticked: Instant::now(),
+        }
+    }
+
+    // Runs the clock of whoever is to move, and gives the game away if they have run out
+    // Gives back whether they just did
+    pub fn tick(&mut self) -> bool {
+        let elapsed = self.last_ticked.elapsed();
+        self.last_ticked = Instant::now();
+
+        let seated = self.seats.iter().all(Option::is_some);
+        let Some(clocks) = &mut self.clocks else {
+            return false;
+        };
+        if !seated || self.game.is_over() {
+            return false;
+        }
+
+        self.used += elapsed;
+        let to_move = self.game.to_move();
+        if !clocks.runs_out(to_move, self.used) {
+            return false;
+        }
+
+        clocks.flag(to_move);
+        self.game.forfeit(to_move, ForfeitReason::Time);
+        true
+    }
+
+    // Stops the clock of whoever made the move, which has to have been checked already
+    // (and the clocks `tick`ed, in case they ran out first)
+    pub fn play(&mut self, played: Move) {
+        let mover = self.game.to_move();
+
+        self.game
+            .play_move(played)
+            .expect("Tried to play a move that was already checked");
+
+        if let Some(clocks) = &mut self.clocks {
+            clocks.finish_move(mover, self.used);
+        }
+        self.used = Duration::ZERO;
+    }
+
+    // Everyone starts again with a full clock
+    pub fn new_game(&mut self, game: Game) {
+        self.game = game;
+        self.clocks = self
+            .clocks
+            .as_ref()
+            .map(|clocks| Clocks::new(clocks.control()));
+        self.used = Duration::ZERO;
+        self.last_ticked = Instant::now();
+    }
+
+    // Sits them in the first empty seat, or with the spectators if there isnt one (or they only want to watch)
+    // Gives back the seat, counting from 0
+    pub fn join(&mut self, id: ClientId, name: &str, watch: bool) -> Option<usize> {
+        if !watch {
+            if let Some(seat) = self.seats.iter().position(Option::is_none) {
+                self.seats[seat] = Some((id, name.to_string()));
+                return Some(seat);
+            }
+        }
+
+        self.spectators.push(id);
+        None
+    }
+
+    // Their seat stays empty for anyone to take, which is how a player that lost their connection gets back in
+    pub fn leave(&mut self, id: ClientId) {
+        for seat in &mut self.seats {
+            if matches!(seat, Some((seated, _)) if *seated == id) {
+                *seat = None;
+            }
+        }
+        self.spectators.retain(|&spectator| spectator != id);
+    }
+
+    pub fn seat_of(&self, id: ClientId) -> Option<usize> {
+        self.seats
+            .iter()
+            .position(|seat| matches!(seat, Some((seated, _)) if *seated == id))
+    }
+
+    // Everyone who needs to hear about what happens, players first
+    pub fn members(&self) -> Vec<ClientId> {
+        self.seats
+            .iter()
+            .flatten()
+            .map(|(id, _)| *id)
+            .chain(self.spectators.iter().copied())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seats.iter().all(Option::is_none) && self.spectators.is_empty()
+    }
+
+    // Who is in the lobby, from the point of view of `id`
+    pub fn message_for(&self, id: ClientId) -> Message {
+        Message::Lobby {
+            code: self.code.clone(),
+            seat: self.seat_of(id).map(|seat| seat as u8 + 1),
+            players: self.seats.clone().map(|seat| seat.map(|(_, name)| name)),
+            spectators: self.spectators.len(),
+        }
+    }
+
+    pub fn game_message(&self) -> Message {
+        // Games on the server are only ever made from moves, so they always know them
+        let Some(game) = GameSnapshot::from_game(&self.game) else {
+            panic!("Tried to send a lobby game without its moves")
+        };
+        Message::Game { game }
+    }
+
+    pub fn clock_message(&self) -> Option<Message> {
+        self.clocks
+            .as_ref()
+            .map(|clocks| Message::clock(clocks, self.used))
+    }
+}
+
+// Random lobby codes, eg "KQWT"
+pub struct Codes(u64);
+
+impl Codes {
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default();
+        // xorshift needs a seed that isnt 0
+        Codes(seed | 1)
+    }
+
+    pub fn new_code(&mut self) -> String {
+        (0..CODE_LENGTH)
+            .map(|_| {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                CODE_LETTERS[(self.0 % CODE_LETTERS.len() as u64) as usize] as char
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,601 @@
+// `connect4-server`: lots of network games at once, with players finding each other by lobby code
+// It plays the host in every game, checking each move with the same rules as the game itself (see `net`)
+//
+// Every connection gets a thread reading from it, and everything they read comes through one channel to
+// the main thread, which owns all the lobbies. So nothing needs locking, and a game is just a `Game`
+
+// These are shared with the game, which uses more of them than the server does
+#[allow(dead_code)]
+#[path = "../../clock.rs"]
+mod clock;
+#[allow(dead_code)]
+#[path = "../../net.rs"]
+mod net;
+#[allow(dead_code)]
+#[path = "../../notation.rs"]
+mod notation;
+#[allow(dead_code)]
+#[path = "../../rules.rs"]
+mod rules;
+#[allow(dead_code)]
+#[path = "../../save.rs"]
+mod save;
+#[allow(dead_code)]
+#[path = "../../variant.rs"]
+mod variant;
+
+mod lobby;
+
+use clock::{Clocks, TimeControl};
+use lobby::*;
+use net::*;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Messages are written on a thread for each client, so someone that stops reading only holds up themselves
+// Once this many are waiting to go, they are dropped
+const MAX_QUEUED_MESSAGES: usize = 64;
+// And their thread gives up on a write that takes longer than this
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+// How often to check for anyone that has gone quiet
+const SWEEP_INTERVAL: Duration = Duration::from_millis(250);
+
+enum Event {
+    Connected(TcpStream),
+    Message(ClientId, Result<Message, NetError>),
+}
+
+struct Client {
+    outgoing: SyncSender<Message>,
+    // Only once they have said hello
+    name: Option<String>,
+    lobby: Option<String>,
+    chat_limit: ChatLimit,
+    last_heard: Instant,
+    last_sent: Instant,
+}
+
+struct Server {
+    clients: HashMap<ClientId, Client>,
+    lobbies: HashMap<String, Lobby>,
+    next_id: ClientId,
+    codes: Codes,
+    // For the reading threads to send on
+    events: Sender<Event>,
+    // Anyone that couldnt be sent to, to be dropped once the message that was being handled is done with
+    failed: Vec<(ClientId, String)>,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let port = match port_from_args(&args) {
+        Ok(port) => port,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("error: couldnt listen on port {port}: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    // Port 0 picks any free port, so say which one it was (the tests rely on this being the first line)
+    let port = listener
+        .local_addr()
+        .map(|address| address.port())
+        .unwrap_or(port);
+    println!("Listening on port {port}");
+    std::io::stdout().flush().ok();
+
+    run(listener);
+}
+
+// Reads `--port <port>`
+fn port_from_args(args: &[String]) -> Result<u16, String> {
+    let mut port = DEFAULT_PORT;
+
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--port" => {
+                let Some(value) = options.next() else {
+                    return Err("--port needs a port number after it".into());
+                };
+                port = value
+                    .parse()
+                    .map_err(|error| format!("invalid port '{value}': {error}"))?;
+            }
+            _ => return Err(format!("unknown option '{arg}'")),
+        }
+    }
+
+    Ok(port)
+}
+
+fn run(listener: TcpListener) {
+    let (sender, events) = mpsc::channel();
+
+    let connected = sender.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if connected.send(Event::Connected(stream)).is_err() {
+                        return;
+                    }
+                }
+                Err(error) => eprintln!("warning: failed to accept a connection: {error}"),
+            }
+        }
+    });
+
+    let mut server = Server {
+        clients: HashMap::new(),
+        lobbies: HashMap::new(),
+        next_id: 0,
+        codes: Codes::from_time(),
+        events: sender,
+        failed: Vec::new(),
+    };
+
+    let mut last_sweep = Instant::now();
+    loop {
+        match events.recv_timeout(SWEEP_INTERVAL) {
+            Ok(Event::Connected(stream)) => server.connect(stream),
+            Ok(Event::Message(id, Ok(message))) => server.receive(id, message),
+            Ok(Event::Message(id, Err(error))) => server.disconnect(id, &error.to_string()),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                panic!("Tried to wait for events, but the server stopped listening for them")
+            }
+        }
+
+        if last_sweep.elapsed() >= SWEEP_INTERVAL {
+            server.sweep();
+            last_sweep = Instant::now();
+        }
+
+        for (id, reason) in std::mem::take(&mut server.failed) {
+            server.disconnect(id, &reason);
+        }
+    }
+}
+
+// Sends everything queued up for one client, until they are dropped (which closes the queue) or a write fails
+// Then it hangs up, so their reading thread finishes too, even if they have gone quiet rather than hanging up
+fn write_messages(mut stream: TcpStream, queued: Receiver<Message>) {
+    thread::spawn(move || {
+        for message in queued {
+            if write_message(&mut stream, &message).is_err() {
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+}
+
+impl Server {
+    fn connect(&mut self, stream: TcpStream) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let setup = || -> std::io::Result<TcpStream> {
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            stream.try_clone()
+        };
+        let reader = match setup() {
+            Ok(reader) => reader,
+            Err(error) => {
+                eprintln!("warning: failed to set up a connection: {error}");
+                return;
+            }
+        };
+
+        if let Ok(peer) = stream.peer_addr() {
+            println!("Client {id} connected from {peer}");
+        }
+
+        let events = self.events.clone();
+        read_messages(reader, move |message| {
+            events.send(Event::Message(id, message)).is_ok()
+        });
+        let (outgoing, queued) = mpsc::sync_channel(MAX_QUEUED_MESSAGES);
+        write_messages(stream, queued);
+
+        self.clients.insert(
+            id,
+            Client {
+                outgoing,
+                name: None,
+                lobby: None,
+                chat_limit: ChatLimit::default(),
+                last_heard: Instant::now(),
+                last_sent: Instant::now(),
+            },
+        );
+    }
+
+    // Takes them out of their lobby too, which goes once the last person has left it
+    fn disconnect(&mut self, id: ClientId, reason: &str) {
+        let Some(client) = self.clients.remove(&id) else {
+            return;
+        };
+        println!("Client {id} left: {reason}");
+        // Their writing thread hangs up once it has sent whatever was left, like an error saying why
+        drop(client.outgoing);
+
+        let Some(code) = client.lobby else {
+            return;
+        };
+        let Some(lobby) = self.lobbies.get_mut(&code) else {
+            return;
+        };
+
+        lobby.leave(id);
+        if lobby.is_empty() {
+            self.lobbies.remove(&code);
+            println!("Lobby {code} closed, {} still open", self.lobbies.len());
+        } else {
+            self.send_lobby(&code);
+        }
+    }
+
+    fn send(&mut self, id: ClientId, message: &Message) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+
+        match client.outgoing.try_send(message.clone()) {
+            Ok(()) => client.last_sent = Instant::now(),
+            Err(TrySendError::Full(_)) => self
+                .failed
+                .push((id, "couldnt keep up with its messages".into())),
+            // Their thread has already given up on them
+            Err(TrySendError::Disconnected(_)) => {
+                self.failed.push((id, "couldnt be sent anything".into()))
+            }
+        }
+    }
+
+    // Tells everyone in the lobby who is in it
+    fn send_lobby(&mut self, code: &str) {
+        let Some(lobby) = self.lobbies.get(code) else {
+            return;
+        };
+
+        let messages: Vec<_> = lobby
+            .members()
+            .into_iter()
+            .map(|member| (member, lobby.message_for(member)))
+            .collect();
+        for (member, message) in messages {
+            self.send(member, &message);
+        }
+    }
+
+    // Pings anyone that hasnt heard from us in a while, and drops anyone we havent heard from
+    fn sweep(&mut self) {
+        let mut quiet = Vec::new();
+        let mut ping = Vec::new();
+
+        for (&id, client) in &self.clients {
+            if client.last_heard.elapsed() > TIMEOUT {
+                quiet.push(id);
+            } else if client.last_sent.elapsed() > PING_INTERVAL {
+                ping.push(id);
+            }
+        }
+
+        for id in ping {
+            self.send(id, &Message::Ping);
+        }
+        for id in quiet {
+            self.disconnect(id, &NetError::TimedOut.to_string());
+        }
+
+        let mut flagged = Vec::new();
+        for (code, lobby) in &mut self.lobbies {
+            if lobby.tick() {
+                flagged.push(code.clone());
+            }
+        }
+        for code in flagged {
+            self.ran_out_of_time(&code);
+        }
+    }
+
+    // Tells everyone in the lobby how much time everyone has, if the game has a clock
+    fn send_clock(&mut self, code: &str) {
+        let Some(lobby) = self.lobbies.get(code) else {
+            return;
+        };
+        let Some(message) = lobby.clock_message() else {
+            return;
+        };
+
+        for member in lobby.members() {
+            self.send(member, &message);
+        }
+    }
+
+    fn ran_out_of_time(&mut self, code: &str) {
+        if let Some(side) = self.lobbies[code].game.winner() {
+            println!(
+                "Lobby {code}: player {} ran out of time",
+                side.other().index() + 1
+            );
+        }
+        self.send_clock(code);
+    }
+
+    fn receive(&mut self, id: ClientId, message: Message) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        client.last_heard = Instant::now();
+
+        if let Err(error) = self.handle(id, message) {
+            self.send(
+                id,
+                &Message::Error {
+                    message: error.clone(),
+                },
+            );
+            self.disconnect(id, &error);
+        }
+    }
+
+    fn handle(&mut self, id: ClientId, message: Message) -> Result<(), String> {
+        let client = &self.clients[&id];
+        let name = client.name.clone();
+        let code = client.lobby.clone();
+
+        match (message, name, code) {
+            (Message::Ping, _, _) => {}
+
+            // Hanging up, with a reason
+            (Message::Error { message }, _, _) => self.failed.push((id, message)),
+
+            (Message::Hello { version, name }, None, _) => {
+                if version != PROTOCOL_VERSION {
+                    return Err(NetError::WrongVersion(version).to_string());
+                }
+
+                let Some(name) = clean_name(&name) else {
+                    return Err("a name cant be empty".into());
+                };
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.name = Some(name);
+                }
+                self.send(id, &Message::hello("connect4-server"));
+            }
+
+            (_, None, _) => return Err("the first message has to be a hello".into()),
+
+            (Message::Create { game, clock }, Some(name), None) => {
+                let game = game.to_game().map_err(|error| error.to_string())?;
+                let clocks = match clock {
+                    Some(clock) => Some(Clocks::new(clock.parse::<TimeControl>()?)),
+                    None => None,
+                };
+
+                let mut code = self.codes.new_code();
+                while self.lobbies.contains_key(&code) {
+                    code = self.codes.new_code();
+                }
+
+                self.lobbies
+                    .insert(code.clone(), Lobby::new(code.clone(), game, clocks));
+                println!(
+                    "Lobby {code} opened by client {id}, {} open",
+                    self.lobbies.len()
+                );
+                self.enter_lobby(id, &name, &code, false);
+            }
+
+            (Message::Join { code }, Some(name), None) => {
+                let code = self.find_lobby(&code)?;
+                self.enter_lobby(id, &name, &code, false);
+            }
+
+            (Message::Watch { code }, Some(name), None) => {
+                let code = self.find_lobby(&code)?;
+                self.enter_lobby(id, &name, &code, true);
+            }
+
+            (Message::Create { .. } | Message::Join { .. } | Message::Watch { .. }, _, Some(_)) => {
+                return Err("you can only be in one lobby at a time".into())
+            }
+
+            (Message::Move { index, notation }, _, Some(code)) => {
+                self.play_move(id, &code, index, &notation)?
+            }
+
+            // A new game, which is only allowed once the last one is over
+            (Message::Game { game }, _, Some(code)) => {
+                let lobby = &self.lobbies[&code];
+                if lobby.seat_of(id).is_none() {
+                    return Err("only players can start a new game".into());
+                }
+
+                if lobby.game.is_over() {
+                    let game = game.to_game().map_err(|error| error.to_string())?;
+                    if let Some(lobby) = self.lobbies.get_mut(&code) {
+                        lobby.new_game(game);
+                    }
+
+                    let lobby = &self.lobbies[&code];
+                    let message = lobby.game_message();
+                    for member in lobby.members() {
+                        self.send(member, &message);
+                    }
+                    self.send_clock(&code);
+                } else {
+                    let message = lobby.game_message();
+                    self.send(id, &message);
+                }
+            }
+
+            (Message::Sync, _, Some(code)) => {
+                let lobby = &self.lobbies[&code];
+                let messages = [Some(lobby.game_message()), lobby.clock_message()];
+                for message in messages.iter().flatten() {
+                    self.send(id, message);
+                }
+            }
+
+            (Message::Chat { text, .. }, Some(name), Some(code)) => {
+                self.chat(id, &name, &code, &text)
+            }
+
+            (Message::Hello { .. }, ..) => return Err("only one hello is needed".into()),
+            (Message::Lobby { .. }, ..) => {
+                return Err("only the server says who is in a lobby".into())
+            }
+            (Message::Clock { .. }, ..) => return Err("only the server keeps time".into()),
+            (
+                Message::Move { .. } | Message::Game { .. } | Message::Sync | Message::Chat { .. },
+                _,
+                None,
+            ) => return Err("join a lobby first".into()),
+        }
+
+        Ok(())
+    }
+
+    // Codes are matched ignoring case, since people type them in
+    fn find_lobby(&self, code: &str) -> Result<String, String> {
+        let code = code.trim().to_uppercase();
+        if self.lobbies.contains_key(&code) {
+            Ok(code)
+        } else {
+            Err(format!("there is no lobby with the code '{code}'"))
+        }
+    }
+
+    fn enter_lobby(&mut self, id: ClientId, name: &str, code: &str, watch: bool) {
+        let Some(lobby) = self.lobbies.get_mut(code) else {
+            return;
+        };
+
+        let seat = lobby.join(id, name, watch);
+        match seat {
+            Some(seat) => println!("Client {id} is player {} in lobby {code}", seat + 1),
+            None => println!("Client {id} is watching lobby {code}"),
+        }
+
+        let messages = [Some(lobby.game_message()), lobby.clock_message()];
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.lobby = Some(code.to_string());
+        }
+
+        // Everyone else gets to hear who joined, and the new one gets the game as it is now
+        self.send_lobby(code);
+        for message in messages.iter().flatten() {
+            self.send(id, message);
+        }
+    }
+
+    // Moves that cant be played just get the game sent back, so the player can try again from there
+    fn play_move(
+        &mut self,
+        id: ClientId,
+        code: &str,
+        index: usize,
+        notation: &str,
+    ) -> Result<(), String> {
+        let Some(lobby) = self.lobbies.get_mut(code) else {
+            return Err(format!("there is no lobby with the code '{code}'"));
+        };
+
+        let Some(seat) = lobby.seat_of(id) else {
+            return Err("only players can move".into());
+        };
+
+        // A move that comes in after the time has run out is too late
+        if lobby.tick() {
+            self.ran_out_of_time(code);
+        }
+        let Some(lobby) = self.lobbies.get_mut(code) else {
+            return Ok(());
+        };
+
+        let to_move = match lobby.game.to_move() {
+            rules::Side::First => 0,
+            rules::Side::Second => 1,
+        };
+        let played = match check_move(&lobby.game, index, notation) {
+            Ok(played) if seat == to_move => played,
+            _ => {
+                let message = lobby.game_message();
+                self.send(id, &message);
+                return Ok(());
+            }
+        };
+
+        lobby.play(played);
+
+        match lobby.game.winner() {
+            Some(rules::Side::First) => println!("Lobby {code}: player 1 won"),
+            Some(rules::Side::Second) => println!("Lobby {code}: player 2 won"),
+            None if lobby.game.is_over() => println!("Lobby {code}: a draw"),
+            None => {}
+        }
+
+        let others: Vec<_> = lobby
+            .members()
+            .into_iter()
+            .filter(|&member| member != id)
+            .collect();
+        let message = Message::play(index, played);
+        for member in others {
+            self.send(member, &message);
+        }
+        self.send_clock(code);
+
+        Ok(())
+    }
+
+    // Chat that is empty or over the limit is dropped without a word, since the game itself would never send it
+    fn chat(&mut self, id: ClientId, name: &str, code: &str, text: &str) {
+        let Some(text) = clean_chat(text) else {
+            return;
+        };
+
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        if !client.chat_limit.allow() {
+            println!("Client {id} is chatting too fast, dropped a message");
+            return;
+        }
+
+        let Some(lobby) = self.lobbies.get(code) else {
+            return;
+        };
+        let others: Vec<_> = lobby
+            .members()
+            .into_iter()
+            .filter(|&member| member != id)
+            .collect();
+        let message = Message::Chat {
+            from: name.to_string(),
+            text,
+        };
+        for member in others {
+            self.send(member, &message);
+        }
+    }
+}
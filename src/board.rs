@@ -1,20 +1,20 @@
+use crate::animation::*;
+use crate::history::*;
+use crate::notation::*;
 use crate::players::*;
+use crate::rules::*;
 use array2d::Array2D;
 use backends::raycast::{bevy_mod_raycast::prelude::RaycastVisibility, RaycastBackendSettings};
 use bevy::{
     color::palettes::css::{DARK_GREEN, DIM_GREY, LIGHT_GREEN},
     prelude::*,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
-    time::Stopwatch,
 };
 use bevy_mod_picking::prelude::*;
 
 use Annulus as Ring;
 
-const ROW_COUNT: f32 = 6.;
-const COL_COUNT: f32 = 7.;
-
-const CIRCLE_RADIUS: f32 = 24.;
+pub const CIRCLE_RADIUS: f32 = 24.;
 const CIRCLE_DIAMETER: f32 = CIRCLE_RADIUS * 2.;
 
 const GAP: f32 = 5.;
@@ -23,239 +23,350 @@ const RED: Color = Color::srgb(1., 0., 0.);
 const BLUE: Color = Color::srgb(0., 0., 1.);
 const YELLOW: Color = Color::srgb(1.5, 1.5, 0.);
 const BLACK: Color = Color::srgb(0., 0., 0.);
+const HINT_COLOUR: Color = Color::srgba(0.2, 1., 0.2, 0.4);
+
+// How much to darken the chips that arent part of the winning line
+const DIM_AMOUNT: f32 = 0.3;
 
 pub fn plugin_board(app: &mut App) {
 
-    app.register_type::<GameState>();
     app.register_type::<Chip>();
 
+    app.add_event::<PlayMoveEvent>();
+    app.add_event::<ChipPlacedEvent>();
+    app.add_event::<ChipPoppedEvent>();
+
     app.insert_resource(RaycastBackendSettings {
         require_markers: false,
         raycast_visibility: RaycastVisibility::Ignore,
     });
-    app.insert_resource(Board::default());
-    app.insert_resource(GameState::Playing);
-
-    app.add_systems(Startup, spawn_board_background);
-    app.add_systems(Startup, spawn_initial_chips);
-    app.add_systems(Startup, spawn_initial_col_hightlights);
+    app.init_resource::<BoardConfig>();
+    app.init_resource::<Rules>();
+    app.init_resource::<Board>();
+    app.init_resource::<ArmedSpecial>();
+    app.init_resource::<HintedColumn>();
+    app.init_resource::<ShownBoard>();
+
+    // Runs at the start, and again whenever the board changes size or rules (eg loading a save)
+    app.add_systems(
+        Update,
+        (
+            despawn_board,
+            spawn_board_background,
+            spawn_initial_chips,
+            spawn_initial_col_hightlights,
+            spawn_pop_pickers,
+        )
+            .chain()
+            .run_if(board_layout_changed)
+            .before(update_chip_colour),
+    );
 
     app.add_systems(Update, update_col_rect_visibility);
+    app.add_systems(Update, update_pop_picker_visibility);
+    app.add_systems(Update, update_arm_special);
+
+    // The falling chip has to exist before the hole it is going into gets coloured in
+    app.add_systems(Update, update_play_move.before(spawn_falling_chips));
+    app.add_systems(Update, update_chip_colour.after(spawn_falling_chips));
+
+    app.add_systems(Update, update_game_over);
+    app.add_systems(Update, update_win_rings);
+    app.add_systems(Update, update_pulse_win_rings);
+    // The board left over from the last game is still there in the menus, but it isnt being played
+    app.add_systems(
+        Update,
+        update_print_notation
+            .run_if(in_state(AppState::InGame).or_else(in_state(AppState::GameOver))),
+    );
+}
 
-    app.add_systems(Update, update_chip_colour);
+// The size of the board, and how many in a row wins
+impl Resource for BoardConfig {}
+
+impl BoardConfig {
+    // Reads `--rows`, `--columns` and `--connect`, anything missing is taken from `default`
+    pub fn from_args(args: &[String], default: BoardConfig) -> Result<BoardConfig, String> {
+        let read = |flag: &str, default: usize| -> Result<usize, String> {
+            let Some(index) = args.iter().position(|arg| arg == flag) else {
+                return Ok(default);
+            };
+
+            match args.get(index + 1) {
+                Some(value) => value
+                    .parse()
+                    .map_err(|error| format!("invalid {flag} '{value}': {error}")),
+                None => Err(format!("{flag} needs a number after it")),
+            }
+        };
 
-    app.add_systems(Update, update_update_game_state);
+        BoardConfig::new(
+            read("--rows", default.rows)?,
+            read("--columns", default.columns)?,
+            read("--connect", default.connect)?,
+        )
+        .map_err(|error| error.to_string())
+    }
 }
 
-#[derive(Deref, DerefMut, Resource)]
-pub struct Board(Array2D<BoardState>);
+// Which rules the game is played by
+impl Resource for Rules {}
 
-impl Default for Board {
-    fn default() -> Self {
-        Board(Array2D::filled_with(BoardState::Empty, 6, 7))
+impl Rules {
+    // Reads `--rules <name>`, standard if its not there
+    pub fn from_args(args: &[String]) -> Result<Rules, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--rules") else {
+            return Ok(Rules::Standard);
+        };
+
+        match args.get(index + 1) {
+            Some(name) => name.parse(),
+            None => Err("--rules needs a name after it, eg popout".into()),
+        }
     }
 }
 
-#[derive(Clone, PartialEq)]
-pub enum BoardState {
-    Empty,
-    Taken(Player),
+fn board_layout_changed(config: Res<BoardConfig>, rules: Res<Rules>) -> bool {
+    config.is_changed() || rules.is_changed()
 }
 
-#[derive(Clone, PartialEq, Resource, Reflect)]
-#[reflect(Resource)]
-pub enum GameState {
-    Playing,
-    Won,
-    Draw,
-}
+// How big the blue background is
+pub fn board_size(config: &BoardConfig) -> Vec2 {
+    let rows = config.rows as f32;
+    let columns = config.columns as f32;
 
-#[derive(Component, Reflect)]
-#[reflect(Component)]
-pub struct Chip {
-    row: f32,
-    col: f32,
+    Vec2::new(
+        GAP + (columns * CIRCLE_DIAMETER) + (columns * GAP),
+        GAP + (rows * CIRCLE_DIAMETER) + (rows * GAP),
+    )
 }
 
-#[derive(Component)]
-struct ColPicker {
-    col: f32,
-}
+// The bevy side of the board, the actual rules live in `rules::Position`
+#[derive(Deref, DerefMut, Resource, Default, Clone)]
+pub struct Board(pub Game);
 
-pub fn is_at_bottom(board: Board, row: usize, column: usize) -> bool {
-    // Check if anything exists below, and if it does, make sure it is not empty
-    // If it is empty, then we are not at the bottom
+impl Board {
+    pub fn new(config: BoardConfig, rules: Rules) -> Self {
+        Board(Game::with_rules(config, rules))
+    }
 
-    // There are none below the 0th
-    if row == 0 {
-        return true;
+    // What a hole on the board should look like
+    pub fn state(&self, row: usize, col: usize) -> BoardState {
+        match self.get(row, col) {
+            Some(side) => BoardState::Taken(side.into()),
+            None => BoardState::Empty,
+        }
     }
 
-    match board.get(row - 1, column) {
-        Some(state) => {
-            match state {
-                BoardState::Taken(_) => true, // Cant go any lower
-                BoardState::Empty => false,   // Could have gone lower
+    pub fn to_array2d(&self) -> Array2D<BoardState> {
+        let (rows, columns) = (self.config().rows, self.config().columns);
+        let mut grid = Array2D::filled_with(BoardState::Empty, rows, columns);
+
+        for row in 0..rows {
+            for col in 0..columns {
+                grid[(row, col)] = self.state(row, col);
             }
         }
-        None => panic!("Tried to access non-existing chip"),
-    }
-}
 
-pub fn get_lowest_chip_row(board: Board, col: usize) -> Option<usize> {
-    let mut row_index = ROW_COUNT as usize - 1;
+        grid
+    }
 
-    // If the top is taken, return none, as the column is already full
-    if let Some(state) = board.get(row_index, col) {
-        match *state {
-            BoardState::Taken(_) => return None,
-            BoardState::Empty => {}
+    pub fn from_array2d(
+        config: BoardConfig,
+        grid: &Array2D<BoardState>,
+    ) -> Result<Board, InvalidPosition> {
+        // A grid of the wrong size is turned down by `from_cells`
+        let mut cells = vec![vec![None; grid.num_columns()]; grid.num_rows()];
+        for (row, cells_row) in cells.iter_mut().enumerate() {
+            for (col, cell) in cells_row.iter_mut().enumerate() {
+                if let BoardState::Taken(player) = &grid[(row, col)] {
+                    *cell = Some(player.clone().into());
+                }
+            }
         }
+
+        // There is no way of knowing what order the chips went in, so the board has no move history
+        Position::from_cells(config, &cells).map(|position| Board(Game::from_position(position)))
     }
 
-    while !is_at_bottom(Board(board.clone()), row_index, col) {
-        row_index -= 1
+    // Reads `--moves <notation>` from the command line, to start from somewhere other than an empty board
+    pub fn from_args(args: &[String], config: BoardConfig, rules: Rules) -> Result<Board, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--moves") else {
+            return Ok(Board::new(config, rules));
+        };
+
+        let Some(moves) = args.get(index + 1) else {
+            return Err("--moves needs a move sequence after it, eg 4453".into());
+        };
+
+        match Game::from_notation_with_rules(config, rules, moves) {
+            Ok(game) => Ok(Board(game)),
+            Err(error) => Err(format!("invalid move sequence '{moves}': {error}")),
+        }
     }
+}
 
-    Some(row_index)
+#[derive(Clone, PartialEq)]
+pub enum BoardState {
+    Empty,
+    Taken(PlayerNum),
 }
 
-fn is_full(board: Board) -> bool {
-    // The board is full when there are no more empty spaces
-    !board
-        .elements_row_major_iter()
-        .any(|f| f == &BoardState::Empty)
+// Where the whole app is at, from the main menu through to someone winning
+// Who won (and with which line) is always on the `Board`, this just says what to show
+#[derive(States, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Setup,
+    Settings,
+    InGame,
+    Paused,
+    GameOver,
+    // Watching a game play back, see `replay`
+    Replay,
 }
 
-fn check_horizontal_wins(board: Board) -> Option<Player> {
-    // Check for 4 in a row, on all rows
+impl AppState {
+    // Anything on the command line that sets up a game skips the menu, and goes straight into it
+    pub fn from_args(args: &[String]) -> AppState {
+        const GAME_FLAGS: [&str; 11] = [
+            "--player1",
+            "--player2",
+            "--moves",
+            "--load",
+            "--rules",
+            "--rows",
+            "--columns",
+            "--connect",
+            "--host",
+            "--join",
+            "--time-control",
+        ];
+
+        // The replay is loaded in straight away, rather than being played from
+        if args.iter().any(|arg| arg == "--replay") {
+            AppState::Replay
+        } else if args.iter().any(|arg| GAME_FLAGS.contains(&arg.as_str())) {
+            AppState::InGame
+        } else {
+            AppState::Menu
+        }
+    }
+}
 
-    // As close to the right as we can check for 4 in a row
-    // (eg at col 4, there are only 2 to the right of it, so its impossible to get 4 in a row starting from col 4)
-    let max_col_index = board.num_columns() - 3;
+// A ring around one of the winning chips
+#[derive(Component)]
+struct WinRing;
 
-    for row_index in 0..board.num_rows() {
-        for col_index in 0..max_col_index {
-            // Its fine to unwrap here, since if the item doesnt exist, something is wrong with max_col_index
-            // (We should never be trying to read a non existent item here)
-            let item1 = board.get(row_index, col_index).unwrap().clone();
-            let item2 = board.get(row_index, col_index + 1).unwrap().clone();
-            let item3 = board.get(row_index, col_index + 2).unwrap().clone();
-            let item4 = board.get(row_index, col_index + 3).unwrap().clone();
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Chip {
+    row: f32,
+    col: f32,
+}
 
-            if let BoardState::Taken(player) = item1.clone() {
-                if item1 == item2 && item1 == item3 && item1 == item4 {
-                    // We found 4 in a row!
-                    return Some(player.clone());
-                }
-            } else {
-                // The board state is empty
-                // Continue searching for a winner
-                continue;
-            }
-        }
-    }
+// Sent whenever someone (a click, or an AI) wants to make a move
+#[derive(Event)]
+pub struct PlayMoveEvent {
+    pub played: Move,
+}
 
-    // No wins were found
-    None
+// Sent once a chip has actually gone into the board
+#[derive(Event)]
+pub struct ChipPlacedEvent {
+    pub row: usize,
+    pub col: usize,
+    pub player: PlayerNum,
 }
-fn check_vertical_wins(board: Board) -> Option<Player> {
-    // Check for 4 in a column, on all columns
 
-    // How low down we can go, where the is still 4 items to check
-    // (eg you cant get ma vertical win if you start from the bottom row)
-    let max_row_index = board.num_columns() - 4;
+// Sent once the bottom chip of a column has been popped out, and the rest have fallen down
+#[derive(Event)]
+pub struct ChipPoppedEvent {
+    pub col: usize,
+}
 
-    for col_index in 0..board.num_columns() {
-        for row_index in 0..max_row_index {
-            let item1 = board.get(row_index, col_index).unwrap().clone();
-            let item2 = board.get(row_index + 1, col_index).unwrap().clone();
-            let item3 = board.get(row_index + 2, col_index).unwrap().clone();
-            let item4 = board.get(row_index + 3, col_index).unwrap().clone();
+// Where the middle of a hole is
+pub fn hole_position(row: usize, col: usize) -> Vec2 {
+    let row = row as f32;
+    let col = col as f32;
 
-            if let BoardState::Taken(player) = item1.clone() {
-                if item1 == item2 && item1 == item3 && item1 == item4 {
-                    return Some(player.clone());
-                }
-            } else {
-                continue;
-            }
-        }
+    Vec2::new(
+        GAP + CIRCLE_RADIUS + (col * CIRCLE_DIAMETER) + (col * GAP),
+        GAP + CIRCLE_RADIUS + (row * CIRCLE_DIAMETER) + (row * GAP),
+    )
+}
+
+pub fn player_colour(player: &PlayerNum) -> Color {
+    match player {
+        PlayerNum::Player1 => RED,
+        PlayerNum::Player2 => YELLOW,
     }
+}
 
-    None
+#[derive(Component)]
+struct ColPicker {
+    col: f32,
 }
-fn check_diagonal_wins(board: Board) -> Option<Player> {
-    // Check for 4 in a diagonal, in both direction
 
-    // First focus on ones going from top left to bottom right
-    let max_row_index = board.num_columns() - 4;
-    let max_col_index = board.num_columns() - 3;
+// The special chip the next click on a column will play, if any (only with power up)
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct ArmedSpecial(pub Option<Special>);
 
-    for row_index in 0..max_row_index {
-        for col_index in 0..max_col_index {
-            let item1 = board.get(row_index, col_index).unwrap().clone();
-            let item2 = board.get(row_index + 1, col_index + 1).unwrap().clone();
-            let item3 = board.get(row_index + 2, col_index + 2).unwrap().clone();
-            let item4 = board.get(row_index + 3, col_index + 3).unwrap().clone();
+// The column to light up as the best move, when a hint has been asked for (see `hint`)
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct HintedColumn(pub Option<usize>);
 
-            if let BoardState::Taken(player) = item1.clone() {
-                if item1 == item2 && item1 == item3 && item1 == item4 {
-                    return Some(player.clone());
-                }
-            } else {
-                // Empty space
-                continue;
-            }
-        }
-    }
+// An earlier point in the game to draw instead of the real board, while looking back over it (see `review`)
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct ShownBoard(pub Option<Board>);
 
-    // Now focus on ones going from top right to bottom left
-    let min_col_index = board.num_columns() - max_col_index;
+// A strip under a column, to click on to pop its bottom chip out (only with popout)
+#[derive(Component)]
+struct PopPicker {
+    col: usize,
+}
 
-    for row_index in 0..max_row_index {
-        for col_index in (min_col_index..board.num_columns()).rev() {
-            let item1 = board.get(row_index, col_index).unwrap().clone();
-            let item2 = board.get(row_index + 1, col_index - 1).unwrap().clone();
-            let item3 = board.get(row_index + 2, col_index - 2).unwrap().clone();
-            let item4 = board.get(row_index + 3, col_index - 3).unwrap().clone();
+// Everything that makes up the board itself, so it can be thrown away when the size changes
+#[derive(Component)]
+struct BoardPart;
 
-            if let BoardState::Taken(player) = item1.clone() {
-                if item1 == item2 && item1 == item3 && item1 == item4 {
-                    return Some(player.clone());
-                }
-            } else {
-                // Empty space
-                continue;
-            }
-        }
+fn despawn_board(mut command: Commands, parts: Query<Entity, With<BoardPart>>) {
+    for part in &parts {
+        command.entity(part).despawn();
     }
-
-    None
 }
 
 fn spawn_board_background(
     mut command: Commands,
+    config: Res<BoardConfig>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     // A Blue backgorund for the board
-    let bg_width = GAP + (COL_COUNT * CIRCLE_DIAMETER) + (COL_COUNT * GAP);
-    let bg_height = GAP + (ROW_COUNT * CIRCLE_DIAMETER) + (ROW_COUNT * GAP);
+    let Vec2 {
+        x: bg_width,
+        y: bg_height,
+    } = board_size(&config);
 
     // Th bg shape
     let bg_rectangle = Mesh2dHandle(meshes.add(Rectangle::new(bg_width, bg_height)));
 
-    command.spawn(MaterialMesh2dBundle {
-        mesh: bg_rectangle.clone(),
-        material: materials.add(BLUE),
-        transform: Transform::from_xyz(bg_width / 2., bg_height / 2., -1.), // Half to center it, -1 to make it behind other stuff
-        ..default()
-    });
+    command.spawn((
+        MaterialMesh2dBundle {
+            mesh: bg_rectangle.clone(),
+            material: materials.add(BLUE),
+            transform: Transform::from_xyz(bg_width / 2., bg_height / 2., -1.), // Half to center it, -1 to make it behind other stuff
+            ..default()
+        },
+        BoardPart,
+    ));
 }
 
 fn spawn_initial_chips(
     mut command: Commands,
+    config: Res<BoardConfig>,
+    rules: Res<Rules>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
@@ -263,15 +374,15 @@ fn spawn_initial_chips(
     let circle = Mesh2dHandle(meshes.add(Circle::new(CIRCLE_RADIUS)));
 
     // Draw the holes
-    for row in 0..ROW_COUNT as u8 {
-        for col in 0..COL_COUNT as u8 {
+    for row in 0..config.rows {
+        for col in 0..config.columns {
             let row = row as f32;
             let col = col as f32;
 
             let x = GAP + CIRCLE_RADIUS + (col * CIRCLE_DIAMETER) + (col * GAP);
             let y = GAP + CIRCLE_RADIUS + (row * CIRCLE_DIAMETER) + (row * GAP);
 
-            command.spawn((
+            let mut chip = command.spawn((
                 MaterialMesh2dBundle {
                     mesh: circle.clone(),
                     material: materials.add(BLACK),
@@ -279,30 +390,44 @@ fn spawn_initial_chips(
                     ..default()
                 },
                 Chip { row, col },
-                Pickable::IGNORE,
+                BoardPart,
             ));
+
+            // Without gravity, the holes themselves get clicked on instead of the columns
+            if rules.variant().has_gravity() {
+                chip.insert(Pickable::IGNORE);
+            } else {
+                chip.insert((
+                    PickableBundle::default(),
+                    On::<Pointer<Click>>::run(update_hole_handle_click),
+                ));
+            }
         }
     }
 }
 
 fn spawn_initial_col_hightlights(
     mut command: Commands,
+    config: Res<BoardConfig>,
+    rules: Res<Rules>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let rect = Mesh2dHandle(meshes.add(Rectangle::new(
-        GAP + CIRCLE_DIAMETER,
-        (GAP + CIRCLE_DIAMETER) * ROW_COUNT + GAP,
-    )));
+    if !rules.variant().has_gravity() {
+        return;
+    }
+
+    let bg_height = board_size(&config).y;
+    let rect = Mesh2dHandle(meshes.add(Rectangle::new(GAP + CIRCLE_DIAMETER, bg_height)));
 
     let mut transparent_black = BLACK;
     transparent_black.set_alpha(0.5);
 
-    for col in 0..COL_COUNT as u8 {
+    for col in 0..config.columns {
         let col = col as f32;
 
         let x = GAP + CIRCLE_RADIUS + (col * CIRCLE_DIAMETER) + (col * GAP);
-        let y = GAP + CIRCLE_RADIUS + (2. * CIRCLE_DIAMETER) + (7. * GAP); // TODO: Acctual calculations
+        let y = bg_height / 2.;
 
         command.spawn((
             MaterialMesh2dBundle {
@@ -315,25 +440,90 @@ fn spawn_initial_col_hightlights(
             ColPicker { col },
             PickableBundle::default(),
             On::<Pointer<Click>>::run(update_col_handle_click),
+            BoardPart,
+        ));
+    }
+}
+
+fn spawn_pop_pickers(
+    mut command: Commands,
+    config: Res<BoardConfig>,
+    rules: Res<Rules>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !rules.variant().has_pops() {
+        return;
+    }
+
+    let height = CIRCLE_RADIUS;
+    let rect = Mesh2dHandle(meshes.add(Rectangle::new(GAP + CIRCLE_DIAMETER, height)));
+
+    let mut transparent_black = BLACK;
+    transparent_black.set_alpha(0.5);
+
+    for col in 0..config.columns {
+        // Right under the bottom hole, just off the board
+        let x = hole_position(0, col).x;
+        let y = -(GAP + height / 2.);
+
+        command.spawn((
+            MaterialMesh2dBundle {
+                mesh: rect.clone(),
+                material: materials.add(transparent_black),
+                transform: Transform::from_xyz(x, y, 0.),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            PopPicker { col },
+            PickableBundle::default(),
+            On::<Pointer<Click>>::run(update_pop_handle_click),
+            BoardPart,
         ));
     }
 }
 
 fn update_chip_colour(
     board: Res<Board>,
+    shown: Res<ShownBoard>,
+    falling_query: Query<&FallingChip>,
+    mut landed: RemovedComponents<FallingChip>,
     mut chip_query: Query<(&Chip, &Handle<ColorMaterial>)>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
+    // Nothing to recolour if no chips have been played or finished falling
+    if !board.is_changed() && !shown.is_changed() && landed.read().count() == 0 {
+        return;
+    }
+
+    let board = shown.0.as_ref().unwrap_or(&board);
+
+    let grid = board.to_array2d();
+    let win = board.win();
+
     for (chip, colour_handle) in &mut chip_query.iter_mut() {
         // Get the board state from the 2d array
-        let state = board.get(chip.row as usize, chip.col as usize).unwrap();
+        let state = grid.get(chip.row as usize, chip.col as usize).unwrap();
+
+        // Holes with a chip still falling into them stay empty until it lands
+        let falling = falling_query
+            .iter()
+            .any(|falling| falling.row == chip.row as usize && falling.col == chip.col as usize);
+
+        // Once someone has won, everything but the winning line fades out
+        // (unless the variant doesnt win with lines, then there is nothing to pick out)
+        let dimmed = match &win {
+            Some(win) if !win.cells.is_empty() => {
+                !win.cells.contains(&(chip.row as usize, chip.col as usize))
+            }
+            _ => false,
+        };
 
         // If its taken, display the players colour,
         let colour = match state {
-            BoardState::Taken(player) => match player.num {
-                PlayerNum::Player1 => RED,
-                PlayerNum::Player2 => YELLOW,
-            },
+            BoardState::Taken(_) if falling => BLACK,
+            BoardState::Taken(player) if dimmed => player_colour(player).darker(DIM_AMOUNT),
+            BoardState::Taken(player) => player_colour(player),
             BoardState::Empty => BLACK,
         };
 
@@ -345,68 +535,309 @@ fn update_chip_colour(
     }
 }
 
+// The hinted column stays lit up (in a different colour) whether its hovered or not
 fn update_col_rect_visibility(
-    mut col_query: Query<(&ColPicker, &PickingInteraction, &mut Visibility)>,
+    hinted: Res<HintedColumn>,
+    mut col_query: Query<(
+        &ColPicker,
+        &PickingInteraction,
+        &Handle<ColorMaterial>,
+        &mut Visibility,
+    )>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    for (col, interaction, mut visibility) in &mut col_query.iter_mut() {
+    for (col, interaction, colour_handle, mut visibility) in &mut col_query.iter_mut() {
+        let is_hinted = **hinted == Some(col.col as usize);
+
         match interaction {
+            _ if is_hinted => *visibility = Visibility::Visible,
             PickingInteraction::None => *visibility = Visibility::Hidden,
             PickingInteraction::Pressed => *visibility = Visibility::Hidden,
             PickingInteraction::Hovered => *visibility = Visibility::Visible,
         }
+
+        let colour = if is_hinted {
+            HINT_COLOUR
+        } else {
+            BLACK.with_alpha(0.5)
+        };
+
+        // Only touching the material when it needs changing, since that sends it off to be redrawn
+        if materials.get(colour_handle).unwrap().color != colour {
+            materials.get_mut(colour_handle).unwrap().color = colour;
+        }
+    }
+}
+
+// Pop pickers only show up when the chip at the bottom is yours to pop
+fn update_pop_picker_visibility(
+    board: Res<Board>,
+    mut pop_query: Query<(&PopPicker, &PickingInteraction, &mut Visibility)>,
+) {
+    for (picker, interaction, mut visibility) in &mut pop_query {
+        *visibility = match interaction {
+            PickingInteraction::Hovered if board.legal_pops().contains(&picker.col) => {
+                Visibility::Visible
+            }
+            _ => Visibility::Hidden,
+        };
     }
 }
 
+// Whether a click on the board should do anything right now
+fn can_click_board(
+    app_state: &State<AppState>,
+    current_player: &CurrentPlayer,
+    falling_query: &Query<(), With<FallingChip>>,
+) -> bool {
+    // No moving again until the last chip has landed, and the AI makes its own moves
+    *app_state.get() == AppState::InGame
+        && !is_animating(falling_query)
+        && current_player.kind == PlayerKind::Human
+}
+
 fn update_col_handle_click(
     event: Listener<Pointer<Click>>,
-    game_state: Res<GameState>,
-    time: Res<Time>,
-    mut col_query: Query<(&mut ColPicker, &Handle<ColorMaterial>)>,
-    mut board: ResMut<Board>,
-    mut current_player: ResMut<CurrentPlayer>,
-    mut next_player_event: EventWriter<NextPlayerEvent>,
+    app_state: Res<State<AppState>>,
+    col_query: Query<&ColPicker>,
+    current_player: Res<CurrentPlayer>,
+    falling_query: Query<(), With<FallingChip>>,
+    mut armed: ResMut<ArmedSpecial>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
+) {
+    if !can_click_board(&app_state, &current_player, &falling_query) {
+        return;
+    }
+
+    let Ok(col) = col_query.get(event.target) else {
+        panic!("Tried to get target from query, where it doesnt exist in the query")
+    };
+    let col = col.col as usize;
+
+    // An armed special only gets one go, even if the rules dont allow it there
+    let played = match armed.take() {
+        Some(special) => Move::Special(special, col),
+        None => Move::Drop(col),
+    };
+
+    play_move_event.send(PlayMoveEvent { played });
+}
+
+fn update_hole_handle_click(
+    event: Listener<Pointer<Click>>,
+    app_state: Res<State<AppState>>,
+    chip_query: Query<&Chip>,
+    current_player: Res<CurrentPlayer>,
+    falling_query: Query<(), With<FallingChip>>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
 ) {
-    if !(*game_state == GameState::Playing) {
+    if !can_click_board(&app_state, &current_player, &falling_query) {
         return;
     }
 
-    let Ok((mut col, colour_handle)) = col_query.get_mut(event.target) else {
+    let Ok(chip) = chip_query.get(event.target) else {
         panic!("Tried to get target from query, where it doesnt exist in the query")
     };
 
-    let column = col.col as usize;
-    let Some(row) = get_lowest_chip_row(Board(board.clone()), column) else {
+    play_move_event.send(PlayMoveEvent {
+        played: Move::Place {
+            row: chip.row as usize,
+            col: chip.col as usize,
+        },
+    });
+}
+
+// Press the letter of a special (eg A for the anvil) to play it with the next click, and again to put it back
+fn update_arm_special(
+    keys: Res<ButtonInput<KeyCode>>,
+    board: Res<Board>,
+    current_player: Res<CurrentPlayer>,
+    mut armed: ResMut<ArmedSpecial>,
+) {
+    // Whatever was armed doesnt carry over to the next player, or a new game
+    if board.is_changed() && armed.is_some() {
+        **armed = None;
+    }
+
+    if current_player.kind != PlayerKind::Human
+        || keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+
+    for &special in board.variant().specials() {
+        let key = match special {
+            Special::Anvil => KeyCode::KeyA,
+            Special::Bomb => KeyCode::KeyB,
+            Special::Double => KeyCode::KeyD,
+        };
+        if !keys.just_pressed(key) {
+            continue;
+        }
+
+        **armed = if **armed == Some(special) {
+            None
+        } else {
+            Some(special)
+        };
+    }
+}
+
+fn update_pop_handle_click(
+    event: Listener<Pointer<Click>>,
+    app_state: Res<State<AppState>>,
+    pop_query: Query<&PopPicker>,
+    current_player: Res<CurrentPlayer>,
+    falling_query: Query<(), With<FallingChip>>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
+) {
+    if !can_click_board(&app_state, &current_player, &falling_query) {
         return;
+    }
+
+    let Ok(picker) = pop_query.get(event.target) else {
+        panic!("Tried to get target from query, where it doesnt exist in the query")
     };
 
-    let Some(board_state) = board.get_mut(row, column) else {
-        panic!("Tried to get the value of a non-existing hole")
+    play_move_event.send(PlayMoveEvent {
+        played: Move::Pop(picker.col),
+    });
+}
+
+fn update_play_move(
+    mut events: EventReader<PlayMoveEvent>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<MoveHistory>,
+    mut next_player_event: EventWriter<NextPlayerEvent>,
+    mut chip_placed_event: EventWriter<ChipPlacedEvent>,
+    mut chip_popped_event: EventWriter<ChipPoppedEvent>,
+) {
+    for event in events.read() {
+        let player = board.to_move().into();
+
+        // Full columns (and anything else the rules dont allow) are just ignored
+        let outcome = match board.play_move(event.played) {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                debug!("Ignoring {:?}: {error}", event.played);
+                continue;
+            }
+        };
+
+        history.push(PlayedMove {
+            played: event.played,
+            row: outcome.row(),
+            player: player.clone(),
+        });
+
+        // Only chips that came in from the top fall, placed chips and bombs just appear (or disappear)
+        match event.played {
+            Move::Drop(col)
+            | Move::Special(Special::Anvil, col)
+            | Move::Special(Special::Double, col) => {
+                chip_placed_event.send(ChipPlacedEvent {
+                    row: outcome.row(),
+                    col,
+                    player,
+                });
+            }
+            Move::Pop(col) => {
+                chip_popped_event.send(ChipPoppedEvent { col });
+            }
+            Move::Place { .. } | Move::Special(Special::Bomb, _) => {}
+        }
+
+        // Next player
+        next_player_event.send_default();
+    }
+}
+
+// The game is over as soon as the board says so, and back on again if the last move gets undone
+fn update_game_over(
+    board: Res<Board>,
+    app_state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !board.is_changed() {
+        return;
+    }
+
+    match (app_state.get(), board.is_over()) {
+        (AppState::InGame, true) => {
+            if board.is_draw() {
+                println!("Draw!");
+            }
+            next_state.set(AppState::GameOver);
+        }
+        (AppState::GameOver, false) => next_state.set(AppState::InGame),
+        _ => {}
+    }
+}
+
+fn update_win_rings(
+    mut command: Commands,
+    board: Res<Board>,
+    shown: Res<ShownBoard>,
+    ring_query: Query<Entity, With<WinRing>>,
+    falling_query: Query<(), With<FallingChip>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // Wait for the winning chip to land before showing off the line
+    let win = match shown.0.as_ref().unwrap_or(&board).win() {
+        Some(win) if !is_animating(&falling_query) => win,
+        _ => {
+            for ring in &ring_query {
+                command.entity(ring).despawn();
+            }
+            return;
+        }
     };
 
-    // Dont update any holes which have already been taken
-    if *board_state != BoardState::Empty {
-        debug!("Tried to change the state of a taken board state...ignoring");
+    if !ring_query.is_empty() && !board.is_changed() && !shown.is_changed() {
         return;
     }
 
-    // Set the new board state
-    *board_state = BoardState::Taken(current_player.clone());
+    for ring in &ring_query {
+        command.entity(ring).despawn();
+    }
+
+    let ring = Mesh2dHandle(meshes.add(Ring::new(CIRCLE_RADIUS, CIRCLE_RADIUS + GAP / 2.)));
+    let material = materials.add(Color::from(LIGHT_GREEN));
+
+    for (row, col) in win.cells {
+        let position = hole_position(row, col);
+
+        command.spawn((
+            MaterialMesh2dBundle {
+                mesh: ring.clone(),
+                material: material.clone(),
+                // In front of the chips
+                transform: Transform::from_xyz(position.x, position.y, 3.),
+                ..default()
+            },
+            WinRing,
+            Pickable::IGNORE,
+        ));
+    }
+}
 
-    // Next player
-    next_player_event.send_default();
+fn update_pulse_win_rings(time: Res<Time>, mut ring_query: Query<&mut Transform, With<WinRing>>) {
+    let scale = 1. + 0.08 * (time.elapsed_seconds() * 5.).sin();
+
+    for mut transform in &mut ring_query {
+        transform.scale = Vec3::splat(scale);
+    }
 }
 
-fn update_update_game_state(mut game_state: ResMut<GameState>, board: Res<Board>) {
-    if is_full(Board(board.clone())) {
-        println!("Draw!");
-        *game_state = GameState::Draw;
+// Press N to get the moves so far, to paste into bug reports
+fn update_print_notation(keys: Res<ButtonInput<KeyCode>>, board: Res<Board>) {
+    if !keys.just_pressed(KeyCode::KeyN) {
         return;
     }
 
-    if check_vertical_wins(Board(board.clone())).is_some()
-        || check_diagonal_wins(Board(board.clone())).is_some()
-        || check_horizontal_wins(Board(board.clone())).is_some()
-    {
-        *game_state = GameState::Won;
+    match board.to_notation() {
+        Some(notation) => println!("Moves so far: {notation}"),
+        None => println!("The moves that were played arent known for this board"),
     }
 }
@@ -0,0 +1,505 @@
+// An opening book: the exact solver score of every position up to some number of moves
+// Solving the first few moves from scratch takes ages, so we do it once and save it to a file
+//
+// The file is laid out as:
+//  - the magic bytes "C4BK", then a version byte
+//  - the board width, height, and book depth, a byte each
+//  - how many entries there are, as a little endian u32
+//  - every entry, sorted by key: a 7 byte little endian key, then the score as an i8
+//  - an FNV-1a checksum of everything before it, as a little endian u64
+
+use crate::rules::*;
+use crate::solver::Solver;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const DEFAULT_BOOK_PATH: &str = "opening_book.bin";
+pub const DEFAULT_BOOK_DEPTH: usize = 12;
+
+// Only standard boards get a book, anything else is rare enough to just solve
+const CONFIG: BoardConfig = BoardConfig::STANDARD;
+
+const MAGIC: &[u8; 4] = b"C4BK";
+const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 4 + 1 + 3 + 4;
+const KEY_LEN: usize = 7;
+const ENTRY_LEN: usize = KEY_LEN + 1;
+const CHECKSUM_LEN: usize = 8;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(std::io::Error),
+    NotABook,
+    UnsupportedVersion(u8),
+    WrongBoardSize { width: u8, height: u8 },
+    WrongLength { expected: usize, found: usize },
+    ChecksumMismatch,
+    // Keys have to be sorted, with no repeats, and fit in the board
+    BadKey { index: usize },
+    BadScore { index: usize, score: i8 },
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io(error) => write!(f, "could not read the opening book: {error}"),
+            BookError::NotABook => write!(f, "this is not an opening book file"),
+            BookError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "opening book version {version} is not supported (expected {VERSION})"
+                )
+            }
+            BookError::WrongBoardSize { width, height } => write!(
+                f,
+                "the opening book is for a {width}x{height} board, not {}x{}",
+                CONFIG.columns, CONFIG.rows
+            ),
+            BookError::WrongLength { expected, found } => write!(
+                f,
+                "the opening book should be {expected} bytes long, but is {found}"
+            ),
+            BookError::ChecksumMismatch => write!(f, "the opening book is corrupt (bad checksum)"),
+            BookError::BadKey { index } => {
+                write!(f, "the opening book has a bad key at entry {index}")
+            }
+            BookError::BadScore { index, score } => {
+                write!(
+                    f,
+                    "the opening book has an impossible score {score} at entry {index}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+impl From<std::io::Error> for BookError {
+    fn from(error: std::io::Error) -> Self {
+        BookError::Io(error)
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub struct OpeningBook {
+    depth: usize,
+    // Sorted by key, keys are `Position::symmetric_key`
+    entries: Vec<(u64, i8)>,
+}
+
+impl OpeningBook {
+    fn from_entries(depth: usize, mut entries: Vec<(u64, i8)>) -> Self {
+        entries.sort_unstable_by_key(|&(key, _)| key);
+        OpeningBook { depth, entries }
+    }
+
+    // The deepest a position can be and still be in the book
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // The solver score of a position, if its in the book
+    pub fn get(&self, position: &Position) -> Option<i32> {
+        self.get_bits(&position.small_bits()?)
+    }
+
+    // The same, straight from the solver
+    pub(crate) fn get_bits(&self, position: &Bits<u64>) -> Option<i32> {
+        if *position.config() != CONFIG || position.moves_played() > self.depth {
+            return None;
+        }
+
+        let key = position.symmetric_key();
+        let index = self
+            .entries
+            .binary_search_by_key(&key, |&(key, _)| key)
+            .ok()?;
+
+        Some(self.entries[index].1 as i32)
+    }
+
+    // The best column, if every column can be looked up in the book
+    pub fn best_move(&self, position: &Position) -> Option<usize> {
+        let mut best: Option<(usize, i32)> = None;
+
+        if *position.config() != CONFIG || position.rules() != Rules::Standard {
+            return None;
+        }
+
+        for col in crate::ai::center_first_order(&CONFIG) {
+            let mut next = *position;
+            let score = match next.play(col) {
+                // Cant do any better than winning now
+                Ok(MoveOutcome::Won { .. }) => return Some(col),
+                Ok(MoveOutcome::Draw { .. }) => 0,
+                Ok(MoveOutcome::Placed { .. }) => -self.get(&next)?,
+                Err(_) => continue,
+            };
+
+            if best.map_or(true, |(_, best_score)| score > best_score) {
+                best = Some((col, score));
+            }
+        }
+
+        best.map(|(col, _)| col)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(HEADER_LEN + self.entries.len() * ENTRY_LEN + CHECKSUM_LEN);
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(CONFIG.columns as u8);
+        bytes.push(CONFIG.rows as u8);
+        bytes.push(self.depth as u8);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        for &(key, score) in &self.entries {
+            bytes.extend_from_slice(&key.to_le_bytes()[..KEY_LEN]);
+            bytes.push(score as u8);
+        }
+
+        bytes.extend_from_slice(&checksum(&bytes).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN || &bytes[..4] != MAGIC {
+            return Err(BookError::NotABook);
+        }
+
+        if bytes[4] != VERSION {
+            return Err(BookError::UnsupportedVersion(bytes[4]));
+        }
+
+        let (width, height, depth) = (bytes[5], bytes[6], bytes[7]);
+        if width as usize != CONFIG.columns || height as usize != CONFIG.rows {
+            return Err(BookError::WrongBoardSize { width, height });
+        }
+
+        let count = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let expected = HEADER_LEN + count * ENTRY_LEN + CHECKSUM_LEN;
+        if bytes.len() != expected {
+            return Err(BookError::WrongLength {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let (contents, stored_checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if checksum(contents) != u64::from_le_bytes(stored_checksum.try_into().unwrap()) {
+            return Err(BookError::ChecksumMismatch);
+        }
+
+        let max_score = CONFIG.cells() as i8 / 2;
+        let mut entries = Vec::with_capacity(count);
+
+        for (index, entry) in contents[HEADER_LEN..].chunks_exact(ENTRY_LEN).enumerate() {
+            let mut key_bytes = [0; 8];
+            key_bytes[..KEY_LEN].copy_from_slice(&entry[..KEY_LEN]);
+            let key = u64::from_le_bytes(key_bytes);
+            let score = entry[KEY_LEN] as i8;
+
+            let sorted = entries.last().map_or(true, |&(last, _)| key > last);
+            if key >> (CONFIG.columns * (CONFIG.rows + 1)) != 0 || !sorted {
+                return Err(BookError::BadKey { index });
+            }
+
+            if !(-max_score..=max_score).contains(&score) {
+                return Err(BookError::BadScore { index, score });
+            }
+
+            entries.push((key, score));
+        }
+
+        Ok(OpeningBook {
+            depth: depth as usize,
+            entries,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        OpeningBook::from_bytes(&std::fs::read(path)?)
+    }
+
+    // Not having a book is fine, so thats None rather than an error, but a broken one still is
+    pub fn load_if_present(path: impl AsRef<Path>) -> Result<Option<Self>, BookError> {
+        match OpeningBook::load(path) {
+            Ok(book) => Ok(Some(book)),
+            Err(BookError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BookError> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    // Solve every position up to `depth` moves in, which can take a very long time for deep books
+    // Positions are solved deepest first, so the shallower ones can use the book made so far
+    pub fn generate(depth: usize, threads: usize, progress: impl Fn(usize, usize, usize)) -> Self {
+        let levels = positions_by_depth(depth);
+        let mut book = Arc::new(OpeningBook::from_entries(depth, Vec::new()));
+
+        for (level, positions) in levels.iter().enumerate().rev() {
+            let chunk_len = positions.len().div_ceil(threads.max(1)).max(1);
+
+            let new_entries: Vec<(u64, i8)> = std::thread::scope(|scope| {
+                let handles: Vec<_> = positions
+                    .chunks(chunk_len)
+                    .map(|chunk| {
+                        let book = Arc::clone(&book);
+                        scope.spawn(move || {
+                            let mut solver = Solver::new();
+                            solver.set_book(Some(book));
+
+                            chunk
+                                .iter()
+                                .map(|position| {
+                                    let score = solver.solve(position, false);
+                                    (position.symmetric_key() as u64, score as i8)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("A book generating thread panicked"))
+                    .collect()
+            });
+
+            let mut entries = book.entries.clone();
+            entries.extend(new_entries);
+            book = Arc::new(OpeningBook::from_entries(depth, entries));
+
+            progress(level, positions.len(), book.len());
+        }
+
+        Arc::try_unwrap(book)
+            .unwrap_or_else(|book| OpeningBook::from_entries(depth, book.entries.clone()))
+    }
+}
+
+// Every position that isnt over yet, up to `depth` moves in, with mirror images only counted once
+fn positions_by_depth(depth: usize) -> Vec<Vec<Position>> {
+    let mut levels = vec![vec![Position::new()]];
+
+    while levels.len() <= depth {
+        let mut seen = HashSet::new();
+        let mut next_level = Vec::new();
+
+        for position in levels.last().unwrap() {
+            for col in 0..CONFIG.columns {
+                let mut next = *position;
+                let Ok(MoveOutcome::Placed { .. }) = next.play(col) else {
+                    continue;
+                };
+
+                if seen.insert(next.symmetric_key()) {
+                    next_level.push(next);
+                }
+            }
+        }
+
+        levels.push(next_level);
+    }
+
+    levels
+}
+
+// `connect4 generate-book [--depth N] [--output PATH] [--threads N]`
+pub fn run_generate_book(args: &[String]) -> Result<(), String> {
+    let mut depth = DEFAULT_BOOK_DEPTH;
+    let mut output = DEFAULT_BOOK_PATH.to_string();
+    let mut threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            return Err(format!("{arg} needs a value after it"));
+        };
+
+        match arg.as_str() {
+            "--depth" => {
+                depth = value
+                    .parse()
+                    .map_err(|error| format!("invalid depth '{value}': {error}"))?
+            }
+            "--output" => output = value.clone(),
+            "--threads" => {
+                threads = value
+                    .parse()
+                    .map_err(|error| format!("invalid thread count '{value}': {error}"))?
+            }
+            _ => return Err(format!("unknown option '{arg}'")),
+        }
+    }
+
+    if depth >= CONFIG.cells() {
+        return Err(format!(
+            "the book depth has to be less than {}",
+            CONFIG.cells()
+        ));
+    }
+
+    println!("Generating an opening book {depth} moves deep, using {threads} threads");
+
+    let book = OpeningBook::generate(depth, threads, |level, solved, total| {
+        println!("Solved {solved} positions {level} moves in ({total} in the book so far)");
+    });
+
+    book.save(&output).map_err(|error| error.to_string())?;
+    println!("Saved {} positions to {output}", book.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::Game;
+
+    fn position(notation: &str) -> Position {
+        *Game::from_notation(notation).unwrap().position()
+    }
+
+    // A book with made up scores, so it doesnt need solving
+    fn book_of(depth: usize, scores: &[(&str, i8)]) -> OpeningBook {
+        let entries = scores
+            .iter()
+            .map(|&(notation, score)| (position(notation).symmetric_key() as u64, score))
+            .collect();
+        OpeningBook::from_entries(depth, entries)
+    }
+
+    fn test_book() -> OpeningBook {
+        // Every move after "12", where only going back in the first column is any good
+        let mut scores = vec![("12", 3)];
+        let notations: Vec<String> = (1..=7).map(|col| format!("12{col}")).collect();
+        for notation in &notations {
+            let score = if notation == "121" { -5 } else { 0 };
+            scores.push((notation, score));
+        }
+        book_of(3, &scores)
+    }
+
+    #[test]
+    fn mirrored_positions_are_looked_up() {
+        let book = test_book();
+        assert_eq!(book.get(&position("12")), Some(3));
+        assert_eq!(book.get(&position("76")), Some(3));
+        assert_eq!(book.get(&position("12").mirror()), Some(3));
+        assert_eq!(book.get(&position("13")), None);
+
+        assert_eq!(book.best_move(&position("12")), Some(0));
+        assert_eq!(book.best_move(&position("76")), Some(6));
+    }
+
+    #[test]
+    fn books_round_trip() {
+        let book = test_book();
+        let read = OpeningBook::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(read.depth(), book.depth());
+        assert_eq!(read.entries, book.entries);
+    }
+
+    #[test]
+    fn broken_books_are_turned_down() {
+        let bytes = test_book().to_bytes();
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(
+            OpeningBook::from_bytes(truncated),
+            Err(BookError::WrongLength { expected, found }) if expected == bytes.len() && found == bytes.len() - 1
+        ));
+        assert!(matches!(
+            OpeningBook::from_bytes(&bytes[..HEADER_LEN]),
+            Err(BookError::NotABook)
+        ));
+        assert!(matches!(
+            OpeningBook::from_bytes(&[]),
+            Err(BookError::NotABook)
+        ));
+
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LEN] ^= 1;
+        assert!(matches!(
+            OpeningBook::from_bytes(&corrupt),
+            Err(BookError::ChecksumMismatch)
+        ));
+
+        let mut header = bytes.clone();
+        header[0] = b'X';
+        assert!(matches!(
+            OpeningBook::from_bytes(&header),
+            Err(BookError::NotABook)
+        ));
+
+        let mut version = bytes.clone();
+        version[4] = VERSION + 1;
+        assert!(matches!(
+            OpeningBook::from_bytes(&version),
+            Err(BookError::UnsupportedVersion(found)) if found == VERSION + 1
+        ));
+
+        let mut size = bytes.clone();
+        size[5] = 8;
+        assert!(matches!(
+            OpeningBook::from_bytes(&size),
+            Err(BookError::WrongBoardSize {
+                width: 8,
+                height: 6
+            })
+        ));
+    }
+
+    #[test]
+    fn bad_entries_are_turned_down() {
+        // Written straight out, so the checksum is right but the entries arent
+        let unsorted = OpeningBook {
+            depth: 1,
+            entries: vec![(2, 0), (1, 0)],
+        };
+        assert!(matches!(
+            OpeningBook::from_bytes(&unsorted.to_bytes()),
+            Err(BookError::BadKey { index: 1 })
+        ));
+
+        let impossible = OpeningBook {
+            depth: 1,
+            entries: vec![(1, 0), (2, 100)],
+        };
+        assert!(matches!(
+            OpeningBook::from_bytes(&impossible.to_bytes()),
+            Err(BookError::BadScore {
+                index: 1,
+                score: 100
+            })
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+// Talking to whoever is on the other end of a network game, in a panel down the right of the board
+// Press Enter to start typing and Enter again to send it (or Escape to give up), or click one of the emotes
+// Whatever is typed here goes out through `network`, which puts anything that comes in into the `ChatLog`
+
+use crate::board::AppState;
+use crate::net::{clean_chat, ChatLimit, MAX_CHAT_LENGTH};
+use crate::network::NetworkSettings;
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+const CHAT_COLOUR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const TYPING_COLOUR: Color = Color::srgb(0.1, 0.4, 0.1);
+const CHAT_WIDTH: f32 = 300.0;
+// How much is remembered, and how much of that fits in the panel
+const MAX_HISTORY: usize = 50;
+const SHOWN_LINES: usize = 8;
+
+// Quick things to say without typing them out
+const EMOTES: [&str; 4] = ["Good game", "Nice move", "Good luck", "Oops"];
+
+#[derive(Clone, PartialEq, Debug)]
+struct ChatLine {
+    // None for things the game itself has to say, like being told to slow down
+    from: Option<String>,
+    text: String,
+}
+
+// Everything said so far, oldest first
+#[derive(Resource, Default, Debug)]
+pub struct ChatLog {
+    lines: VecDeque<ChatLine>,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, from: &str, text: &str) {
+        self.push_line(Some(from.to_string()), text);
+    }
+
+    pub fn push_note(&mut self, text: &str) {
+        self.push_line(None, text);
+    }
+
+    fn push_line(&mut self, from: Option<String>, text: &str) {
+        self.lines.push_back(ChatLine {
+            from,
+            text: text.to_string(),
+        });
+        while self.lines.len() > MAX_HISTORY {
+            self.lines.pop_front();
+        }
+    }
+}
+
+// Something said on this computer, already cleaned up, for `network` to send
+#[derive(Event, Clone, Debug)]
+pub struct SendChatEvent(pub String);
+
+#[derive(Resource, Default)]
+struct ChatInput {
+    typing: bool,
+    draft: String,
+    limit: ChatLimit,
+}
+
+#[derive(Component)]
+struct ChatText;
+
+#[derive(Component)]
+struct ChatInputText;
+
+#[derive(Component, Clone, Copy)]
+struct EmoteButton(usize);
+
+pub fn plugin_chat(app: &mut App) {
+    app.init_resource::<ChatLog>();
+    app.init_resource::<ChatInput>();
+    app.add_event::<SendChatEvent>();
+
+    app.add_systems(Startup, spawn_chat);
+
+    // Straight after the keys are read, so nothing else sees the ones that were typed into the chat
+    app.add_systems(
+        PreUpdate,
+        update_chat_typing
+            .after(InputSystem)
+            .run_if(not(in_state(AppState::Setup))),
+    );
+    app.add_systems(OnEnter(AppState::Setup), stop_chat_typing);
+
+    app.add_systems(Update, (update_emote_buttons, update_chat_text).chain());
+}
+
+// Anything that isnt allowed is dropped here, rather than being sent for the other side to throw away
+fn say(
+    text: &str,
+    input: &mut ChatInput,
+    log: &mut ChatLog,
+    settings: &NetworkSettings,
+    send_chat_event: &mut EventWriter<SendChatEvent>,
+) {
+    let Some(text) = clean_chat(text) else {
+        return;
+    };
+
+    if !input.limit.allow() {
+        log.push_note("Slow down, that was a lot of messages");
+        return;
+    }
+
+    log.push(&settings.name, &text);
+    send_chat_event.send(SendChatEvent(text));
+}
+
+fn update_chat_typing(
+    mut events: EventReader<KeyboardInput>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    settings: Res<NetworkSettings>,
+    mut input: ResMut<ChatInput>,
+    mut log: ResMut<ChatLog>,
+    mut send_chat_event: EventWriter<SendChatEvent>,
+) {
+    let was_typing = input.typing;
+
+    for event in events.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+
+        if !input.typing {
+            if event.logical_key == Key::Enter {
+                input.typing = true;
+            }
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if input.draft.chars().count() < MAX_CHAT_LENGTH {
+                        input.draft.push(c);
+                    }
+                }
+            }
+            Key::Space if input.draft.chars().count() < MAX_CHAT_LENGTH => input.draft.push(' '),
+            Key::Backspace => {
+                input.draft.pop();
+            }
+            Key::Enter => {
+                let draft = std::mem::take(&mut input.draft);
+                say(
+                    &draft,
+                    &mut input,
+                    &mut log,
+                    &settings,
+                    &mut send_chat_event,
+                );
+                input.typing = false;
+            }
+            Key::Escape => {
+                input.draft.clear();
+                input.typing = false;
+            }
+            _ => {}
+        }
+    }
+
+    // Otherwise typing an R would reconnect, Escape would pause and so on
+    if was_typing || input.typing {
+        keys.reset_all();
+    }
+}
+
+fn stop_chat_typing(mut input: ResMut<ChatInput>) {
+    input.typing = false;
+}
+
+fn spawn_chat(mut command: Commands) {
+    command
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // Under the undo and redo buttons
+                right: Val::Px(5.0),
+                top: Val::Px(45.0),
+                width: Val::Px(CHAT_WIDTH),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(5.0),
+                padding: UiRect::all(Val::Px(5.0)),
+                ..default()
+            },
+            background_color: CHAT_COLOUR.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((TextBundle::from_section("", TextStyle::default()), ChatText));
+
+            parent.spawn((
+                TextBundle::from_section("", TextStyle::default())
+                    .with_background_color(Color::BLACK),
+                ChatInputText,
+            ));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_wrap: FlexWrap::Wrap,
+                        column_gap: Val::Px(5.0),
+                        row_gap: Val::Px(5.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (index, emote) in EMOTES.into_iter().enumerate() {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    background_color: Color::BLACK.into(),
+                                    ..default()
+                                },
+                                EmoteButton(index),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(emote, TextStyle::default()));
+                            });
+                    }
+                });
+        });
+}
+
+fn update_emote_buttons(
+    buttons: Query<(&Interaction, &EmoteButton), Changed<Interaction>>,
+    settings: Res<NetworkSettings>,
+    mut input: ResMut<ChatInput>,
+    mut log: ResMut<ChatLog>,
+    mut send_chat_event: EventWriter<SendChatEvent>,
+) {
+    for (interaction, &EmoteButton(index)) in &buttons {
+        if *interaction == Interaction::Pressed {
+            say(
+                EMOTES[index],
+                &mut input,
+                &mut log,
+                &settings,
+                &mut send_chat_event,
+            );
+        }
+    }
+}
+
+fn update_chat_text(
+    log: Res<ChatLog>,
+    input: Res<ChatInput>,
+    mut chat_texts: Query<&mut Text, (With<ChatText>, Without<ChatInputText>)>,
+    mut input_texts: Query<(&mut Text, &mut BackgroundColor), With<ChatInputText>>,
+) {
+    if log.is_changed() {
+        let shown = log
+            .lines
+            .iter()
+            .skip(log.lines.len().saturating_sub(SHOWN_LINES))
+            .map(|line| match &line.from {
+                Some(from) => format!("{from}: {}", line.text),
+                None => format!("({})", line.text),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        for mut text in &mut chat_texts {
+            text.sections[0].value = shown.clone();
+        }
+    }
+
+    if input.is_changed() {
+        let (prompt, colour) = if input.typing {
+            (format!("> {}_", input.draft), TYPING_COLOUR)
+        } else {
+            ("Press Enter to chat".to_string(), Color::BLACK)
+        };
+
+        for (mut text, mut background) in &mut input_texts {
+            text.sections[0].value = prompt.clone();
+            background.0 = colour;
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// The clocks on the screen, for games with a time control (see `clock` for how time is handed out)
+// In a network game the host (or server) keeps time, and the guest only shows what it gets told
+
+use crate::animation::{is_animating, FallingChip};
+use crate::board::{AppState, Board};
+use crate::clock::{Clocks, TimeControl};
+use crate::network::NetworkSettings;
+use crate::notation::ForfeitReason;
+use crate::players::{Player, PlayerKind, PlayerNum};
+use crate::rules::Side;
+
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+use std::time::Duration;
+
+const CLOCK_COLOUR: Color = Color::srgba(0., 0., 0., 0.6);
+const TO_MOVE_COLOUR: Color = Color::srgb(0.1, 0.4, 0.1);
+const LOW_TIME_COLOUR: Color = Color::srgb(1., 0.3, 0.3);
+
+// Under this the clock goes red, and shows tenths of a second too
+const LOW_TIME: Duration = Duration::from_secs(10);
+
+pub fn plugin_chess_clock(app: &mut App) {
+    app.init_resource::<ClockSettings>();
+    app.init_resource::<GameClocks>();
+    app.init_resource::<MoveStopwatch>();
+    app.init_resource::<CountedMoves>();
+
+    app.add_systems(Startup, spawn_clocks);
+
+    // After the moves for this frame have been played, so a clock never runs for the wrong player
+    // A replay isnt played against the clock
+    app.add_systems(
+        PostUpdate,
+        (
+            update_clocks_after_move
+                .run_if(resource_changed::<Board>.or_else(resource_changed::<ClockSettings>)),
+            update_move_stopwatch.run_if(in_state(AppState::InGame)),
+            update_flag,
+        )
+            .chain()
+            .run_if(not(in_state(AppState::Replay))),
+    );
+    app.add_systems(Update, update_clock_texts);
+}
+
+// The time control for new games, None to play without clocks
+#[derive(Resource, Clone, Copy, Default, Debug)]
+pub struct ClockSettings(pub Option<TimeControl>);
+
+impl ClockSettings {
+    // Reads `--time-control <control>`, eg `--time-control fischer:3m+2s`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        match args.iter().position(|arg| arg == "--time-control") {
+            Some(index) => match args.get(index + 1) {
+                Some(control) => Ok(ClockSettings(Some(control.parse()?))),
+                None => Err("--time-control needs a value after it".into()),
+            },
+            None => Ok(ClockSettings(None)),
+        }
+    }
+}
+
+// The clocks for the game being played, if it has any
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct GameClocks(pub Option<Clocks>);
+
+// How long whoever is to move has been thinking
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct MoveStopwatch(pub Stopwatch);
+
+// How many moves had been played the last time the board changed, to tell a move apart from a new game
+#[derive(Resource, Default, Deref, DerefMut)]
+struct CountedMoves(Option<usize>);
+
+#[derive(Component)]
+struct ClockPanel;
+
+#[derive(Component)]
+struct ClockText(Side);
+
+// Only the host (or server) decides how long anyone has
+fn is_guest(network: &Option<Res<NetworkSettings>>) -> bool {
+    network.as_ref().is_some_and(|settings| !settings.is_host())
+}
+
+// Whoever just moved stops their clock
+// Anything else (a new game or loading one) starts both clocks again
+// Moves cant be taken back while there are clocks, since the time they took cant be given back
+fn update_clocks_after_move(
+    board: Res<Board>,
+    settings: Res<ClockSettings>,
+    network: Option<Res<NetworkSettings>>,
+    mut clocks: ResMut<GameClocks>,
+    mut stopwatch: ResMut<MoveStopwatch>,
+    mut counted: ResMut<CountedMoves>,
+) {
+    let moves = board.moves_played();
+    let before = counted.replace(moves);
+    let guest = is_guest(&network);
+
+    if before.is_some_and(|before| before + 1 == moves) {
+        if !guest {
+            if let Some(clocks) = clocks.0.as_mut() {
+                clocks.finish_move(board.to_move().other(), stopwatch.elapsed());
+            }
+        }
+        stopwatch.reset();
+        return;
+    }
+
+    // A guest gets told the clocks along with the game instead
+    let new_game = before != Some(moves) || (moves == 0 && !board.is_over());
+    if !guest && (new_game || settings.is_changed()) {
+        **clocks = settings.0.map(Clocks::new);
+        stopwatch.reset();
+    }
+}
+
+// Only while someone here is playing, so waiting for the other side of a network game doesnt use up anyones time
+// (Watching a game, the clocks only move when the server says)
+fn update_move_stopwatch(
+    time: Res<Time>,
+    board: Res<Board>,
+    clocks: Res<GameClocks>,
+    players: Query<&Player>,
+    falling: Query<(), With<FallingChip>>,
+    mut stopwatch: ResMut<MoveStopwatch>,
+) {
+    if clocks.is_none() || board.is_over() || is_animating(&falling) {
+        return;
+    }
+
+    if players
+        .iter()
+        .all(|player| player.kind == PlayerKind::Remote)
+    {
+        return;
+    }
+
+    stopwatch.tick(time.delta());
+}
+
+// Running out of time loses the game
+fn update_flag(
+    mut board: ResMut<Board>,
+    network: Option<Res<NetworkSettings>>,
+    mut clocks: ResMut<GameClocks>,
+    stopwatch: Res<MoveStopwatch>,
+) {
+    if board.is_over() {
+        return;
+    }
+
+    // Checked first, so the clocks dont count as changed every frame
+    let to_move = board.to_move();
+    let runs_out = clocks
+        .0
+        .as_ref()
+        .is_some_and(|clocks| clocks.runs_out(to_move, stopwatch.elapsed()));
+    if runs_out && !is_guest(&network) {
+        if let Some(clocks) = clocks.0.as_mut() {
+            clocks.flag(to_move);
+        }
+    }
+
+    let Some(side) = clocks.0.as_ref().and_then(Clocks::flagged) else {
+        return;
+    };
+    info!("Player {} ran out of time", side.index() + 1);
+    board.forfeit(side, ForfeitReason::Time);
+}
+
+fn spawn_clocks(mut command: Commands) {
+    command
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(5.0),
+                    bottom: Val::Px(5.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(5.0),
+                    padding: UiRect::all(Val::Px(5.0)),
+                    ..default()
+                },
+                background_color: CLOCK_COLOUR.into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ClockPanel,
+        ))
+        .with_children(|parent| {
+            for side in [Side::First, Side::Second] {
+                parent.spawn((
+                    TextBundle::from_section("", TextStyle::default()).with_style(Style {
+                        padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                        ..default()
+                    }),
+                    ClockText(side),
+                ));
+            }
+        });
+}
+
+// Minutes and seconds, eg "4:05", and tenths of a second too once time is short, eg "0:09.4"
+fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if time < LOW_TIME {
+        format!("0:{seconds:02}.{}", time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn update_clock_texts(
+    clocks: Res<GameClocks>,
+    stopwatch: Res<MoveStopwatch>,
+    board: Res<Board>,
+    app_state: Res<State<AppState>>,
+    players: Query<&Player>,
+    mut panels: Query<&mut Visibility, With<ClockPanel>>,
+    mut texts: Query<(&ClockText, &mut Text, &mut BackgroundColor)>,
+) {
+    let playing = matches!(
+        app_state.get(),
+        AppState::InGame | AppState::Paused | AppState::GameOver
+    );
+    let shown = clocks.0.as_ref().filter(|_| playing);
+
+    let visibility = match shown {
+        Some(_) => Visibility::Inherited,
+        None => Visibility::Hidden,
+    };
+    for mut panel in &mut panels {
+        if *panel != visibility {
+            *panel = visibility;
+        }
+    }
+
+    let Some(clocks) = shown else {
+        return;
+    };
+
+    let to_move = board.to_move();
+    for (&ClockText(side), mut text, mut background) in &mut texts {
+        let num = PlayerNum::from(side);
+        let Some(player) = players.iter().find(|p| p.num == num) else {
+            continue;
+        };
+
+        let left = clocks.shown(side, to_move, stopwatch.elapsed());
+        let value = format!("{} {}", player.name, format_clock(left));
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+
+        let colour = if left < LOW_TIME {
+            LOW_TIME_COLOUR
+        } else {
+            Color::WHITE
+        };
+        if text.sections[0].style.color != colour {
+            text.sections[0].style.color = colour;
+        }
+
+        let highlight = if side == to_move && !board.is_over() {
+            TO_MOVE_COLOUR
+        } else {
+            Color::NONE
+        };
+        if background.0 != highlight {
+            background.0 = highlight;
+        }
+    }
+}
@@ -0,0 +1,375 @@
+// Chess clocks, and the different ways of handing out time, from `--time-control`
+// Like the rules, this doesnt know anything about bevy, so the server can keep time with it too
+// (see `chess_clock` for the clocks on the screen)
+
+use crate::rules::Side;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// The longest any time in a time control can be, which is plenty for a game of connect 4
+// (and keeps the clocks small enough to send as milliseconds)
+const MAX_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeControl {
+    // One lot of time for the whole game
+    SuddenDeath { time: Duration },
+    // With `increment` added on after every move
+    Fischer { time: Duration, increment: Duration },
+    // Up to `delay` of every move is given back after it, so quick moves dont cost anything
+    Bronstein { time: Duration, delay: Duration },
+    // Every move has to be made within `time`, and whatever isnt used is lost
+    PerMove { time: Duration },
+}
+
+impl TimeControl {
+    // What the setup screen goes through, in order
+    pub const PRESETS: [TimeControl; 4] = [
+        TimeControl::SuddenDeath {
+            time: Duration::from_secs(5 * 60),
+        },
+        TimeControl::Fischer {
+            time: Duration::from_secs(3 * 60),
+            increment: Duration::from_secs(2),
+        },
+        TimeControl::Bronstein {
+            time: Duration::from_secs(5 * 60),
+            delay: Duration::from_secs(3),
+        },
+        TimeControl::PerMove {
+            time: Duration::from_secs(15),
+        },
+    ];
+
+    pub fn starting_time(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { time }
+            | TimeControl::Fischer { time, .. }
+            | TimeControl::Bronstein { time, .. }
+            | TimeControl::PerMove { time } => time,
+        }
+    }
+
+    // For the setup screen, eg "3 min + 2 s"
+    pub fn title(&self) -> String {
+        match *self {
+            TimeControl::SuddenDeath { time } => describe_duration(time),
+            TimeControl::Fischer { time, increment } => {
+                format!(
+                    "{} + {}",
+                    describe_duration(time),
+                    describe_duration(increment)
+                )
+            }
+            TimeControl::Bronstein { time, delay } => {
+                format!(
+                    "{}, {} delay",
+                    describe_duration(time),
+                    describe_duration(delay)
+                )
+            }
+            TimeControl::PerMove { time } => format!("{} a move", describe_duration(time)),
+        }
+    }
+}
+
+fn describe_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 60 && seconds % 60 == 0 {
+        format!("{} min", seconds / 60)
+    } else {
+        format!("{seconds} s")
+    }
+}
+
+// Whole minutes or seconds, eg "5m" or "90s" (or just "90")
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, unit) = match text.strip_suffix('m') {
+        Some(minutes) => (minutes, 60),
+        None => (text.strip_suffix('s').unwrap_or(text), 1),
+    };
+
+    let number = number
+        .parse::<u64>()
+        .map_err(|error| format!("invalid time '{text}': {error}"))?;
+
+    match number.checked_mul(unit).map(Duration::from_secs) {
+        Some(time) if time <= MAX_TIME => Ok(time),
+        _ => Err(format!("invalid time '{text}': it can be 24 hours at most")),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 60 && seconds % 60 == 0 {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+// Eg "sudden:5m", "fischer:3m+2s", "bronstein:5m+3s" or "move:15s"
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let Some((kind, times)) = s.split_once(':') else {
+            return Err(format!(
+                "unknown time control '{s}', expected sudden:<time>, fischer:<time>+<increment>, \
+                 bronstein:<time>+<delay> or move:<time>"
+            ));
+        };
+
+        let (time, extra) = match times.split_once('+') {
+            Some((time, extra)) => (parse_duration(time)?, Some(parse_duration(extra)?)),
+            None => (parse_duration(times)?, None),
+        };
+
+        if time.is_zero() {
+            return Err("a time control needs some time in it".into());
+        }
+
+        match (kind, extra) {
+            ("sudden", None) => Ok(TimeControl::SuddenDeath { time }),
+            ("fischer", Some(increment)) => Ok(TimeControl::Fischer { time, increment }),
+            ("bronstein", Some(delay)) => Ok(TimeControl::Bronstein { time, delay }),
+            ("move", None) => Ok(TimeControl::PerMove { time }),
+            ("fischer", None) => Err("fischer needs an increment, eg fischer:3m+2s".into()),
+            ("bronstein", None) => Err("bronstein needs a delay, eg bronstein:5m+3s".into()),
+            ("sudden" | "move", Some(_)) => Err(format!("{kind} cant have anything added on")),
+            _ => Err(format!(
+                "unknown time control '{kind}', expected sudden, fischer, bronstein or move"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TimeControl::SuddenDeath { time } => write!(f, "sudden:{}", format_duration(time)),
+            TimeControl::Fischer { time, increment } => write!(
+                f,
+                "fischer:{}+{}",
+                format_duration(time),
+                format_duration(increment)
+            ),
+            TimeControl::Bronstein { time, delay } => write!(
+                f,
+                "bronstein:{}+{}",
+                format_duration(time),
+                format_duration(delay)
+            ),
+            TimeControl::PerMove { time } => write!(f, "move:{}", format_duration(time)),
+        }
+    }
+}
+
+// Both players clocks
+// How long the player to move has been thinking is kept by whoever uses these, since only they know
+// when the clock should be running
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Clocks {
+    control: TimeControl,
+    // What each side had left when their current (or next) move started, player 1 first
+    remaining: [Duration; 2],
+    // Whoever ran out of time, and lost because of it
+    flagged: Option<Side>,
+}
+
+impl Clocks {
+    pub fn new(control: TimeControl) -> Self {
+        Clocks {
+            control,
+            remaining: [control.starting_time(); 2],
+            flagged: None,
+        }
+    }
+
+    // The clocks as someone else has them, eg the host of a network game
+    pub fn from_parts(
+        control: TimeControl,
+        remaining: [Duration; 2],
+        flagged: Option<Side>,
+    ) -> Self {
+        Clocks {
+            control,
+            remaining,
+            flagged,
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn remaining(&self) -> [Duration; 2] {
+        self.remaining
+    }
+
+    pub fn flagged(&self) -> Option<Side> {
+        self.flagged
+    }
+
+    // What a clock shows, which is counting down for whoever is to move
+    pub fn shown(&self, side: Side, to_move: Side, used: Duration) -> Duration {
+        let remaining = self.remaining[side.index()];
+        if side == to_move {
+            remaining.saturating_sub(used)
+        } else {
+            remaining
+        }
+    }
+
+    pub fn runs_out(&self, to_move: Side, used: Duration) -> bool {
+        self.flagged.is_none() && self.shown(to_move, to_move, used).is_zero()
+    }
+
+    pub fn flag(&mut self, side: Side) {
+        self.flagged = Some(side);
+    }
+
+    // Stops `mover`s clock after a move that took `used`, and gives them whatever the time control adds on
+    pub fn finish_move(&mut self, mover: Side, used: Duration) {
+        let remaining = &mut self.remaining[mover.index()];
+        let left = remaining.saturating_sub(used);
+
+        *remaining = match self.control {
+            TimeControl::SuddenDeath { .. } => left,
+            // Saturating, since the times can be as long as anyone likes
+            TimeControl::Fischer { increment, .. } => left.saturating_add(increment),
+            TimeControl::Bronstein { delay, .. } => left.saturating_add(used.min(delay)),
+            TimeControl::PerMove { time } => time,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn time_controls_round_trip() {
+        let cases = [
+            ("sudden:5m", TimeControl::SuddenDeath { time: secs(300) }),
+            (
+                "fischer:3m+2s",
+                TimeControl::Fischer {
+                    time: secs(180),
+                    increment: secs(2),
+                },
+            ),
+            (
+                "bronstein:90s+3s",
+                TimeControl::Bronstein {
+                    time: secs(90),
+                    delay: secs(3),
+                },
+            ),
+            ("move:15s", TimeControl::PerMove { time: secs(15) }),
+        ];
+
+        for (text, control) in cases {
+            assert_eq!(text.parse(), Ok(control));
+            assert_eq!(control.to_string(), text);
+        }
+
+        for control in TimeControl::PRESETS {
+            assert_eq!(control.to_string().parse(), Ok(control));
+        }
+
+        // Seconds dont need their s, and get shown as minutes when they can be
+        assert_eq!(
+            " Sudden:120 ".parse::<TimeControl>().map(|c| c.to_string()),
+            Ok("sudden:2m".into())
+        );
+    }
+
+    #[test]
+    fn bad_time_controls_are_turned_down() {
+        for text in [
+            "",
+            "5m",
+            "sudden:",
+            "sudden:0",
+            "sudden:5h",
+            "sudden:5m+2s",
+            "fischer:3m",
+            "bronstein:5m",
+            "blitz:5m",
+            "sudden:999999999999999999m",
+            "sudden:99999999999999999s",
+            "sudden:1441m",
+            "fischer:1m+86401s",
+            "fischer:1m+99999999999999999999s",
+        ] {
+            assert!(text.parse::<TimeControl>().is_err(), "{text}");
+        }
+
+        assert_eq!(
+            "fischer:1440m+86400s".parse(),
+            Ok(TimeControl::Fischer {
+                time: MAX_TIME,
+                increment: MAX_TIME
+            })
+        );
+    }
+
+    #[test]
+    fn sudden_death_only_counts_down() {
+        let mut clocks = Clocks::new(TimeControl::SuddenDeath { time: secs(60) });
+        clocks.finish_move(Side::First, secs(10));
+        assert_eq!(clocks.remaining(), [secs(50), secs(60)]);
+
+        assert_eq!(clocks.shown(Side::Second, Side::Second, secs(15)), secs(45));
+        assert_eq!(clocks.shown(Side::First, Side::Second, secs(15)), secs(50));
+        assert!(!clocks.runs_out(Side::Second, secs(59)));
+        assert!(clocks.runs_out(Side::Second, secs(60)));
+
+        clocks.flag(Side::Second);
+        assert_eq!(clocks.flagged(), Some(Side::Second));
+        assert!(!clocks.runs_out(Side::Second, secs(60)));
+    }
+
+    #[test]
+    fn fischer_adds_the_increment() {
+        let mut clocks = Clocks::new(TimeControl::Fischer {
+            time: secs(60),
+            increment: secs(2),
+        });
+        clocks.finish_move(Side::First, secs(10));
+        clocks.finish_move(Side::Second, secs(1));
+        assert_eq!(clocks.remaining(), [secs(52), secs(61)]);
+    }
+
+    #[test]
+    fn bronstein_gives_back_up_to_the_delay() {
+        let mut clocks = Clocks::new(TimeControl::Bronstein {
+            time: secs(60),
+            delay: secs(3),
+        });
+
+        // Under the delay costs nothing
+        clocks.finish_move(Side::First, secs(2));
+        assert_eq!(clocks.remaining(), [secs(60), secs(60)]);
+
+        // And over it only costs what was over
+        clocks.finish_move(Side::Second, secs(10));
+        assert_eq!(clocks.remaining(), [secs(60), secs(53)]);
+    }
+
+    #[test]
+    fn per_move_starts_again_every_move() {
+        let mut clocks = Clocks::new(TimeControl::PerMove { time: secs(15) });
+        clocks.finish_move(Side::First, secs(14));
+        assert_eq!(clocks.remaining(), [secs(15), secs(15)]);
+        assert!(clocks.runs_out(Side::Second, secs(15)));
+    }
+}
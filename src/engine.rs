@@ -0,0 +1,331 @@
+// A simple text protocol for bots, so they can be written in any language and still play
+// Loosely based on UCI from chess, one line at a time over the engine's stdin and stdout
+//
+// Sent to the engine:
+//  - `isready`: answered with `readyok` once the engine can take commands
+//  - `position [rules <name>] [board <columns> <rows> <connect>] moves <moves>`: the game so far,
+//    with the moves written like `notation` does them (and nothing after `moves` at the start of a game)
+//    Anything left out is the same as a normal game, so `position moves 4453` is standard rules on a 7x6 board
+//  - `go movetime <milliseconds>`: think for about that long, then answer `bestmove <move>`, eg `bestmove 3`
+//  - `quit`: stop straight away
+//
+// Lines from the engine starting with `info` are ignored, so they can be used for logging
+// The `connect4-engine` binary is the built in AI, speaking this protocol
+
+use crate::notation::{move_notation, parse_move, Game};
+use crate::rules::*;
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MOVETIME: Duration = Duration::from_millis(1000);
+
+// Engines can take a while to load things like opening books
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+// Extra time on top of the move time, for sending things back and forth
+const GRACE_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    IsReady,
+    Position {
+        rules: Rules,
+        config: BoardConfig,
+        moves: String,
+    },
+    Go {
+        movetime: Duration,
+    },
+    Quit,
+}
+
+impl Command {
+    pub fn position(game: &Game) -> Command {
+        let Some(moves) = game.to_notation() else {
+            panic!("Tried to send a game to an engine without knowing all of its moves")
+        };
+
+        Command::Position {
+            rules: game.rules(),
+            config: *game.config(),
+            moves,
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::IsReady => write!(f, "isready"),
+            Command::Position {
+                rules,
+                config,
+                moves,
+            } => {
+                write!(
+                    f,
+                    "position rules {rules} board {} {} {} moves",
+                    config.columns, config.rows, config.connect
+                )?;
+                if !moves.is_empty() {
+                    write!(f, " {moves}")?;
+                }
+                Ok(())
+            }
+            Command::Go { movetime } => write!(f, "go movetime {}", movetime.as_millis()),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let number = |word: Option<&str>, what: &str| -> Result<usize, String> {
+            let Some(word) = word else {
+                return Err(format!("{what} needs a number"));
+            };
+            word.parse()
+                .map_err(|error| format!("invalid {what} '{word}': {error}"))
+        };
+
+        match words.next() {
+            Some("isready") => Ok(Command::IsReady),
+            Some("quit") => Ok(Command::Quit),
+            Some("go") => match words.next() {
+                Some("movetime") => Ok(Command::Go {
+                    movetime: Duration::from_millis(number(words.next(), "movetime")? as u64),
+                }),
+                Some(other) => Err(format!("unknown go option '{other}'")),
+                None => Err("go needs a movetime".into()),
+            },
+            Some("position") => {
+                let mut rules = Rules::Standard;
+                let mut config = BoardConfig::STANDARD;
+                let mut moves = None;
+
+                while let Some(word) = words.next() {
+                    match word {
+                        "rules" => match words.next() {
+                            Some(name) => rules = name.parse()?,
+                            None => return Err("rules needs a name".into()),
+                        },
+                        "board" => {
+                            let columns = number(words.next(), "columns")?;
+                            let rows = number(words.next(), "rows")?;
+                            let connect = number(words.next(), "connect")?;
+                            config = BoardConfig::new(rows, columns, connect)
+                                .map_err(|error| error.to_string())?;
+                        }
+                        // The moves are always last, and can be missing at the start of a game
+                        "moves" => {
+                            moves = Some(words.next().unwrap_or_default().to_string());
+                            break;
+                        }
+                        other => return Err(format!("unknown position option '{other}'")),
+                    }
+                }
+
+                match moves {
+                    Some(moves) => Ok(Command::Position {
+                        rules,
+                        config,
+                        moves,
+                    }),
+                    None => Err("position needs moves, eg position moves 4453".into()),
+                }
+            }
+            Some(other) => Err(format!("unknown command '{other}'")),
+            None => Err("empty command".into()),
+        }
+    }
+}
+
+// Everything that makes an engine lose the game
+#[derive(Debug)]
+pub enum EngineError {
+    NoCommand,
+    Spawn(std::io::Error),
+    // Writing to it failed, usually because it has already stopped
+    Io(std::io::Error),
+    Exited,
+    Timeout(Duration),
+    BadReply(String),
+    IllegalMove(Move, MoveError),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::NoCommand => write!(f, "no engine command was given"),
+            EngineError::Spawn(error) => write!(f, "the engine could not be started: {error}"),
+            EngineError::Io(error) => write!(f, "could not talk to the engine: {error}"),
+            EngineError::Exited => write!(f, "the engine stopped running"),
+            EngineError::Timeout(timeout) => {
+                write!(
+                    f,
+                    "the engine didnt answer within {}ms",
+                    timeout.as_millis()
+                )
+            }
+            EngineError::BadReply(reply) => {
+                write!(f, "the engine sent '{reply}', which makes no sense")
+            }
+            EngineError::IllegalMove(next, error) => write!(
+                f,
+                "the engine played {}, which isnt allowed: {error}",
+                move_notation(*next)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+// An engine running in another process
+// It gets told to quit (and killed, to be sure) when this is dropped
+pub struct EngineProcess {
+    child: Child,
+    stdin: ChildStdin,
+    // Read on another thread, so waiting for an answer can time out
+    replies: Receiver<String>,
+}
+
+impl EngineProcess {
+    // Starts the engine, and waits until it says its ready
+    // `command` is the program then its arguments, split on spaces
+    pub fn start(command: &str) -> Result<Self, EngineError> {
+        let mut words = command.split_whitespace();
+        let Some(program) = words.next() else {
+            return Err(EngineError::NoCommand);
+        };
+
+        let mut child = std::process::Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(EngineError::Spawn)?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            panic!("Tried to talk to an engine without piping its stdin and stdout")
+        };
+
+        // Stops by itself once the engine closes its stdout
+        let (sender, replies) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = EngineProcess {
+            child,
+            stdin,
+            replies,
+        };
+
+        engine.send(&Command::IsReady)?;
+        match engine.read_reply(STARTUP_TIMEOUT)? {
+            reply if reply == "readyok" => Ok(engine),
+            reply => Err(EngineError::BadReply(reply)),
+        }
+    }
+
+    fn send(&mut self, command: &Command) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(EngineError::Io)
+    }
+
+    // The next line that isnt just info
+    fn read_reply(&mut self, timeout: Duration) -> Result<String, EngineError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(left) {
+                Ok(line) if line.trim().is_empty() || line.starts_with("info") => continue,
+                Ok(line) => return Ok(line.trim().to_string()),
+                Err(RecvTimeoutError::Timeout) => return Err(EngineError::Timeout(timeout)),
+                Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Exited),
+            }
+        }
+    }
+
+    // Asks for a move, and checks the rules allow it
+    pub fn best_move(&mut self, game: &Game, movetime: Duration) -> Result<Move, EngineError> {
+        self.send(&Command::position(game))?;
+        self.send(&Command::Go { movetime })?;
+
+        let reply = self.read_reply(movetime + GRACE_TIME)?;
+        let Some(next) = reply
+            .strip_prefix("bestmove ")
+            .and_then(|text| parse_move(text).ok())
+        else {
+            return Err(EngineError::BadReply(reply));
+        };
+
+        let mut position = *game.position();
+        match position.play_move(next) {
+            Ok(_) => Ok(next),
+            Err(error) => Err(EngineError::IllegalMove(next, error)),
+        }
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        // It might already be gone, which is fine
+        let _ = self.send(&Command::Quit);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// How long engines get for each move, from `--movetime <milliseconds>`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EngineSettings {
+    pub movetime: Duration,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        EngineSettings {
+            movetime: DEFAULT_MOVETIME,
+        }
+    }
+}
+
+impl EngineSettings {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--movetime") else {
+            return Ok(EngineSettings::default());
+        };
+
+        match args
+            .get(index + 1)
+            .map(|value| (value, value.parse::<u64>()))
+        {
+            Some((_, Ok(millis))) if millis > 0 => Ok(EngineSettings {
+                movetime: Duration::from_millis(millis),
+            }),
+            Some((value, _)) => Err(format!(
+                "invalid movetime '{value}', it has to be a whole number of milliseconds above 0"
+            )),
+            None => Err("--movetime needs a number of milliseconds after it, eg 500".into()),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::animation::{is_animating, FallingChip};
+use crate::board::{AppState, Board, PlayMoveEvent};
+use crate::engine::{EngineError, EngineProcess, EngineSettings};
+use crate::notation::{ForfeitReason, Game};
+use crate::players::{Player, PlayerKind, PlayerNum};
+use crate::rules::{Move, Side};
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+
+impl Resource for EngineSettings {}
+
+// Players that are other programs, see `engine` for how they are talked to
+pub fn plugin_engine_player(app: &mut App) {
+    app.init_resource::<EngineSettings>();
+    app.init_resource::<EnginePlayers>();
+
+    app.add_systems(
+        Update,
+        update_engine_move.run_if(in_state(AppState::InGame)),
+    );
+}
+
+// An engine working out its move, off on another thread so the game doesnt freeze
+struct Thinking {
+    side: Side,
+    command: String,
+    // What the game was when it started, so a move for an old position can be thrown away
+    notation: String,
+    task: Task<(Option<EngineProcess>, Result<Move, EngineError>)>,
+}
+
+#[derive(Resource, Default)]
+struct EnginePlayers {
+    // Kept running between moves so they only have to start up once, along with the command that started them
+    // Each one is taken out while its thinking
+    running: [Option<(String, EngineProcess)>; 2],
+    thinking: Option<Thinking>,
+    // A move has been sent, but the board hasnt played it yet
+    move_sent: bool,
+}
+
+fn side_index(side: Side) -> usize {
+    match side {
+        Side::First => 0,
+        Side::Second => 1,
+    }
+}
+
+fn update_engine_move(
+    mut board: ResMut<Board>,
+    players: Query<&Player>,
+    settings: Res<EngineSettings>,
+    falling: Query<(), With<FallingChip>>,
+    mut engines: ResMut<EnginePlayers>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
+) {
+    if let Some(thinking) = &mut engines.thinking {
+        let Some((engine, result)) = block_on(future::poll_once(&mut thinking.task)) else {
+            return;
+        };
+
+        let Some(thinking) = engines.thinking.take() else {
+            return;
+        };
+        engines.running[side_index(thinking.side)] =
+            engine.map(|engine| (thinking.command, engine));
+
+        // Undoing, loading or starting a new game while it was thinking makes the move useless
+        if board.is_over() || board.to_notation().as_ref() != Some(&thinking.notation) {
+            return;
+        }
+
+        match result {
+            Ok(played) => {
+                play_move_event.send(PlayMoveEvent { played });
+                engines.move_sent = true;
+            }
+            // A broken engine cant carry on, but the game can
+            Err(error) => {
+                error!(
+                    "Player {} forfeits the game: {error}",
+                    side_index(thinking.side) + 1
+                );
+                board.forfeit(thinking.side, ForfeitReason::Engine);
+            }
+        }
+        return;
+    }
+
+    // Otherwise it would start thinking about the same position again
+    if engines.move_sent {
+        if !board.is_changed() {
+            return;
+        }
+        engines.move_sent = false;
+    }
+
+    if board.is_over() || is_animating(&falling) {
+        return;
+    }
+
+    // Go off the board rather than the current player, since the board is never out of date
+    let side = board.to_move();
+    let num: PlayerNum = side.into();
+    let Some(player) = players.iter().find(|p| p.num == num) else {
+        return;
+    };
+
+    let PlayerKind::Engine { command } = &player.kind else {
+        return;
+    };
+
+    // Engines never get to see a game they dont know all the moves of, like one loaded without them
+    let Some(notation) = board.to_notation() else {
+        error!(
+            "Player {} forfeits the game: the engine cant be told the moves so far",
+            side_index(side) + 1
+        );
+        board.forfeit(side, ForfeitReason::Engine);
+        return;
+    };
+
+    // The player could have changed to a different engine since last time
+    let engine = match engines.running[side_index(side)].take() {
+        Some((running, engine)) if running == *command => Some(engine),
+        _ => None,
+    };
+
+    let game: Game = board.0.clone();
+    let movetime = settings.movetime;
+    let engine_command = command.clone();
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let mut engine = match engine {
+            Some(engine) => engine,
+            None => match EngineProcess::start(&engine_command) {
+                Ok(engine) => engine,
+                Err(error) => return (None, Err(error)),
+            },
+        };
+
+        let result = engine.best_move(&game, movetime);
+        // One that went wrong isnt any use for the next move either
+        (result.is_ok().then_some(engine), result)
+    });
+
+    engines.thinking = Some(Thinking {
+        side,
+        command: command.clone(),
+        notation,
+        task,
+    });
+}
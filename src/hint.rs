@@ -0,0 +1,245 @@
+// Help for anyone learning the game: press H to light up the best column, and E to show what every
+// column leads to with perfect play (win in N, loss in N or a draw, for whoever is to move)
+// The solver only knows the standard rules on boards it can fit, so other games only get the hint,
+// from the AI looking ahead a few moves. Either way the thinking happens on another thread, so the
+// game carries on as normal while it does
+// Network games dont get any help at all, since that would be cheating
+
+use crate::ai::{best_move_with_book, center_first_order};
+use crate::ai_player::{LoadedBook, SharedSolver};
+use crate::board::{board_size, hole_position, AppState, Board, HintedColumn};
+use crate::book::OpeningBook;
+use crate::network::NetworkSettings;
+use crate::notation::move_notation;
+use crate::rules::{BoardConfig, Move, Position};
+use crate::solver::{Evaluation, Solver};
+
+use bevy::color::palettes::css::{LIGHT_GREEN, SALMON};
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, Task};
+
+const HINT_KEY: KeyCode = KeyCode::KeyH;
+const EVALUATIONS_KEY: KeyCode = KeyCode::KeyE;
+// How far ahead the AI looks for a hint when the solver cant help
+const HINT_DEPTH: u32 = 8;
+const EVALUATION_FONT_SIZE: f32 = 14.;
+
+#[derive(Resource, Default, Clone, Debug)]
+pub struct HintSettings {
+    pub show_evaluations: bool,
+}
+
+// What the solver (or the AI) made of a position
+#[derive(Clone, Debug)]
+struct Thoughts {
+    position: Position,
+    best: Option<Move>,
+    // What playing in each column leads to, None for a full one. Empty when the solver couldnt help
+    columns: Vec<Option<Evaluation>>,
+}
+
+#[derive(Resource, Default)]
+struct Hints {
+    thinking: Option<Task<Thoughts>>,
+    thoughts: Option<Thoughts>,
+    // The position H was pressed on, so the hint goes away once a move is played
+    asked_for: Option<Position>,
+}
+
+impl Hints {
+    fn thoughts_on(&self, position: &Position) -> Option<&Thoughts> {
+        self.thoughts
+            .as_ref()
+            .filter(|thoughts| thoughts.position == *position)
+    }
+}
+
+#[derive(Component)]
+struct EvaluationText {
+    col: usize,
+}
+
+pub fn plugin_hint(app: &mut App) {
+    app.init_resource::<HintSettings>();
+    app.init_resource::<Hints>();
+
+    app.add_systems(
+        Update,
+        (
+            update_hint_keys.run_if(in_state(AppState::InGame)),
+            update_hint_thinking,
+            update_hinted_column,
+            spawn_evaluation_texts.run_if(resource_changed::<BoardConfig>),
+            update_evaluation_texts,
+        )
+            .chain()
+            .run_if(not(resource_exists::<NetworkSettings>)),
+    );
+}
+
+fn update_hint_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    board: Res<Board>,
+    mut settings: ResMut<HintSettings>,
+    mut hints: ResMut<Hints>,
+) {
+    if keys.just_pressed(HINT_KEY) && !board.is_over() {
+        hints.asked_for = Some(*board.position());
+    }
+
+    if keys.just_pressed(EVALUATIONS_KEY) {
+        settings.show_evaluations = !settings.show_evaluations;
+    }
+}
+
+// Works out the best move, and how every column turns out if the solver knows
+fn think(solver: &mut Solver, position: Position, book: Option<&OpeningBook>) -> Thoughts {
+    if !Solver::can_solve(&position) {
+        return Thoughts {
+            position,
+            best: best_move_with_book(&position, HINT_DEPTH, book),
+            columns: Vec::new(),
+        };
+    }
+
+    let config = position.config();
+    let scores = solver.analyze(&position);
+
+    // The best score, going for the middle when its a tie (the same as `Solver::best_move`)
+    let mut best: Option<(usize, i32)> = None;
+    for col in center_first_order(config) {
+        if let Some(score) = scores[col] {
+            if best.map_or(true, |(_, best_score)| score > best_score) {
+                best = Some((col, score));
+            }
+        }
+    }
+
+    Thoughts {
+        position,
+        best: best.map(|(col, _)| Move::Drop(col)),
+        columns: scores
+            .into_iter()
+            .map(|score| {
+                score.map(|score| Evaluation::from_score(score, config, position.moves_played()))
+            })
+            .collect(),
+    }
+}
+
+// Only ever thinks about one position at a time. One that is out of date by the time its done is thrown
+// away, and the current one gets thought about instead
+fn update_hint_thinking(
+    board: Res<Board>,
+    settings: Res<HintSettings>,
+    book: Res<LoadedBook>,
+    solver: Res<SharedSolver>,
+    mut hints: ResMut<Hints>,
+) {
+    if let Some(thinking) = &mut hints.thinking {
+        let Some(thoughts) = block_on(future::poll_once(thinking)) else {
+            return;
+        };
+
+        hints.thinking = None;
+        hints.thoughts = Some(thoughts);
+    }
+
+    let position = *board.position();
+    let wanted = settings.show_evaluations || hints.asked_for == Some(position);
+    if !wanted || position.is_over() || hints.thoughts_on(&position).is_some() {
+        return;
+    }
+
+    hints.thinking = Some(solver.spawn(&position, &book, move |solver, book| {
+        think(solver, position, book)
+    }));
+}
+
+fn update_hinted_column(board: Res<Board>, hints: Res<Hints>, mut hinted: ResMut<HintedColumn>) {
+    let position = board.position();
+
+    let best = match hints.thoughts_on(position) {
+        Some(thoughts) if hints.asked_for == Some(*position) => thoughts.best,
+        _ => None,
+    };
+
+    let col = best.map(|best| best.col());
+    if **hinted != col {
+        // Pops, specials and placing chips cant be shown by just lighting up a column
+        if let Some(best) = best {
+            info!("Hint: {}", move_notation(best));
+        }
+        **hinted = col;
+    }
+}
+
+// Just above each column, and thrown away again when the board changes size
+fn spawn_evaluation_texts(
+    mut command: Commands,
+    config: Res<BoardConfig>,
+    texts: Query<Entity, With<EvaluationText>>,
+) {
+    for text in &texts {
+        command.entity(text).despawn();
+    }
+
+    let y = board_size(&config).y + EVALUATION_FONT_SIZE;
+    for col in 0..config.columns {
+        let x = hole_position(0, col).x;
+
+        command.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font_size: EVALUATION_FONT_SIZE,
+                        ..default()
+                    },
+                )
+                .with_justify(JustifyText::Center),
+                transform: Transform::from_xyz(x, y, 1.),
+                ..default()
+            },
+            EvaluationText { col },
+        ));
+    }
+}
+
+fn evaluation_label(evaluation: Option<Evaluation>) -> (String, Color) {
+    match evaluation {
+        Some(Evaluation::Win { moves }) => (format!("Win {moves}"), LIGHT_GREEN.into()),
+        Some(Evaluation::Loss { moves }) => (format!("Loss {moves}"), SALMON.into()),
+        Some(Evaluation::Draw { .. }) => ("Draw".to_string(), Color::WHITE),
+        // A full column
+        None => (String::new(), Color::WHITE),
+    }
+}
+
+fn update_evaluation_texts(
+    board: Res<Board>,
+    settings: Res<HintSettings>,
+    hints: Res<Hints>,
+    mut texts: Query<(&EvaluationText, &mut Text)>,
+) {
+    let position = board.position();
+    let thoughts = hints.thoughts_on(position);
+
+    for (evaluation_text, mut text) in &mut texts {
+        let (label, colour) = match thoughts {
+            _ if !settings.show_evaluations || position.is_over() => (String::new(), Color::WHITE),
+            Some(thoughts) => {
+                evaluation_label(thoughts.columns.get(evaluation_text.col).copied().flatten())
+            }
+            // Still thinking about it
+            None if Solver::can_solve(position) => ("...".to_string(), Color::WHITE),
+            None => (String::new(), Color::WHITE),
+        };
+
+        let section = &mut text.sections[0];
+        if section.value != label || section.style.color != colour {
+            section.value = label;
+            section.style.color = colour;
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::animation::{is_animating, FallingChip};
+use crate::board::{AppState, Board};
+use crate::chess_clock::GameClocks;
+use crate::network::NetworkSettings;
+use crate::notation::Game;
+use crate::players::{CurrentPlayer, Player, PlayerKind, PlayerNum};
+use crate::rules::Move;
+
+use bevy::prelude::*;
+
+// Every move that has been played, in order, and the ones that were undone (so they can be redone)
+#[derive(Resource, Default, Clone, Debug)]
+pub struct MoveHistory {
+    pub played: Vec<PlayedMove>,
+    undone: Vec<PlayedMove>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PlayedMove {
+    pub played: Move,
+    // Where the chip landed, or 0 for a pop
+    pub row: usize,
+    pub player: PlayerNum,
+}
+
+impl MoveHistory {
+    pub fn from_game(game: &Game) -> Self {
+        let mut replay = Game::with_rules(*game.config(), game.rules());
+        let mut played = Vec::new();
+
+        for &next in game.moves() {
+            let player = replay.to_move().into();
+            let Ok(outcome) = replay.play_move(next) else {
+                panic!("Tried to make a history out of a game with an illegal move")
+            };
+
+            played.push(PlayedMove {
+                played: next,
+                row: outcome.row(),
+                player,
+            });
+        }
+
+        MoveHistory {
+            played,
+            undone: Vec::new(),
+        }
+    }
+
+    // A new move means the undone ones cant be redone any more
+    pub fn push(&mut self, played: PlayedMove) {
+        self.played.push(played);
+        self.undone.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.played.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+}
+
+#[derive(Event, Default)]
+pub struct UndoEvent;
+
+#[derive(Event, Default)]
+pub struct RedoEvent;
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum HistoryButton {
+    Undo,
+    Redo,
+}
+
+pub fn plugin_history(app: &mut App) {
+    app.add_event::<UndoEvent>();
+    app.add_event::<RedoEvent>();
+
+    app.add_systems(Startup, create_move_history);
+    app.add_systems(Startup, spawn_history_buttons);
+
+    // Undoing the winning move is fine, but not from the menus
+    app.add_systems(
+        Update,
+        update_history_keys
+            .run_if(in_state(AppState::InGame).or_else(in_state(AppState::GameOver))),
+    );
+    app.add_systems(Update, update_history_buttons);
+    app.add_systems(Update, update_history_button_colour);
+    // Both sides of a network game have to agree on every move, so nothing can be taken back,
+    // and a replay moves the board on by itself
+    // Taking moves back cant give back the time they took either, so games against the clock cant do it
+    app.add_systems(
+        Update,
+        update_undo_redo
+            .run_if(not(resource_exists::<NetworkSettings>))
+            .run_if(has_no_clocks)
+            .run_if(not(in_state(AppState::Replay))),
+    );
+}
+
+fn has_no_clocks(clocks: Res<GameClocks>) -> bool {
+    clocks.is_none()
+}
+
+fn create_move_history(mut command: Commands, board: Res<Board>) {
+    command.insert_resource(MoveHistory::from_game(&board));
+}
+
+fn spawn_history_buttons(mut command: Commands) {
+    command
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(5.0),
+                top: Val::Px(5.0),
+                column_gap: Val::Px(5.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for (button, label) in [(HistoryButton::Undo, "Undo"), (HistoryButton::Redo, "Redo")] {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                ..default()
+                            },
+                            background_color: Color::BLACK.into(),
+                            ..default()
+                        },
+                        button,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(label, TextStyle::default()));
+                    });
+            }
+        });
+}
+
+// Ctrl+Z to undo, Ctrl+Y or Ctrl+Shift+Z to redo
+fn update_history_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut undo_event: EventWriter<UndoEvent>,
+    mut redo_event: EventWriter<RedoEvent>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if keys.just_pressed(KeyCode::KeyY) || (shift && keys.just_pressed(KeyCode::KeyZ)) {
+        redo_event.send_default();
+    } else if keys.just_pressed(KeyCode::KeyZ) {
+        undo_event.send_default();
+    }
+}
+
+fn update_history_buttons(
+    buttons: Query<(&Interaction, &HistoryButton), Changed<Interaction>>,
+    mut undo_event: EventWriter<UndoEvent>,
+    mut redo_event: EventWriter<RedoEvent>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            HistoryButton::Undo => undo_event.send_default(),
+            HistoryButton::Redo => redo_event.send_default(),
+        };
+    }
+}
+
+// Buttons that cant do anything are greyed out, which is all of them in a network game or against the clock
+fn update_history_button_colour(
+    history: Res<MoveHistory>,
+    network: Option<Res<NetworkSettings>>,
+    clocks: Res<GameClocks>,
+    mut buttons: Query<(&HistoryButton, &mut BackgroundColor)>,
+) {
+    if !history.is_changed() && !clocks.is_changed() {
+        return;
+    }
+
+    for (button, mut colour) in &mut buttons {
+        let enabled = match button {
+            HistoryButton::Undo => history.can_undo(),
+            HistoryButton::Redo => history.can_redo(),
+        };
+
+        *colour = if enabled && network.is_none() && clocks.is_none() {
+            Color::BLACK
+        } else {
+            Color::srgb(0.4, 0.4, 0.4)
+        }
+        .into();
+    }
+}
+
+// Against an AI, undo and redo keep going until its a humans turn again,
+// otherwise the AI would just play the same move straight away
+fn is_ais_turn(board: &Board, players: &Query<&Player>) -> bool {
+    let to_move: PlayerNum = board.to_move().into();
+    let has_human = players.iter().any(|p| p.kind == PlayerKind::Human);
+    let ai_to_move = players
+        .iter()
+        .any(|p| p.num == to_move && p.kind != PlayerKind::Human);
+
+    has_human && ai_to_move && !board.is_over()
+}
+
+fn update_undo_redo(
+    mut undo_events: EventReader<UndoEvent>,
+    mut redo_events: EventReader<RedoEvent>,
+    players: Query<&Player>,
+    mut board: ResMut<Board>,
+    mut history: ResMut<MoveHistory>,
+    mut current_player: ResMut<CurrentPlayer>,
+    falling: Query<(), With<FallingChip>>,
+) {
+    let undos = undo_events.read().count();
+    let redos = redo_events.read().count();
+    if undos == 0 && redos == 0 {
+        return;
+    }
+
+    // Wait for the chip to land, the same as dropping another one would
+    if is_animating(&falling) {
+        return;
+    }
+
+    for _ in 0..undos {
+        loop {
+            let Some(played) = history.played.pop() else {
+                break;
+            };
+
+            if board.undo().is_none() {
+                // The board doesnt know its own moves, so nothing can be taken back
+                history.played.push(played);
+                break;
+            }
+            history.undone.push(played);
+
+            if !is_ais_turn(&board, &players) {
+                break;
+            }
+        }
+    }
+
+    for _ in 0..redos {
+        loop {
+            let Some(played) = history.undone.pop() else {
+                break;
+            };
+
+            if let Err(error) = board.play_move(played.played) {
+                panic!("Failed to redo a move that was already played once: {error}")
+            }
+            history.played.push(played);
+
+            if !is_ais_turn(&board, &players) {
+                break;
+            }
+        }
+    }
+
+    let to_move: PlayerNum = board.to_move().into();
+    if let Some(player) = players.iter().find(|p| p.num == to_move) {
+        **current_player = player.clone();
+    }
+}
@@ -19,6 +19,12 @@ pub fn input(prompt: impl AsRef<str> + std::fmt::Display) -> String {
 
         // Read stdin into the "buffer"
         match stdin().read_line(&mut inp_buffer) {
+            // Nothing was read, so stdin has been closed (eg piped input ran out)
+            // Asking again would loop forever, so just stop
+            Ok(0) => {
+                println!();
+                std::process::exit(0);
+            }
             Ok(_) => {
                 return inp_buffer;
             }
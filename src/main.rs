@@ -8,18 +8,205 @@ use bevy::{
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_mod_picking::prelude::*;
 
+mod ai;
+mod ai_player;
+mod analyze;
+mod animation;
+mod book;
+mod chat;
+use chat::*;
+mod chess_clock;
+use chess_clock::*;
+mod clock;
+mod engine;
+mod engine_player;
+use engine_player::*;
+mod hint;
+use hint::*;
+mod history;
+mod input_handlers;
+mod net;
+mod network;
+use network::*;
+mod notation;
+mod replay;
+use replay::*;
+mod review;
+use review::*;
+mod rules;
+use rules::{BoardConfig, Rules};
+mod save;
+mod save_load;
+mod solver;
+mod tournament;
+mod tui;
+mod variant;
+use ai_player::*;
+use animation::*;
+use history::*;
+use save_load::*;
 mod players;
 use players::*;
 mod board;
 use board::*;
 mod menu;
 use menu::*;
+mod variant_menu;
+use variant_menu::*;
+mod main_menu;
+use main_menu::*;
+mod setup;
+use setup::*;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Tools that dont need a window
+    let tool: Option<fn(&[String]) -> Result<(), String>> = match args.first().map(String::as_str) {
+        Some("generate-book") => Some(book::run_generate_book),
+        Some("analyze") => Some(analyze::run_analyze),
+        Some("tournament") => Some(tournament::run_tournament),
+        _ => None,
+    };
+    if let Some(tool) = tool {
+        if let Err(error) = tool(&args[1..]) {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let player_kinds = match PlayerKinds::from_args(args.clone()) {
+        Ok(kinds) => kinds,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    // The rules come first, since they decide what size board to have if it isnt given
+    let rules = match Rules::from_args(&args) {
+        Ok(rules) => rules,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let board_config = match BoardConfig::from_args(&args, rules.variant().default_config()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let starting_board = match Board::from_args(&args, board_config, rules) {
+        Ok(board) => board,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let startup_save = match StartupSave::from_args(&args) {
+        Ok(save) => save,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let startup_replay = match StartupReplay::from_args(&args) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let engine_settings = match engine::EngineSettings::from_args(&args) {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let clock_settings = match ClockSettings::from_args(&args) {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let network_settings = match NetworkSettings::from_args(&args) {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    if startup_replay.0.is_some() && network_settings.is_some() {
+        eprintln!("error: replays cant be watched during a network game");
+        std::process::exit(1);
+    }
+
+    // The whole game in the terminal instead, without ever making a window
+    if args.iter().any(|arg| arg == "--tui") {
+        if network_settings.is_some() {
+            eprintln!("error: network games need the window, so cant be played with --tui");
+            std::process::exit(1);
+        }
+        if startup_replay.0.is_some() {
+            eprintln!("error: replays need the window, so cant be watched with --tui");
+            std::process::exit(1);
+        }
+        if clock_settings.0.is_some() {
+            eprintln!("error: clocks need the window, so cant be played with --tui");
+            std::process::exit(1);
+        }
+
+        if let Err(error) = tui::run_tui(
+            &player_kinds,
+            engine_settings,
+            starting_board.0,
+            startup_save.0.as_deref(),
+        ) {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let animation_settings = match AnimationSettings::from_args(&args) {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    // Starting a game from the command line skips the main menu
+    let initial_state = AppState::from_args(&args);
+
     let mut app = App::new();
+    app.insert_resource(player_kinds);
+    app.insert_resource(board_config);
+    app.insert_resource(rules);
+    app.insert_resource(starting_board);
+    app.insert_resource(startup_save);
+    app.insert_resource(startup_replay);
+    app.insert_resource(animation_settings);
+    app.insert_resource(engine_settings);
+    app.insert_resource(clock_settings);
 
     // Plugins
     app.add_plugins(DefaultPlugins);
+    app.insert_state(initial_state);
+    app.enable_state_scoped_entities::<AppState>();
     app.add_plugins(
         DefaultPickingPlugins
             .build()
@@ -31,10 +218,32 @@ fn main() {
 
     app.add_plugins(plugin_board);
     app.add_plugins(plugin_players);
+    app.add_plugins(plugin_ai_player);
+    app.add_plugins(plugin_engine_player);
+    app.add_plugins(plugin_save_load);
+    app.add_plugins(plugin_history);
+    app.add_plugins(plugin_hint);
+    app.add_plugins(plugin_review);
+    app.add_plugins(plugin_replay);
+    app.add_plugins(plugin_chess_clock);
+    app.add_plugins(plugin_animation);
     app.add_plugins(plugin_status_text);
+    app.add_plugins(plugin_variant_menu);
+    app.add_plugins(plugin_main_menu);
+    app.add_plugins(plugin_setup);
+
+    if let Some(network_settings) = network_settings {
+        app.insert_resource(network_settings);
+        app.add_plugins(plugin_network);
+        app.add_plugins(plugin_chat);
+    }
 
     // On startup
     app.add_systems(Startup, setup_camera);
+    app.add_systems(
+        Update,
+        update_camera_zoom.run_if(resource_changed::<BoardConfig>),
+    );
 
     app.run();
 }
@@ -42,3 +251,22 @@ fn main() {
 fn setup_camera(mut command: Commands) {
     command.spawn(Camera2dBundle::default());
 }
+
+// The board is drawn up and to the right of the middle of the screen, so zoom out until big boards fit in that corner
+fn update_camera_zoom(
+    config: Res<BoardConfig>,
+    windows: Query<&Window>,
+    mut projections: Query<&mut OrthographicProjection>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+
+    let board = board_size(&config);
+    let space = Vec2::new(window.width(), window.height()) / 2. - 20.;
+    let scale = (board / space).max_element().max(1.);
+
+    for mut projection in &mut projections {
+        projection.scale = scale;
+    }
+}
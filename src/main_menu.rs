@@ -0,0 +1,318 @@
+use crate::animation::AnimationSettings;
+use crate::board::{AppState, Board};
+use crate::hint::HintSettings;
+use crate::menu::start_new_game;
+use crate::players::{get_player1, Player};
+use crate::replay::{ReplaySource, StartReplayEvent};
+use crate::rules::{BoardConfig, Rules};
+use crate::save::DEFAULT_SAVE_PATH;
+use crate::save_load::{LoadGameEvent, SaveGameEvent};
+
+use bevy::{prelude::*, ui::FocusPolicy};
+
+const BUTTON_COLOUR: Color = Color::BLACK;
+const DISABLED_COLOUR: Color = Color::srgb(0.4, 0.4, 0.4);
+const SCREEN_COLOUR: Color = Color::srgb(0.05, 0.05, 0.2);
+const PAUSED_COLOUR: Color = Color::srgba(0., 0., 0., 0.7);
+
+// The settings screen goes round these, in order
+const ANIMATION_SPEEDS: [f32; 4] = [0.5, 1., 2., 4.];
+
+// Everything outside of actually playing: the main menu, settings, pausing, and the end of a game
+// (setting up a new game has its own plugin, in `setup`)
+pub fn plugin_main_menu(app: &mut App) {
+    app.add_systems(OnEnter(AppState::Menu), spawn_main_menu);
+    app.add_systems(OnEnter(AppState::Settings), spawn_settings);
+    app.add_systems(OnEnter(AppState::Paused), spawn_paused);
+    app.add_systems(OnEnter(AppState::GameOver), spawn_game_over);
+
+    app.add_systems(Update, update_menu_buttons);
+    app.add_systems(Update, update_menu_button_colour);
+    app.add_systems(
+        Update,
+        update_settings_labels.run_if(in_state(AppState::Settings)),
+    );
+    app.add_systems(Update, update_escape_key);
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+enum MenuButton {
+    NewGame,
+    Continue,
+    Load,
+    Replay,
+    Settings,
+    Quit,
+    Resume,
+    Save,
+    MainMenu,
+    PlayAgain,
+    Animations,
+    AnimationSpeed,
+    Evaluations,
+    Back,
+}
+
+// A page over the top of the whole game, which goes again once the state changes
+// Blocks clicks, so nothing underneath it can be pressed by accident
+pub fn spawn_screen(
+    command: &mut Commands,
+    state: AppState,
+    colour: Color,
+    children: impl FnOnce(&mut ChildBuilder),
+) {
+    command
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: colour.into(),
+                focus_policy: FocusPolicy::Block,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StateScoped(state),
+        ))
+        .with_children(children);
+}
+
+pub fn spawn_title(parent: &mut ChildBuilder, title: &str) {
+    parent.spawn(TextBundle::from_section(
+        title,
+        TextStyle {
+            font_size: 48.0,
+            ..default()
+        },
+    ));
+}
+
+// A button with some text on it, styled like the undo and redo ones
+pub fn spawn_button(parent: &mut ChildBuilder, label: impl Into<String>, button: impl Bundle) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(16.0), Val::Px(8.0)),
+                    min_width: Val::Px(200.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOUR.into(),
+                ..default()
+            },
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, TextStyle::default()));
+        });
+}
+
+// Whether there is a game to go back to
+fn can_continue(board: &Board) -> bool {
+    board.moves_played() > 0
+}
+
+fn spawn_main_menu(mut command: Commands) {
+    spawn_screen(&mut command, AppState::Menu, SCREEN_COLOUR, |parent| {
+        spawn_title(parent, "Connect 4");
+        spawn_button(parent, "New Game", MenuButton::NewGame);
+        spawn_button(parent, "Continue", MenuButton::Continue);
+        spawn_button(parent, "Load", MenuButton::Load);
+        spawn_button(parent, "Watch Replay", MenuButton::Replay);
+        spawn_button(parent, "Settings", MenuButton::Settings);
+        spawn_button(parent, "Quit", MenuButton::Quit);
+    });
+}
+
+fn spawn_settings(mut command: Commands) {
+    spawn_screen(&mut command, AppState::Settings, SCREEN_COLOUR, |parent| {
+        spawn_title(parent, "Settings");
+        // The labels get filled in by `update_settings_labels`
+        spawn_button(parent, "", MenuButton::Animations);
+        spawn_button(parent, "", MenuButton::AnimationSpeed);
+        spawn_button(parent, "", MenuButton::Evaluations);
+        spawn_button(parent, "Back", MenuButton::Back);
+    });
+}
+
+fn spawn_paused(mut command: Commands) {
+    spawn_screen(&mut command, AppState::Paused, PAUSED_COLOUR, |parent| {
+        spawn_title(parent, "Paused");
+        spawn_button(parent, "Resume", MenuButton::Resume);
+        spawn_button(parent, "Save", MenuButton::Save);
+        spawn_button(parent, "Main Menu", MenuButton::MainMenu);
+    });
+}
+
+// Just a couple of buttons along the bottom, so the winning line can still be seen
+fn spawn_game_over(mut command: Commands) {
+    command
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    bottom: Val::Px(10.0),
+                    justify_content: JustifyContent::Center,
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                },
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StateScoped(AppState::GameOver),
+        ))
+        .with_children(|parent| {
+            spawn_button(parent, "Play Again", MenuButton::PlayAgain);
+            spawn_button(parent, "Main Menu", MenuButton::MainMenu);
+        });
+}
+
+// Bevy systems take whatever they need as arguments, so this is just how many things the menus can do
+#[allow(clippy::too_many_arguments)]
+fn update_menu_buttons(
+    mut command: Commands,
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    board: Res<Board>,
+    config: Res<BoardConfig>,
+    rules: Res<Rules>,
+    players: Query<&Player>,
+    mut settings: ResMut<AnimationSettings>,
+    mut hint_settings: ResMut<HintSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut save_event: EventWriter<SaveGameEvent>,
+    mut load_event: EventWriter<LoadGameEvent>,
+    mut start_replay_event: EventWriter<StartReplayEvent>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            MenuButton::NewGame => next_state.set(AppState::Setup),
+            MenuButton::Continue if can_continue(&board) => next_state.set(if board.is_over() {
+                AppState::GameOver
+            } else {
+                AppState::InGame
+            }),
+            MenuButton::Continue => {}
+            // Loading moves on to the game by itself, if the save is any good
+            MenuButton::Load => {
+                load_event.send(LoadGameEvent {
+                    path: DEFAULT_SAVE_PATH.into(),
+                });
+            }
+            // Of the same save Load uses
+            MenuButton::Replay => {
+                start_replay_event.send(StartReplayEvent {
+                    source: ReplaySource::Save(DEFAULT_SAVE_PATH.into()),
+                });
+            }
+            MenuButton::Settings => next_state.set(AppState::Settings),
+            MenuButton::Quit => {
+                exit.send(AppExit::Success);
+            }
+            MenuButton::Resume => next_state.set(AppState::InGame),
+            MenuButton::Save => {
+                save_event.send(SaveGameEvent {
+                    path: DEFAULT_SAVE_PATH.into(),
+                });
+            }
+            MenuButton::MainMenu | MenuButton::Back => next_state.set(AppState::Menu),
+            MenuButton::PlayAgain => start_new_game(
+                &mut command,
+                &mut next_state,
+                *config,
+                *rules,
+                get_player1(&players),
+            ),
+            MenuButton::Animations => settings.enabled = !settings.enabled,
+            MenuButton::AnimationSpeed => {
+                settings.speed = ANIMATION_SPEEDS
+                    .into_iter()
+                    .find(|&speed| speed > settings.speed)
+                    .unwrap_or(ANIMATION_SPEEDS[0]);
+            }
+            MenuButton::Evaluations => {
+                hint_settings.show_evaluations = !hint_settings.show_evaluations
+            }
+        }
+    }
+}
+
+// Continue is greyed out when there isnt anything to continue
+fn update_menu_button_colour(
+    board: Res<Board>,
+    mut buttons: Query<(&MenuButton, &mut BackgroundColor)>,
+) {
+    for (button, mut colour) in &mut buttons {
+        let enabled = *button != MenuButton::Continue || can_continue(&board);
+        let new_colour = if enabled {
+            BUTTON_COLOUR
+        } else {
+            DISABLED_COLOUR
+        };
+
+        if colour.0 != new_colour {
+            colour.0 = new_colour;
+        }
+    }
+}
+
+fn update_settings_labels(
+    settings: Res<AnimationSettings>,
+    hint_settings: Res<HintSettings>,
+    buttons: Query<(&MenuButton, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (button, children) in &buttons {
+        let label = match button {
+            MenuButton::Animations if settings.enabled => "Animations: On".to_string(),
+            MenuButton::Animations => "Animations: Off".to_string(),
+            MenuButton::AnimationSpeed => format!("Animation Speed: {}x", settings.speed),
+            MenuButton::Evaluations if hint_settings.show_evaluations => {
+                "Show Evaluations: On".to_string()
+            }
+            MenuButton::Evaluations => "Show Evaluations: Off".to_string(),
+            _ => continue,
+        };
+
+        for &child in children {
+            if let Ok(mut text) = texts.get_mut(child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
+// Escape pauses the game, and goes back a screen everywhere else
+fn update_escape_key(
+    keys: Res<ButtonInput<KeyCode>>,
+    app_state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    match app_state.get() {
+        AppState::InGame => next_state.set(AppState::Paused),
+        AppState::Paused => next_state.set(AppState::InGame),
+        AppState::Setup | AppState::Settings | AppState::GameOver | AppState::Replay => {
+            next_state.set(AppState::Menu)
+        }
+        AppState::Menu => {}
+    }
+}
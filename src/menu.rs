@@ -1,31 +1,23 @@
-use crate::board::{Board, BoardState, GameState};
-use crate::players::{get_player1, CurrentPlayer, Player};
+use crate::board::{AppState, ArmedSpecial, Board};
+use crate::chess_clock::GameClocks;
+use crate::history::MoveHistory;
+use crate::players::{CurrentPlayer, Player, PlayerNum};
+use crate::replay::{replay_status, Replay};
+use crate::rules::{BoardConfig, Rules};
 
-use array2d::Array2D;
-use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
-use bevy::{prelude::*, text::Text2dBundle};
-use bevy_mod_picking::events::{Click, Pointer};
-use bevy_mod_picking::prelude::On;
-use bevy_mod_picking::PickableBundle;
+use bevy::prelude::*;
 
 pub fn plugin_status_text(app: &mut App) {
     app.register_type::<StatusText>();
-    app.register_type::<ResetButton>();
 
     app.add_systems(Startup, spawn_inital_status_text);
-    app.add_systems(Startup, spawn_click_to_reset);
     app.add_systems(Update, write_current_status_text);
-    app.add_systems(Update, show_hide_reset_button);
 }
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 struct StatusText;
 
-#[derive(Component, Reflect)]
-#[reflect(Component)]
-struct ResetButton;
-
 fn spawn_inital_status_text(mut command: Commands) {
     command.spawn((
         // Create a TextBundle that has a Text with a single section.
@@ -45,56 +37,72 @@ fn spawn_inital_status_text(mut command: Commands) {
     ));
 }
 
+// Bevy systems take whatever they need as arguments, and the status text shows a bit of everything
+#[allow(clippy::too_many_arguments)]
 fn write_current_status_text(
     current_player: Res<CurrentPlayer>,
-    game_state: Res<GameState>,
+    board: Res<Board>,
+    armed: Res<ArmedSpecial>,
+    app_state: Res<State<AppState>>,
+    replay: Res<Replay>,
+    history: Res<MoveHistory>,
+    players: Query<&Player>,
+    clocks: Res<GameClocks>,
     mut status_text: Query<&mut Text, With<StatusText>>,
 ) {
-    let new_text = match *game_state {
-        GameState::Playing => current_player.name.clone(),
-        GameState::Won => format!("{} has won!", current_player.name.clone()),
-        GameState::Draw => String::from("No one won. No one lost. It is a draw."),
+    let result = if let Some(win) = board.win() {
+        // The current player has already moved on to the loser by now
+        let winner = PlayerNum::from(win.winner);
+        let Some(winner) = players.iter().find(|p| p.num == winner) else {
+            panic!("Tried to announce a winner that isnt a player")
+        };
+        let flagged = clocks.0.as_ref().and_then(|clocks| clocks.flagged());
+        if flagged == Some(win.winner.other()) {
+            Some(format!("{} has won on time!", winner.name))
+        } else {
+            Some(format!("{} has won!", winner.name))
+        }
+    } else if board.is_draw() {
+        Some(String::from("No one won. No one lost. It is a draw."))
+    } else {
+        None
+    };
+
+    let new_text = if *app_state.get() == AppState::Replay {
+        // Which move its on, rather than whose turn it is
+        let text = replay_status(&replay, &history, &players);
+        match result {
+            Some(result) => format!("{text}\n{result}"),
+            None => text,
+        }
+    } else if let Some(result) = result {
+        result
+    } else {
+        // Along with anything the variant wants to say, like a score
+        let mut text = current_player.name.clone();
+        if let Some(status) = board.variant().status(&board) {
+            text = format!("{text}\n{status}");
+        }
+        if let Some(special) = **armed {
+            text = format!("{text}\nThe {} is ready, click a column", special.name());
+        }
+        text
     };
 
     let mut text_entity = status_text.single_mut();
     *text_entity = Text::from_section(new_text, TextStyle::default())
 }
 
-fn spawn_click_to_reset(
-    mut command: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+// An empty board, with `player1` to go first
+pub fn start_new_game(
+    command: &mut Commands,
+    next_state: &mut NextState<AppState>,
+    config: BoardConfig,
+    rules: Rules,
+    player1: Player,
 ) {
-    let text_bg_rect = Mesh2dHandle(meshes.add(Rectangle::new(30., 15.)));
-    command.spawn((
-        MaterialMesh2dBundle {
-            mesh: text_bg_rect,
-            material: materials.add(Color::BLACK),
-            transform: Transform::from_xyz(0., 0., 0.),
-            visibility: Visibility::Hidden,
-            ..default()
-        },
-        PickableBundle::default(),
-        On::<Pointer<Click>>::run(reset_game),
-        ResetButton,
-    ));
-}
-
-fn show_hide_reset_button(
-    game_state: Res<GameState>,
-    mut button: Query<&mut Visibility, With<ResetButton>>,
-) {
-    let mut button_visibility: Mut<'_, Visibility> = button.get_single_mut().unwrap();
-    match *game_state {
-        GameState::Playing => *button_visibility = Visibility::Hidden,
-        GameState::Won => *button_visibility = Visibility::Visible,
-        GameState::Draw => *button_visibility = Visibility::Visible,
-    };
-}
-
-fn reset_game(mut command: Commands, players: Query<&Player>) {
-    command.insert_resource(GameState::Playing);
-    command.insert_resource(Board::default());
-    command.insert_resource(CurrentPlayer(get_player1(&players)));
-
+    command.insert_resource(Board::new(config, rules));
+    command.insert_resource(MoveHistory::default());
+    command.insert_resource(CurrentPlayer(player1));
+    next_state.set(AppState::InGame);
 }
@@ -0,0 +1,566 @@
+// Playing against another copy of the game over TCP, for `--host` and `--join`, and talking to `connect4-server`
+//
+// Every message is one line of JSON. Both sides start with a `hello`, then the host sends its whole
+// game, which the guest takes over. After that each move is sent as it is played, and the other side
+// checks it against its own board before playing it too. The host always has the real game, so when
+// the two disagree (or the guest comes back after losing the connection) the host just sends it again
+//
+// A server is the host for every game on it. After the `hello`, a client says which lobby it wants
+// with `create`, `join` or `watch`, and gets told its seat with `lobby` before the game is sent
+//
+// Either side can send `chat` at any point after the `hello`. A server passes it on to everyone else
+// in the lobby, in the order it came in, after cleaning it up with `clean_chat` and dropping anything
+// over the `ChatLimit`
+//
+// Games with a time control have their clocks kept by the host (or server) alone. It sends `clock` after
+// every move, a new game, or someone running out of time, and the other side just shows what it says
+//
+// Nothing here knows about Bevy, see `network` for the part that does
+
+use crate::clock::{Clocks, TimeControl};
+use crate::notation::{move_notation, parse_move, Game};
+use crate::rules::*;
+use crate::save::SavedConfig;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const PROTOCOL_VERSION: u32 = 3;
+pub const DEFAULT_PORT: u16 = 4444;
+
+// Sent when there is nothing else to say, so a quiet game isnt mistaken for a dead connection
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+// Hearing nothing at all for this long means the other side has gone
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// In bytes, way more than the longest game needs, but it stops anyone sending one line that never ends
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+
+// In characters, anything longer is cut short
+pub const MAX_CHAT_LENGTH: usize = 120;
+// For names too, the same as the setup screen allows
+pub const MAX_NAME_LENGTH: usize = 16;
+// A few chat messages can go at once, but after that only one every couple of seconds
+const CHAT_BURST: f32 = 5.0;
+const CHAT_REFILL: Duration = Duration::from_secs(2);
+
+// A whole game, as the host sends it
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub config: SavedConfig,
+    // The name of the variant, eg "popout"
+    pub rules: String,
+    pub moves: String,
+}
+
+impl GameSnapshot {
+    // None if we dont know every move that was played, since then there is no way to send it
+    pub fn from_game(game: &Game) -> Option<Self> {
+        Some(GameSnapshot {
+            config: SavedConfig::from(*game.config()),
+            rules: game.rules().name().to_string(),
+            moves: game.to_notation()?,
+        })
+    }
+
+    pub fn to_game(&self) -> Result<Game, NetError> {
+        let SavedConfig {
+            rows,
+            columns,
+            connect,
+        } = self.config;
+        let config = BoardConfig::new(rows, columns, connect)
+            .map_err(|error| NetError::BadGame(error.to_string()))?;
+        let rules: Rules = self.rules.parse().map_err(NetError::BadGame)?;
+
+        Game::from_notation_with_rules(config, rules, &self.moves)
+            .map_err(|error| NetError::BadGame(format!("invalid moves '{}': {error}", self.moves)))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Message {
+    // The first thing each side sends
+    Hello {
+        version: u32,
+        name: String,
+    },
+    // From the host, the guest throws away whatever it had and plays this instead
+    // The guest sends one to start a new game, which the host only takes once the last one is over
+    // (and either way answers with the game everyone is playing now)
+    Game {
+        game: GameSnapshot,
+    },
+    // `index` is how many moves came before it, so one that got lost or doubled up is noticed
+    Move {
+        index: usize,
+        notation: String,
+    },
+    // From the guest, asking for the game again when it cant make sense of a move
+    Sync,
+    // To a server, for a new lobby playing `game`, with whoever made it as player 1
+    // `clock` is the time control, eg "fischer:3m+2s", if the game has one
+    Create {
+        game: GameSnapshot,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clock: Option<String>,
+    },
+    // To a server, for a seat in a lobby, or to watch if both are taken
+    Join {
+        code: String,
+    },
+    // To a server, to watch a lobby without playing
+    Watch {
+        code: String,
+    },
+    // From a server, straight after joining and whenever someone comes or goes
+    // `seat` is 1 or 2 for whoever gets it, or None for someone watching
+    Lobby {
+        code: String,
+        seat: Option<u8>,
+        players: [Option<String>; 2],
+        spectators: usize,
+    },
+    // Something someone said. `from` is whoever said it, which a server fills in itself
+    Chat {
+        from: String,
+        text: String,
+    },
+    // From the host, how much time everyone has. Times are in milliseconds, player 1 first, and `used`
+    // is how long whoever is to move has been thinking. `flagged` is 1 or 2 for whoever ran out
+    Clock {
+        control: String,
+        remaining: [u64; 2],
+        used: u64,
+        flagged: Option<u8>,
+    },
+    Ping,
+    // Whoever sends this closes the connection straight after
+    Error {
+        message: String,
+    },
+}
+
+impl Message {
+    pub fn hello(name: &str) -> Self {
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn play(index: usize, next: Move) -> Self {
+        Message::Move {
+            index,
+            notation: move_notation(next),
+        }
+    }
+
+    pub fn clock(clocks: &Clocks, used: Duration) -> Self {
+        Message::Clock {
+            control: clocks.control().to_string(),
+            remaining: clocks.remaining().map(millis),
+            used: millis(used),
+            flagged: clocks.flagged().map(|side| side.index() as u8 + 1),
+        }
+    }
+}
+
+// Clocks that somehow got too long to send just show as the longest time there can be
+fn millis(time: Duration) -> u64 {
+    u64::try_from(time.as_millis()).unwrap_or(u64::MAX)
+}
+
+// The clocks out of a `Message::Clock`, along with how long the player to move has been thinking
+pub fn read_clock(
+    control: &str,
+    remaining: [u64; 2],
+    used: u64,
+    flagged: Option<u8>,
+) -> Result<(Clocks, Duration), NetError> {
+    let control: TimeControl = control.parse().map_err(NetError::BadGame)?;
+    let flagged = match flagged {
+        None => None,
+        Some(1) => Some(Side::First),
+        Some(2) => Some(Side::Second),
+        Some(seat) => {
+            return Err(NetError::Unexpected(format!(
+                "player {seat} running out of time"
+            )))
+        }
+    };
+
+    Ok((
+        Clocks::from_parts(control, remaining.map(Duration::from_millis), flagged),
+        Duration::from_millis(used),
+    ))
+}
+
+// What is left of a chat message without anything that shouldnt be in one, or None if that is nothing
+pub fn clean_chat(text: &str) -> Option<String> {
+    clean_text(text, MAX_CHAT_LENGTH)
+}
+
+// The same for the name someone says hello with, since everyone else in the lobby gets shown it
+pub fn clean_name(name: &str) -> Option<String> {
+    clean_text(name, MAX_NAME_LENGTH)
+}
+
+fn clean_text(text: &str, max_length: usize) -> Option<String> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text: String = text.trim().chars().take(max_length).collect();
+    let text = text.trim_end();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+// How many chat messages someone is allowed to send right now, so no one can flood the chat
+pub struct ChatLimit {
+    allowance: f32,
+    last_checked: Instant,
+}
+
+impl Default for ChatLimit {
+    fn default() -> Self {
+        ChatLimit {
+            allowance: CHAT_BURST,
+            last_checked: Instant::now(),
+        }
+    }
+}
+
+impl ChatLimit {
+    // Uses up one message if there is one to use
+    pub fn allow(&mut self) -> bool {
+        let refilled = self.last_checked.elapsed().as_secs_f32() / CHAT_REFILL.as_secs_f32();
+        self.allowance = (self.allowance + refilled).min(CHAT_BURST);
+        self.last_checked = Instant::now();
+
+        if self.allowance >= 1.0 {
+            self.allowance -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Reads the move out of a `Message::Move`, checking it can be played as the next move of `game`
+pub fn check_move(game: &Game, index: usize, notation: &str) -> Result<Move, NetError> {
+    if index != game.moves().len() {
+        return Err(NetError::OutOfSync {
+            expected: game.moves().len(),
+            got: index,
+        });
+    }
+
+    let next = parse_move(notation).map_err(|error| NetError::IllegalMove {
+        notation: notation.to_string(),
+        reason: error.to_string(),
+    })?;
+
+    // Try it out on a copy, so the real board only ever gets moves that work
+    let mut copy = game.clone();
+    match copy.play_move(next) {
+        Ok(_) => Ok(next),
+        Err(error) => Err(NetError::IllegalMove {
+            notation: notation.to_string(),
+            reason: error.to_string(),
+        }),
+    }
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    BadMessage(serde_json::Error),
+    // The other side hung up
+    Closed,
+    TimedOut,
+    WrongVersion(u32),
+    // The other side sent an `Error`
+    Refused(String),
+    BadGame(String),
+    // The other side sent something that doesnt make sense right now
+    Unexpected(String),
+    TooLong,
+    OutOfSync { expected: usize, got: usize },
+    IllegalMove { notation: String, reason: String },
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Io(error) => write!(f, "{error}"),
+            NetError::BadMessage(error) => write!(f, "couldnt read a message: {error}"),
+            NetError::Closed => write!(f, "the connection was closed"),
+            NetError::TimedOut => write!(f, "heard nothing for {} seconds", TIMEOUT.as_secs()),
+            NetError::WrongVersion(version) => write!(
+                f,
+                "the other side speaks version {version}, but we speak version {PROTOCOL_VERSION}"
+            ),
+            NetError::Refused(message) => write!(f, "the other side said: {message}"),
+            NetError::BadGame(error) => write!(f, "got a game that cant be played: {error}"),
+            NetError::Unexpected(what) => write!(f, "didnt expect {what}"),
+            NetError::TooLong => write!(f, "got a message longer than {MAX_MESSAGE_LENGTH} bytes"),
+            NetError::OutOfSync { expected, got } => {
+                write!(f, "expected move {} but got move {}", expected + 1, got + 1)
+            }
+            NetError::IllegalMove { notation, reason } => {
+                write!(
+                    f,
+                    "got the move '{notation}', which cant be played: {reason}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<io::Error> for NetError {
+    fn from(error: io::Error) -> Self {
+        NetError::Io(error)
+    }
+}
+
+// Reads messages off `stream` on their own thread, handing each one to `deliver` until it says to stop
+// The last thing it gets is always an error, eg `NetError::Closed` when the other side hangs up
+pub fn read_messages(
+    stream: TcpStream,
+    mut deliver: impl FnMut(Result<Message, NetError>) -> bool + Send + 'static,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+
+        loop {
+            // One more than the limit, to tell a line thats too long apart from one thats just long enough
+            line.clear();
+            let read = (&mut reader)
+                .take(MAX_MESSAGE_LENGTH as u64 + 1)
+                .read_line(&mut line);
+
+            let message = match read {
+                Ok(0) => break,
+                Ok(_) if line.len() > MAX_MESSAGE_LENGTH && !line.ends_with('\n') => {
+                    Err(NetError::TooLong)
+                }
+                Ok(_) => serde_json::from_str(&line).map_err(NetError::BadMessage),
+                Err(error) => Err(NetError::Io(error)),
+            };
+
+            let failed = message.is_err();
+            if !deliver(message) || failed {
+                return;
+            }
+        }
+        deliver(Err(NetError::Closed));
+    });
+}
+
+pub fn write_message(stream: &mut TcpStream, message: &Message) -> Result<(), NetError> {
+    let mut line = serde_json::to_string(message).expect("Failed to turn a message into json");
+    line.push('\n');
+
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+// One end of a connection
+// Messages are read on their own thread, so checking for them never waits
+pub struct Connection {
+    stream: TcpStream,
+    peer: SocketAddr,
+    incoming: Receiver<Result<Message, NetError>>,
+    last_heard: Instant,
+    last_sent: Instant,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Result<Self, NetError> {
+        // Moves are tiny, and should go straight away rather than waiting to be bundled up
+        stream.set_nodelay(true)?;
+        let peer = stream.peer_addr()?;
+
+        let (sender, incoming) = mpsc::channel();
+        read_messages(stream.try_clone()?, move |message| {
+            sender.send(message).is_ok()
+        });
+
+        Ok(Connection {
+            stream,
+            peer,
+            incoming,
+            last_heard: Instant::now(),
+            last_sent: Instant::now(),
+        })
+    }
+
+    // `address` can leave off the port, eg "192.168.1.20" or "localhost:5000"
+    pub fn connect(address: &str) -> Result<Self, NetError> {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{address}:{DEFAULT_PORT}")
+        };
+
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no addresses to try");
+        for socket in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT) {
+                Ok(stream) => return Connection::new(stream),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(NetError::Io(last_error))
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<(), NetError> {
+        write_message(&mut self.stream, message)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    // The next message that has come in, without waiting for one
+    // Pings are dealt with in here, and only show up as the connection staying alive
+    pub fn receive(&mut self) -> Result<Option<Message>, NetError> {
+        loop {
+            match self.incoming.try_recv() {
+                Ok(Ok(message)) => {
+                    self.last_heard = Instant::now();
+                    match message {
+                        Message::Ping => continue,
+                        Message::Error { message } => return Err(NetError::Refused(message)),
+                        message => return Ok(Some(message)),
+                    }
+                }
+                Ok(Err(error)) => return Err(error),
+                Err(TryRecvError::Disconnected) => return Err(NetError::Closed),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        if self.last_heard.elapsed() > TIMEOUT {
+            return Err(NetError::TimedOut);
+        }
+        if self.last_sent.elapsed() > PING_INTERVAL {
+            self.send(&Message::Ping)?;
+        }
+        Ok(None)
+    }
+
+    // Waits up to `timeout` for the next message, for things with nothing better to do in the meantime
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>, NetError> {
+        let start = Instant::now();
+        loop {
+            if let Some(message) = self.receive()? {
+                return Ok(Some(message));
+            }
+            if start.elapsed() >= timeout {
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    // Tells the other side why, before hanging up
+    pub fn close_with_error(mut self, message: &str) {
+        let _ = self.send(&Message::Error {
+            message: message.to_string(),
+        });
+    }
+}
+
+// So the reading thread finishes, and the other side hears about it straight away
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// Waits for guests to connect, without blocking
+pub struct Listener {
+    listener: TcpListener,
+}
+
+impl Listener {
+    // Listens on every network the computer is on, so others on the LAN can join
+    pub fn bind(port: u16) -> Result<Self, NetError> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Listener { listener })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener
+            .local_addr()
+            .map(|address| address.port())
+            .unwrap_or_default()
+    }
+
+    // Someone new, if anyone has turned up
+    pub fn accept(&self) -> Result<Option<Connection>, NetError> {
+        match self.listener.accept() {
+            Ok((stream, _)) => {
+                // Some platforms pass on the listener being non blocking, but the reading thread wants to wait
+                stream.set_nonblocking(false)?;
+                Connection::new(stream).map(Some)
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(NetError::Io(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clocks_are_sent_as_milliseconds() {
+        let control = TimeControl::Fischer {
+            time: Duration::from_secs(60),
+            increment: Duration::from_secs(2),
+        };
+        let clocks = Clocks::from_parts(
+            control,
+            [Duration::from_millis(1500), Duration::MAX],
+            Some(Side::Second),
+        );
+
+        let Message::Clock {
+            control: sent,
+            remaining,
+            used,
+            flagged,
+        } = Message::clock(&clocks, Duration::from_millis(250))
+        else {
+            panic!("Tried to send the clocks as something else")
+        };
+        assert_eq!(remaining, [1500, u64::MAX]);
+        assert_eq!(used, 250);
+
+        let (read, used) = read_clock(&sent, [1500, 60_000], used, flagged).unwrap();
+        assert_eq!(read.control(), control);
+        assert_eq!(
+            read.remaining(),
+            [Duration::from_millis(1500), Duration::from_secs(60)]
+        );
+        assert_eq!(read.flagged(), Some(Side::Second));
+        assert_eq!(used, Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,739 @@
+// Playing someone on another computer, see `net` for what goes over the wire
+// Between two copies of the game the host is always player 1 and the guest player 2. On a server
+// it says which seat we get, if any. Every other seat is a `PlayerKind::Remote` that only moves
+// when a move comes in for it
+
+use crate::board::{AppState, Board, PlayMoveEvent};
+use crate::chat::{ChatLog, SendChatEvent};
+use crate::chess_clock::{ClockSettings, GameClocks, MoveStopwatch};
+use crate::history::MoveHistory;
+use crate::net::*;
+use crate::notation::Game;
+use crate::players::{CurrentPlayer, Player, PlayerKind, PlayerNum};
+use crate::rules::Move;
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+
+const RECONNECT_KEY: KeyCode = KeyCode::KeyR;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NetworkRole {
+    Host {
+        port: u16,
+    },
+    // Anything `Connection::connect` understands, eg "192.168.1.20" or "localhost:5000"
+    Join {
+        address: String,
+        // Only for `connect4-server`
+        lobby: Option<LobbyChoice>,
+    },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LobbyChoice {
+    Create,
+    Join(String),
+    Watch(String),
+}
+
+// Only exists when playing over the network
+#[derive(Resource, Clone, Debug)]
+pub struct NetworkSettings {
+    pub role: NetworkRole,
+    // What the other side calls us
+    pub name: String,
+}
+
+impl NetworkSettings {
+    // Reads `--host <port>` or `--join <address>`, and `--name <name>`
+    // Joining a server also needs `--lobby new`, `--lobby <code>` or `--watch <code>`
+    // None if none of them are there, for a game on just this computer
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let value = |flag: &str| match args.iter().position(|arg| arg == flag) {
+            Some(index) => match args.get(index + 1) {
+                Some(value) => Ok(Some(value.clone())),
+                None => Err(format!("{flag} needs a value after it")),
+            },
+            None => Ok(None),
+        };
+
+        let lobby = match (value("--lobby")?, value("--watch")?) {
+            (Some(_), Some(_)) => return Err("cant both --lobby and --watch a game".into()),
+            (Some(code), None) if code == "new" => Some(LobbyChoice::Create),
+            (Some(code), None) => Some(LobbyChoice::Join(code)),
+            (None, Some(code)) => Some(LobbyChoice::Watch(code)),
+            (None, None) => None,
+        };
+
+        let role = match (value("--host")?, value("--join")?) {
+            (Some(_), Some(_)) => return Err("cant both --host and --join a game".into()),
+            (Some(_), None) if lobby.is_some() => {
+                return Err("--lobby and --watch are for joining a server, with --join".into())
+            }
+            (Some(port), None) => match port.parse() {
+                Ok(port) => NetworkRole::Host { port },
+                Err(error) => return Err(format!("invalid port '{port}': {error}")),
+            },
+            (None, Some(address)) => NetworkRole::Join { address, lobby },
+            (None, None) if lobby.is_some() => {
+                return Err("--lobby and --watch need a server to --join".into())
+            }
+            (None, None) => return Ok(None),
+        };
+
+        let name = match value("--name")? {
+            Some(name) => name,
+            None => match role {
+                NetworkRole::Host { .. } => "Player 1".into(),
+                NetworkRole::Join { .. } => "Player 2".into(),
+            },
+        };
+
+        Ok(Some(NetworkSettings { role, name }))
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, NetworkRole::Host { .. })
+    }
+
+    fn lobby(&self) -> Option<&LobbyChoice> {
+        match &self.role {
+            NetworkRole::Join { lobby, .. } => lobby.as_ref(),
+            NetworkRole::Host { .. } => None,
+        }
+    }
+}
+
+fn seat_index(num: &PlayerNum) -> usize {
+    match num {
+        PlayerNum::Player1 => 0,
+        PlayerNum::Player2 => 1,
+    }
+}
+
+#[derive(Resource, Default)]
+struct Network {
+    // Only for the host
+    listener: Option<Listener>,
+    // Only for the guest, while it is trying to get through
+    connecting: Option<Task<Result<Connection, NetError>>>,
+    connection: Option<Connection>,
+    // Both sides have said hello, so moves can start going back and forth
+    greeted: bool,
+    // Which player is on this computer, None when only watching
+    seat: Option<PlayerNum>,
+    // What the other side says everyone is called, player 1 first
+    names: [Option<String>; 2],
+    // Once a server has put us in a lobby, so a reconnect goes back to the same one
+    code: Option<String>,
+    // Every move both sides know about
+    agreed: Vec<Move>,
+    // The guest has asked for the whole game again, and is waiting for it
+    syncing: bool,
+    // The host owes the other side the clocks, even if they havent changed
+    send_clock: bool,
+    // What went wrong last, to show until things are working again
+    problem: Option<String>,
+    // How fast the other side can chat without a server in between, since a server keeps everyone to it itself
+    chat_limit: ChatLimit,
+    // What the local seat gets to be once the other side is there, it cant move while no one is
+    local_kind: Option<PlayerKind>,
+}
+
+impl Network {
+    fn is_ready(&self) -> bool {
+        self.connection.is_some() && self.greeted
+    }
+
+    // Whoever the local player is playing against is there too
+    fn opponent_here(&self) -> bool {
+        match &self.seat {
+            Some(seat) => self.is_ready() && self.names[1 - seat_index(seat)].is_some(),
+            None => false,
+        }
+    }
+
+    fn disconnect(&mut self, error: NetError) {
+        warn!("Lost the network game: {error}");
+        self.connection = None;
+        self.greeted = false;
+        self.syncing = false;
+        self.problem = Some(error.to_string());
+    }
+
+    // For things that are our side's problem to report, rather than the connection going
+    fn disconnect_with_error(&mut self, error: NetError) {
+        if let Some(connection) = self.connection.take() {
+            connection.close_with_error(&error.to_string());
+        }
+        self.disconnect(error);
+    }
+
+    fn send(&mut self, message: &Message) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+
+        if let Err(error) = connection.send(message) {
+            self.disconnect(error);
+        }
+    }
+
+    // The host's game is always the right one, so this is how every disagreement ends
+    fn send_game(&mut self, game: &Game) {
+        let Some(snapshot) = GameSnapshot::from_game(game) else {
+            self.disconnect_with_error(NetError::BadGame(
+                "the host doesnt know the moves of its game, so it cant be shared".into(),
+            ));
+            return;
+        };
+
+        self.send(&Message::Game { game: snapshot });
+        self.agreed = game.moves().to_vec();
+        self.send_clock = true;
+    }
+
+    fn ask_for_game(&mut self) {
+        if !self.syncing {
+            self.send(&Message::Sync);
+            self.syncing = true;
+        }
+    }
+}
+
+pub fn plugin_network(app: &mut App) {
+    app.init_resource::<Network>();
+
+    app.add_systems(Startup, (start_network, spawn_network_text));
+
+    // Before anything else, so moves that come in are played this frame, and whoever is to move
+    // never gets the chance to click for the other side
+    app.add_systems(
+        PreUpdate,
+        (
+            update_network_connect,
+            update_network_send,
+            update_network_receive,
+            update_network_seats,
+        )
+            .chain(),
+    );
+
+    app.add_systems(
+        Update,
+        update_reconnect_key.run_if(not(in_state(AppState::Setup))),
+    );
+    app.add_systems(Update, update_network_text);
+}
+
+fn start_guest_connecting(network: &mut Network, address: &str) {
+    let address = address.to_string();
+    network.connecting =
+        Some(AsyncComputeTaskPool::get().spawn(async move { Connection::connect(&address) }));
+}
+
+fn start_network(settings: Res<NetworkSettings>, mut network: ResMut<Network>) {
+    match &settings.role {
+        NetworkRole::Host { port } => {
+            network.seat = Some(PlayerNum::Player1);
+            match Listener::bind(*port) {
+                Ok(listener) => {
+                    info!("Hosting on port {}", listener.port());
+                    network.listener = Some(listener);
+                }
+                Err(error) => {
+                    error!("Failed to host on port {port}: {error}");
+                    network.problem = Some(format!("couldnt listen on port {port}: {error}"));
+                }
+            }
+        }
+        NetworkRole::Join { address, lobby } => {
+            // A server says which seat we get once we are in the lobby
+            if lobby.is_none() {
+                network.seat = Some(PlayerNum::Player2);
+            }
+            start_guest_connecting(&mut network, address);
+        }
+    }
+}
+
+fn update_network_connect(
+    settings: Res<NetworkSettings>,
+    clock_settings: Res<ClockSettings>,
+    board: Res<Board>,
+    mut network: ResMut<Network>,
+) {
+    let connection = if let Some(listener) = &network.listener {
+        match listener.accept() {
+            Ok(Some(connection)) => connection,
+            Ok(None) => return,
+            Err(error) => {
+                warn!("Failed to accept someone: {error}");
+                return;
+            }
+        }
+    } else if let Some(connecting) = &mut network.connecting {
+        let Some(result) = block_on(future::poll_once(connecting)) else {
+            return;
+        };
+        network.connecting = None;
+
+        match result {
+            Ok(connection) => connection,
+            Err(error) => {
+                network.disconnect(error);
+                return;
+            }
+        }
+    } else {
+        return;
+    };
+
+    // Only two can play
+    if network.connection.is_some() {
+        info!(
+            "Turning away {}, someone is already playing",
+            connection.peer()
+        );
+        connection.close_with_error("someone is already playing here");
+        return;
+    }
+
+    info!("Connected to {}", connection.peer());
+    network.connection = Some(connection);
+    network.problem = None;
+    // The guest doesnt send anything until it has the host's game, whatever it had before is out of date
+    network.syncing = !settings.is_host();
+    network.send(&Message::hello(&settings.name));
+
+    // Coming back to a lobby goes back to the same one, and the same seat if no one else has taken it
+    let lobby = match (settings.lobby(), network.code.clone()) {
+        (Some(LobbyChoice::Watch(_)), Some(code)) => Message::Watch { code },
+        (Some(_), Some(code)) => Message::Join { code },
+        (Some(LobbyChoice::Create), None) => {
+            // A game loaded without its moves cant be shared, so that starts again
+            let game = match GameSnapshot::from_game(&board) {
+                Some(game) => game,
+                None => GameSnapshot::from_game(&Game::with_rules(*board.config(), board.rules()))
+                    .expect("Tried to share a new game, but it didnt know its moves"),
+            };
+            Message::Create {
+                game,
+                clock: clock_settings.0.map(|control| control.to_string()),
+            }
+        }
+        (Some(LobbyChoice::Join(code)), None) => Message::Join { code: code.clone() },
+        (Some(LobbyChoice::Watch(code)), None) => Message::Watch { code: code.clone() },
+        (None, _) => return,
+    };
+    network.send(&lobby);
+}
+
+// Tells the other side about any moves made here, and anything said in the chat
+// The host sends the clocks too, after the moves that changed them
+fn update_network_send(
+    settings: Res<NetworkSettings>,
+    board: Res<Board>,
+    clocks: Res<GameClocks>,
+    stopwatch: Res<MoveStopwatch>,
+    mut network: ResMut<Network>,
+    mut send_chat_events: EventReader<SendChatEvent>,
+    mut log: ResMut<ChatLog>,
+) {
+    for SendChatEvent(text) in send_chat_events.read() {
+        if network.is_ready() {
+            network.send(&Message::Chat {
+                from: settings.name.clone(),
+                text: text.clone(),
+            });
+        } else {
+            log.push_note("Not connected, so no one heard that");
+        }
+    }
+
+    if !network.is_ready() || network.syncing {
+        return;
+    }
+
+    let moves = board.moves();
+    let snapshot = GameSnapshot::from_game(&board);
+
+    if snapshot.is_some() && moves.starts_with(&network.agreed) {
+        for (index, &next) in moves.iter().enumerate().skip(network.agreed.len()) {
+            network.send(&Message::play(index, next));
+        }
+        network.agreed = moves.to_vec();
+    } else {
+        // Anything else means this side started a new game, or loaded one
+        // Thats fine for the host, but a guest can only suggest it, and waits to hear back what the game is
+        match snapshot {
+            _ if settings.is_host() => network.send_game(&board),
+            Some(game) if network.seat.is_some() => {
+                network.send(&Message::Game { game });
+                network.syncing = true;
+            }
+            _ => network.ask_for_game(),
+        }
+    }
+
+    if settings.is_host() && (clocks.is_changed() || network.send_clock) {
+        network.send_clock = false;
+        if let Some(clocks) = &clocks.0 {
+            network.send(&Message::clock(clocks, stopwatch.elapsed()));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_network_receive(
+    mut command: Commands,
+    settings: Res<NetworkSettings>,
+    board: Res<Board>,
+    players: Query<&Player>,
+    mut network: ResMut<Network>,
+    mut log: ResMut<ChatLog>,
+    mut clocks: ResMut<GameClocks>,
+    mut stopwatch: ResMut<MoveStopwatch>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
+) {
+    // Moves that come in together have to be checked one after the other, before the board has played any
+    let mut game: Game = board.0.clone();
+
+    loop {
+        let Some(connection) = &mut network.connection else {
+            return;
+        };
+
+        let message = match connection.receive() {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(error) => {
+                network.disconnect(error);
+                return;
+            }
+        };
+
+        if let Err(error) = receive_message(
+            &mut command,
+            &settings,
+            &players,
+            &mut network,
+            &mut game,
+            &mut log,
+            &mut clocks,
+            &mut stopwatch,
+            &mut play_move_event,
+            message,
+        ) {
+            network.disconnect_with_error(error);
+            return;
+        }
+    }
+}
+
+// The same as loading a save, except the other side has already checked it
+fn adopt_game(command: &mut Commands, players: &Query<&Player>, game: &Game) {
+    let board = Board(game.clone());
+    let to_move: PlayerNum = board.to_move().into();
+    if let Some(player) = players.iter().find(|p| p.num == to_move) {
+        command.insert_resource(CurrentPlayer(player.clone()));
+    }
+    command.insert_resource(*board.config());
+    command.insert_resource(board.rules());
+    command.insert_resource(MoveHistory::from_game(&board));
+    command.insert_resource(board);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_message(
+    command: &mut Commands,
+    settings: &NetworkSettings,
+    players: &Query<&Player>,
+    network: &mut Network,
+    game: &mut Game,
+    log: &mut ChatLog,
+    // Not just `&mut GameClocks`, which would count as changing them every time
+    clocks: &mut ResMut<GameClocks>,
+    stopwatch: &mut ResMut<MoveStopwatch>,
+    play_move_event: &mut EventWriter<PlayMoveEvent>,
+    message: Message,
+) -> Result<(), NetError> {
+    if !network.greeted && !matches!(message, Message::Hello { .. }) {
+        return Err(NetError::Unexpected("anything before hello".into()));
+    }
+
+    match message {
+        Message::Hello { version, name } => {
+            if version != PROTOCOL_VERSION {
+                return Err(NetError::WrongVersion(version));
+            }
+            network.greeted = true;
+
+            // A server says who everyone is when it puts us in a lobby
+            if settings.lobby().is_some() {
+                return Ok(());
+            }
+
+            info!("Playing against {name}");
+            if let Some(seat) = &network.seat {
+                network.names[1 - seat_index(seat)] = Some(name);
+            }
+
+            // Whoever just joined (or came back) gets the game as it is now
+            if settings.is_host() {
+                network.send_game(game);
+            }
+        }
+
+        Message::Lobby {
+            code,
+            seat,
+            players,
+            ..
+        } if settings.lobby().is_some() => {
+            network.seat = match seat {
+                Some(1) => Some(PlayerNum::Player1),
+                Some(2) => Some(PlayerNum::Player2),
+                _ => None,
+            };
+            network.names = players;
+            network.code = Some(code);
+        }
+
+        Message::Game { game: snapshot } if !settings.is_host() => {
+            *game = snapshot.to_game()?;
+            network.agreed = game.moves().to_vec();
+            network.syncing = false;
+            adopt_game(command, players, game);
+        }
+
+        // The guest wants a new game, which it can have once the last one is finished
+        Message::Game { game: snapshot } => {
+            if game.is_over() {
+                *game = snapshot.to_game()?;
+                adopt_game(command, players, game);
+            }
+            network.send_game(game);
+        }
+
+        Message::Move { index, notation } => {
+            // Everything up to the game turning up is going to be wrong anyway
+            if network.syncing {
+                return Ok(());
+            }
+
+            let to_move: PlayerNum = game.to_move().into();
+            let result = if network.seat.as_ref() == Some(&to_move) {
+                Err(NetError::IllegalMove {
+                    notation,
+                    reason: "its not their turn".into(),
+                })
+            } else {
+                check_move(game, index, &notation)
+            };
+
+            match result {
+                Ok(played) => {
+                    game.play_move(played)
+                        .expect("Tried to play a network move that was already checked");
+                    network.agreed.push(played);
+                    play_move_event.send(PlayMoveEvent { played });
+                }
+                // Not worth giving up over, the two sides just need to agree on the game again
+                Err(error) => {
+                    warn!("Getting back in sync with the other side: {error}");
+                    if settings.is_host() {
+                        network.send_game(game);
+                    } else {
+                        network.ask_for_game();
+                    }
+                }
+            }
+        }
+
+        // Whatever the other side sends, it still only gets shown once it has been cleaned up here
+        Message::Chat { from, text } => {
+            let opponent = network
+                .seat
+                .as_ref()
+                .and_then(|seat| network.names[1 - seat_index(seat)].clone());
+
+            // A server has already put the right name on it, and kept everyone to the limit
+            // Without one, the other side could say it was anyone, and ignore its own limit
+            let from = if settings.lobby().is_some() {
+                Some(from)
+            } else if network.chat_limit.allow() {
+                opponent
+            } else {
+                warn!("The other side is chatting too fast, dropped a message");
+                None
+            };
+
+            if let (Some(from), Some(text)) = (from, clean_chat(&text)) {
+                log.push(&from, &text);
+            }
+        }
+
+        Message::Clock {
+            control,
+            remaining,
+            used,
+            flagged,
+        } if !settings.is_host() => {
+            let (new_clocks, used) = read_clock(&control, remaining, used, flagged)?;
+            clocks.0 = Some(new_clocks);
+            stopwatch.set_elapsed(used);
+        }
+
+        Message::Sync if settings.is_host() => network.send_game(game),
+
+        Message::Sync => return Err(NetError::Unexpected("the host to ask for the game".into())),
+        Message::Clock { .. } => return Err(NetError::Unexpected("the guest to keep time".into())),
+        Message::Lobby { .. } => {
+            return Err(NetError::Unexpected("a lobby without a server".into()))
+        }
+        Message::Create { .. } | Message::Join { .. } | Message::Watch { .. } => {
+            return Err(NetError::Unexpected(
+                "lobby requests, this isnt a server".into(),
+            ))
+        }
+
+        // Both get dealt with by the connection itself
+        Message::Ping | Message::Error { .. } => {}
+    }
+
+    Ok(())
+}
+
+// Keeps the remote seats as remote (starting a new game from the menus puts them back to whatever was picked),
+// and the local one waiting while there is no one to play
+fn update_network_seats(
+    settings: Res<NetworkSettings>,
+    mut network: ResMut<Network>,
+    mut players: Query<&mut Player>,
+    mut current_player: ResMut<CurrentPlayer>,
+) {
+    let ready = network.opponent_here();
+
+    for mut player in &mut players {
+        if network.seat.as_ref() != Some(&player.num) {
+            if player.kind != PlayerKind::Remote {
+                player.kind = PlayerKind::Remote;
+            }
+            if let Some(name) = &network.names[seat_index(&player.num)] {
+                if player.name != *name {
+                    player.name = name.clone();
+                }
+            }
+        } else {
+            if player.name != settings.name {
+                player.name = settings.name.clone();
+            }
+
+            match (ready, player.kind == PlayerKind::Remote) {
+                (true, true) => {
+                    player.kind = network.local_kind.take().unwrap_or(PlayerKind::Human)
+                }
+                (false, false) => {
+                    network.local_kind = Some(player.kind.clone());
+                    player.kind = PlayerKind::Remote;
+                }
+                _ => {}
+            }
+        }
+
+        if player.num == current_player.num && *player != **current_player {
+            **current_player = player.clone();
+        }
+    }
+}
+
+fn update_reconnect_key(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<NetworkSettings>,
+    mut network: ResMut<Network>,
+) {
+    if !keys.just_pressed(RECONNECT_KEY) {
+        return;
+    }
+
+    // The host just keeps listening, so only the guest has anything to do
+    let NetworkRole::Join { address, .. } = &settings.role else {
+        return;
+    };
+
+    if network.connection.is_none() && network.connecting.is_none() {
+        start_guest_connecting(&mut network, address);
+    }
+}
+
+#[derive(Component)]
+struct NetworkText;
+
+fn spawn_network_text(mut command: Commands) {
+    command.spawn((
+        TextBundle::from_section("", TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(5.0),
+            bottom: Val::Px(5.0),
+            ..default()
+        }),
+        NetworkText,
+    ));
+}
+
+fn network_status(settings: &NetworkSettings, network: &Network) -> String {
+    let opponent = network
+        .seat
+        .as_ref()
+        .and_then(|seat| network.names[1 - seat_index(seat)].as_deref());
+
+    let mut text = match (&settings.role, &network.connection) {
+        (_, Some(connection)) if network.greeted => {
+            let place = match &network.code {
+                Some(code) => format!(" in lobby {code} at {}", connection.peer()),
+                None => format!(" at {}", connection.peer()),
+            };
+
+            match (&network.seat, opponent) {
+                (None, _) => format!("Watching{place}"),
+                (Some(_), Some(name)) => format!("Playing {name}{place}"),
+                (Some(_), None) => format!("Waiting for someone to play{place}"),
+            }
+        }
+        (_, Some(connection)) => format!("Connected to {}, saying hello", connection.peer()),
+        (NetworkRole::Host { port }, None) if network.listener.is_some() => {
+            format!("Hosting on port {port}, waiting for someone to join")
+        }
+        (NetworkRole::Host { .. }, None) => "Not hosting".to_string(),
+        (NetworkRole::Join { address, .. }, None) if network.connecting.is_some() => {
+            format!("Connecting to {address}...")
+        }
+        (NetworkRole::Join { address, .. }, None) => {
+            format!("Not connected to {address}, press R to try again")
+        }
+    };
+
+    if network.syncing {
+        text.push_str("\nCatching up with the host...");
+    }
+    if let Some(problem) = &network.problem {
+        text = format!("{text}\n({problem})");
+    }
+    text
+}
+
+fn update_network_text(
+    settings: Res<NetworkSettings>,
+    network: Res<Network>,
+    mut texts: Query<&mut Text, With<NetworkText>>,
+) {
+    let status = network_status(&settings, &network);
+
+    for mut text in &mut texts {
+        if text.sections[0].value != status {
+            text.sections[0].value = status.clone();
+        }
+    }
+}
@@ -0,0 +1,459 @@
+// The usual way of writing down a connect 4 game: every move as a 1-based column number, in order
+// eg "4453" is the first player in the middle, the second player on top of them, and so on
+// With popout, a pop is the column with a p in front, so "p4" pops the bottom chip out of the middle
+// Other variants add a few more: specials are their letter in front ("a4" is an anvil, see `Special::letter`),
+// and without gravity a chip is the column then the row as a capital letter, from A at the bottom ("2B")
+
+use crate::rules::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Deref;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NotationError {
+    // `index` is where in the string the bad move is, counting from 0
+    InvalidCharacter { index: usize, character: char },
+    // A letter at the very end, with no column after it
+    MissingColumn { index: usize },
+    ColumnOutOfRange { index: usize, column: usize },
+    ColumnFull { index: usize, column: usize },
+    CantPop { index: usize, column: usize },
+    GameOver { index: usize },
+    // Anything else the rules wont allow
+    Illegal { index: usize, error: MoveError },
+}
+
+impl NotationError {
+    pub fn index(&self) -> usize {
+        match *self {
+            NotationError::InvalidCharacter { index, .. } => index,
+            NotationError::MissingColumn { index } => index,
+            NotationError::ColumnOutOfRange { index, .. } => index,
+            NotationError::ColumnFull { index, .. } => index,
+            NotationError::CantPop { index, .. } => index,
+            NotationError::GameOver { index } => index,
+            NotationError::Illegal { index, .. } => index,
+        }
+    }
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotationError::InvalidCharacter { index, character } => {
+                write!(f, "'{character}' at index {index} is not a column number")
+            }
+            NotationError::MissingColumn { index } => {
+                write!(f, "the move at index {index} needs a column after it")
+            }
+            NotationError::ColumnOutOfRange { index, column } => write!(
+                f,
+                "column {column} at index {index} does not exist on this board"
+            ),
+            NotationError::ColumnFull { index, column } => {
+                write!(f, "column {column} at index {index} is already full")
+            }
+            NotationError::CantPop { index, column } => {
+                write!(f, "column {column} at index {index} cant be popped from")
+            }
+            NotationError::GameOver { index } => {
+                write!(
+                    f,
+                    "the move at index {index} is after the game was already over"
+                )
+            }
+            NotationError::Illegal { index, error } => {
+                write!(f, "the move at index {index} isnt allowed, {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+// Seeing the same position this many times is a draw
+const REPETITION_LIMIT: usize = 3;
+
+// Why someone lost without a line being made
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ForfeitReason {
+    // Their clock ran out
+    Time,
+    // Their engine crashed, stopped answering, or tried to play something it couldnt
+    Engine,
+}
+
+/// A position, along with every move that got there
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Game {
+    position: Position,
+    moves: Vec<Move>,
+    // How many times each position has come up, for the repetition draw
+    // (which can only happen when chips come out, since otherwise the board only ever fills up)
+    seen: HashMap<(u128, Side, VariantState), usize>,
+    // Whoever gave the game up, and why, since the position only knows who won
+    forfeited: Option<(Side, ForfeitReason)>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::from_position(Position::default())
+    }
+}
+
+// Read only, so moves cant skip the history
+impl Deref for Game {
+    type Target = Position;
+
+    fn deref(&self) -> &Position {
+        &self.position
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game::default()
+    }
+
+    pub fn with_config(config: BoardConfig) -> Self {
+        Game::with_rules(config, Rules::Standard)
+    }
+
+    pub fn with_rules(config: BoardConfig, rules: Rules) -> Self {
+        Game::from_position(Position::with_rules(config, rules))
+    }
+
+    // For when all we have is the chips on the board, and not the order they went in
+    pub fn from_position(position: Position) -> Self {
+        let mut seen = HashMap::new();
+        seen.insert(Game::repetition_key(&position), 1);
+
+        Game {
+            position,
+            moves: Vec::new(),
+            seen,
+            forfeited: None,
+        }
+    }
+
+    // For a standard board
+    pub fn from_notation(notation: &str) -> Result<Self, NotationError> {
+        Game::from_notation_with_config(BoardConfig::STANDARD, notation)
+    }
+
+    // For standard rules
+    pub fn from_notation_with_config(
+        config: BoardConfig,
+        notation: &str,
+    ) -> Result<Self, NotationError> {
+        Game::from_notation_with_rules(config, Rules::Standard, notation)
+    }
+
+    pub fn from_notation_with_rules(
+        config: BoardConfig,
+        rules: Rules,
+        notation: &str,
+    ) -> Result<Self, NotationError> {
+        let mut game = Game::with_rules(config, rules);
+
+        // Ignore whitespace around the moves, but still count it in the index
+        let leading = notation.chars().take_while(|c| c.is_whitespace()).count();
+        let mut moves = notation
+            .trim()
+            .chars()
+            .enumerate()
+            .map(|(index, c)| (index + leading, c))
+            .peekable();
+
+        while let Some((index, character)) = moves.next() {
+            let (next, column) = read_move(&mut moves, index, character)?;
+
+            match game.play_move(next) {
+                Ok(_) => {}
+                Err(MoveError::ColumnOutOfRange(_)) => {
+                    return Err(NotationError::ColumnOutOfRange { index, column })
+                }
+                Err(MoveError::ColumnFull(_)) => {
+                    return Err(NotationError::ColumnFull { index, column })
+                }
+                Err(MoveError::CantPop(_)) => return Err(NotationError::CantPop { index, column }),
+                Err(MoveError::GameOver) => return Err(NotationError::GameOver { index }),
+                Err(error) => return Err(NotationError::Illegal { index, error }),
+            }
+        }
+
+        Ok(game)
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn forfeited(&self) -> Option<(Side, ForfeitReason)> {
+        self.forfeited
+    }
+
+    // Drops a chip into a column
+    pub fn play(&mut self, col: usize) -> Result<MoveOutcome, MoveError> {
+        self.play_move(Move::Drop(col))
+    }
+
+    pub fn play_move(&mut self, next: Move) -> Result<MoveOutcome, MoveError> {
+        let outcome = self.position.play_move(next)?;
+        self.moves.push(next);
+
+        let key = Game::repetition_key(&self.position);
+        let seen = self.seen.entry(key).or_insert(0);
+        *seen += 1;
+
+        if *seen >= REPETITION_LIMIT && !self.position.is_over() {
+            self.position.set_drawn();
+            return Ok(MoveOutcome::Draw { row: outcome.row() });
+        }
+
+        Ok(outcome)
+    }
+
+    // Anything that makes two positions different, not just the chips
+    fn repetition_key(position: &Position) -> (u128, Side, VariantState) {
+        (position.key(), position.to_move(), *position.state())
+    }
+
+    // Gives the game to the other side, for when someone cant carry on (like an engine crashing)
+    // Nothing happens if the game is already over
+    pub fn forfeit(&mut self, side: Side, reason: ForfeitReason) {
+        if !self.position.is_over() {
+            self.position.set_winner(side.other());
+            self.forfeited = Some((side, reason));
+        }
+    }
+
+    // Takes back the last move, giving it back
+    // None if there are no moves, or we dont know what they were
+    pub fn undo(&mut self) -> Option<Move> {
+        if self.moves.len() != self.position.moves_played() {
+            return None;
+        }
+
+        let mut moves = std::mem::take(&mut self.moves);
+        let last = moves.pop()?;
+
+        // Positions cant go backwards, so play everything else again from the start
+        let mut game = Game::with_rules(*self.position.config(), self.position.rules());
+        for next in moves {
+            game.play_move(next)
+                .expect("Tried to replay a move that was already played once");
+        }
+        *self = game;
+
+        Some(last)
+    }
+
+    // None if we dont know every move that was played
+    pub fn to_notation(&self) -> Option<String> {
+        if self.moves.len() != self.position.moves_played() {
+            return None;
+        }
+
+        Some(self.moves.iter().map(|&next| move_notation(next)).collect())
+    }
+}
+
+// How one move gets written down
+pub fn move_notation(next: Move) -> String {
+    match next {
+        Move::Drop(col) => (col + 1).to_string(),
+        Move::Pop(col) => format!("p{}", col + 1),
+        Move::Place { row, col } => format!("{}{}", col + 1, (b'A' + row as u8) as char),
+        Move::Special(special, col) => format!("{}{}", special.letter(), col + 1),
+    }
+}
+
+// Just one move on its own, like someone typing in their turn
+pub fn parse_move(text: &str) -> Result<Move, NotationError> {
+    let leading = text.chars().take_while(|c| c.is_whitespace()).count();
+    let mut chars = text
+        .trim()
+        .chars()
+        .enumerate()
+        .map(|(index, c)| (index + leading, c))
+        .peekable();
+
+    let Some((index, character)) = chars.next() else {
+        return Err(NotationError::MissingColumn { index: leading });
+    };
+    let (next, _) = read_move(&mut chars, index, character)?;
+
+    match chars.next() {
+        Some((index, character)) => Err(NotationError::InvalidCharacter { index, character }),
+        None => Ok(next),
+    }
+}
+
+// Reads the rest of the move starting with `character`, giving back the move and the column as it was written
+fn read_move(
+    moves: &mut Peekable<impl Iterator<Item = (usize, char)>>,
+    index: usize,
+    character: char,
+) -> Result<(Move, usize), NotationError> {
+    // Pops and specials have a letter before the column
+    let is_letter = character == 'p' || Special::from_letter(character).is_some();
+    let kind = is_letter.then_some(character);
+    let character = if kind.is_some() {
+        match moves.next() {
+            Some((_, character)) => character,
+            None => return Err(NotationError::MissingColumn { index }),
+        }
+    } else {
+        character
+    };
+
+    let Some(column) = character.to_digit(10) else {
+        return Err(NotationError::InvalidCharacter { index, character });
+    };
+    let column = column as usize;
+
+    // Column 0 would underflow, and its out of range anyway
+    let col = column.checked_sub(1).unwrap_or(usize::MAX);
+
+    // And placed chips have their row after it
+    let row = moves
+        .next_if(|(_, character)| character.is_ascii_uppercase())
+        .map(|(_, character)| (character as u8 - b'A') as usize);
+
+    let next = match (kind, row) {
+        (None, None) => Move::Drop(col),
+        (None, Some(row)) => Move::Place { row, col },
+        (Some('p'), None) => Move::Pop(col),
+        (Some(character), None) => match Special::from_letter(character) {
+            Some(special) => Move::Special(special, col),
+            None => return Err(NotationError::InvalidCharacter { index, character }),
+        },
+        (Some(character), Some(_)) => {
+            return Err(NotationError::InvalidCharacter { index, character })
+        }
+    };
+
+    Ok((next, column))
+}
+
+impl FromStr for Game {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Game::from_notation(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_moves_say_where_they_are() {
+        assert_eq!(
+            Game::from_notation("1111111"),
+            Err(NotationError::ColumnFull {
+                index: 6,
+                column: 1
+            })
+        );
+        assert_eq!(
+            Game::from_notation("448"),
+            Err(NotationError::ColumnOutOfRange {
+                index: 2,
+                column: 8
+            })
+        );
+        // Column 0 doesnt exist either
+        assert_eq!(
+            Game::from_notation("40"),
+            Err(NotationError::ColumnOutOfRange {
+                index: 1,
+                column: 0
+            })
+        );
+        // The first player has four down the left by then
+        assert_eq!(
+            Game::from_notation("12121214"),
+            Err(NotationError::GameOver { index: 7 })
+        );
+        assert_eq!(
+            Game::from_notation("44x3"),
+            Err(NotationError::InvalidCharacter {
+                index: 2,
+                character: 'x'
+            })
+        );
+        // Whitespace at the start still counts
+        assert_eq!(
+            Game::from_notation("  4x"),
+            Err(NotationError::InvalidCharacter {
+                index: 3,
+                character: 'x'
+            })
+        );
+        assert_eq!(
+            Game::from_notation("44p"),
+            Err(NotationError::MissingColumn { index: 2 })
+        );
+    }
+
+    #[test]
+    fn notation_round_trips() {
+        let cases = [
+            (Rules::Standard, "4453"),
+            (Rules::PopOut, "1212p1"),
+            (Rules::PowerUp, "4a4b4d33"),
+            (Rules::NoGravity, "2B1A3C"),
+        ];
+
+        for (rules, notation) in cases {
+            let config = rules.variant().default_config();
+            let game = Game::from_notation_with_rules(config, rules, notation).unwrap();
+            assert_eq!(game.to_notation().as_deref(), Some(notation), "{rules:?}");
+            assert_eq!(game.moves().len(), game.moves_played());
+        }
+
+        // A game made from just the chips doesnt know what order they went in
+        let game = Game::from_position(*Game::from_notation("4453").unwrap().position());
+        assert_eq!(game.to_notation(), None);
+    }
+
+    #[test]
+    fn the_same_position_three_times_is_a_draw() {
+        // Each time round, both players drop a chip and pop it straight back out, which empties the board
+        let once = "12p1p2";
+        let game =
+            Game::from_notation_with_rules(BoardConfig::STANDARD, Rules::PopOut, once).unwrap();
+        assert!(!game.is_over());
+
+        // The empty board with the first player to move has come up for the third time
+        let twice = once.repeat(2);
+        let game =
+            Game::from_notation_with_rules(BoardConfig::STANDARD, Rules::PopOut, &twice).unwrap();
+        assert!(game.is_draw());
+        assert_eq!(game.winner(), None);
+
+        assert_eq!(
+            Game::from_notation_with_rules(BoardConfig::STANDARD, Rules::PopOut, &(twice + "1")),
+            Err(NotationError::GameOver { index: 12 })
+        );
+
+        // The last pop is what makes it a draw
+        let mut game =
+            Game::from_notation_with_rules(BoardConfig::STANDARD, Rules::PopOut, "12p1p212p1")
+                .unwrap();
+        assert_eq!(
+            game.play_move(Move::Pop(1)),
+            Ok(MoveOutcome::Draw { row: 0 })
+        );
+    }
+}
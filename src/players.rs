@@ -1,3 +1,6 @@
+use crate::ai::DEFAULT_DEPTH;
+use crate::board::Board;
+use crate::rules::Side;
 use bevy::{
     prelude::*,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
@@ -7,9 +10,108 @@ use bevy::{
 pub struct Player {
     pub name: String,
     pub num: PlayerNum,
+    pub kind: PlayerKind,
 }
 
-#[derive(Clone, PartialEq, Component)]
+// Who is actually making the moves for a player
+#[derive(Clone, PartialEq, Debug)]
+pub enum PlayerKind {
+    Human,
+    Ai { depth: u32 },
+    // Another program, talking over the protocol in `engine`
+    Engine { command: String },
+    // Someone playing from another computer, see `network`
+    // Only ever set by `--host` or `--join`, so it cant be picked on the command line
+    Remote,
+}
+
+impl std::str::FromStr for PlayerKind {
+    type Err = String;
+
+    // Either "human", "ai", "ai:<depth>", or "engine:<command>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Commands can have capitals in, so this is checked before lowercasing
+        if let Some(command) = s.trim().strip_prefix("engine:") {
+            if command.trim().is_empty() {
+                return Err("engine: needs a command after it, eg engine:./my-bot".into());
+            }
+            return Ok(PlayerKind::Engine {
+                command: command.trim().to_string(),
+            });
+        }
+
+        match s.trim().to_lowercase().as_str() {
+            "human" => Ok(PlayerKind::Human),
+            "ai" => Ok(PlayerKind::Ai {
+                depth: DEFAULT_DEPTH,
+            }),
+            other => {
+                let Some(depth) = other.strip_prefix("ai:") else {
+                    return Err(format!(
+                        "unknown player type '{s}', expected human, ai, ai:<depth> or engine:<command>"
+                    ));
+                };
+
+                match depth.parse() {
+                    Ok(depth) => Ok(PlayerKind::Ai { depth }),
+                    Err(error) => Err(format!("invalid ai depth '{depth}': {error}")),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for PlayerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerKind::Human => write!(f, "human"),
+            PlayerKind::Ai { depth } => write!(f, "ai:{depth}"),
+            PlayerKind::Engine { command } => write!(f, "engine:{command}"),
+            PlayerKind::Remote => write!(f, "remote"),
+        }
+    }
+}
+
+// What each player should be, picked before the game starts
+#[derive(Resource, Clone)]
+pub struct PlayerKinds {
+    pub player1: PlayerKind,
+    pub player2: PlayerKind,
+}
+
+impl Default for PlayerKinds {
+    fn default() -> Self {
+        PlayerKinds {
+            player1: PlayerKind::Human,
+            player2: PlayerKind::Human,
+        }
+    }
+}
+
+impl PlayerKinds {
+    // Reads `--player1 <kind>` and `--player2 <kind>` from the command line
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut kinds = PlayerKinds::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let kind = match arg.as_str() {
+                "--player1" => &mut kinds.player1,
+                "--player2" => &mut kinds.player2,
+                _ => continue,
+            };
+
+            let Some(value) = args.next() else {
+                return Err(format!("{arg} needs a player type after it"));
+            };
+            *kind = value.parse()?;
+        }
+
+        Ok(kinds)
+    }
+}
+
+#[derive(Clone, PartialEq, Component, Reflect)]
 pub enum PlayerNum {
     Player1,
     Player2,
@@ -17,6 +119,25 @@ pub enum PlayerNum {
 pub use PlayerNum::Player1;
 pub use PlayerNum::Player2;
 
+// Player 1 always plays first
+impl From<Side> for PlayerNum {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::First => Player1,
+            Side::Second => Player2,
+        }
+    }
+}
+
+impl From<PlayerNum> for Side {
+    fn from(num: PlayerNum) -> Self {
+        match num {
+            Player1 => Side::First,
+            Player2 => Side::Second,
+        }
+    }
+}
+
 #[derive(Deref, DerefMut, Resource)]
 pub struct CurrentPlayer(pub Player);
 
@@ -25,24 +146,33 @@ pub struct NextPlayerEvent;
 
 pub fn plugin_players(app: &mut App) {
     app.add_event::<NextPlayerEvent>();
+    app.init_resource::<PlayerKinds>();
     app.add_systems(Startup, create_players);
     app.add_systems(Startup, create_current_player.after(create_players));
     app.add_systems(Update, next_player_event_handler);
 }
 
-fn create_players(mut command: Commands) {
+fn create_players(mut command: Commands, kinds: Res<PlayerKinds>) {
     command.spawn(Player {
         name: "Player 1".into(),
         num: PlayerNum::Player1,
+        kind: kinds.player1.clone(),
     });
     command.spawn(Player {
         name: "Player 2".into(),
         num: PlayerNum::Player2,
+        kind: kinds.player2.clone(),
     });
 }
 
-fn create_current_player(mut command: Commands, players: Query<&Player>) {
-    command.insert_resource(CurrentPlayer(get_player1(&players)))
+fn create_current_player(mut command: Commands, players: Query<&Player>, board: Res<Board>) {
+    // The game might not be starting from an empty board
+    let player = match PlayerNum::from(board.to_move()) {
+        Player1 => get_player1(&players),
+        Player2 => get_player2(&players),
+    };
+
+    command.insert_resource(CurrentPlayer(player))
 }
 
 pub fn get_player1(players: &Query<&Player>) -> Player {
@@ -59,15 +189,19 @@ pub fn get_player2(players: &Query<&Player>) -> Player {
     }
 }
 
+// Some variants give out extra turns, so its whoever the board says, rather than just the other player
 pub fn next_player_event_handler(
     mut events: EventReader<NextPlayerEvent>,
+    board: Res<Board>,
     players: Query<&Player>,
     mut current_player: ResMut<CurrentPlayer>,
 ) {
-    for event in events.read() {
-        match current_player.num {
-            Player1 => **current_player = get_player2(&players),
-            Player2 => **current_player = get_player1(&players),
-        }
+    if events.read().count() == 0 {
+        return;
+    }
+
+    match PlayerNum::from(board.to_move()) {
+        Player1 => **current_player = get_player1(&players),
+        Player2 => **current_player = get_player2(&players),
     }
 }
@@ -0,0 +1,526 @@
+// Watching a saved game (or a move string) play back on the board, from `--replay <save or moves>` or the
+// main menu. It plays by itself, or step through it with the arrow keys, Home and End jump to either end,
+// and typing a move number then Enter jumps to that move. Up and Down change how fast it plays
+// Play From Here (or P) takes over from wherever the replay is up to, as a new game with the same players
+// Going forward one move drops the chip in like any other move, anything else just swaps the board over
+
+use crate::animation::{is_animating, FallingChip};
+use crate::board::{AppState, Board, PlayMoveEvent};
+use crate::history::MoveHistory;
+use crate::network::NetworkSettings;
+use crate::notation::{move_notation, Game};
+use crate::players::{CurrentPlayer, Player, PlayerNum};
+use crate::rules::{BoardConfig, Rules};
+use crate::save_load::CheckedSave;
+
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+
+const PLAY_KEY: KeyCode = KeyCode::Space;
+const FIRST_KEY: KeyCode = KeyCode::Home;
+const BACK_KEY: KeyCode = KeyCode::ArrowLeft;
+const FORWARD_KEY: KeyCode = KeyCode::ArrowRight;
+const LAST_KEY: KeyCode = KeyCode::End;
+const FASTER_KEY: KeyCode = KeyCode::ArrowUp;
+const SLOWER_KEY: KeyCode = KeyCode::ArrowDown;
+const JUMP_KEY: KeyCode = KeyCode::Enter;
+const PLAY_FROM_HERE_KEY: KeyCode = KeyCode::KeyP;
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+// How long each move stays up at normal speed
+const SECONDS_PER_MOVE: f32 = 1.0;
+// Up and Down go through these, in order
+const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1., 2., 4.];
+const NORMAL_SPEED: usize = 2;
+
+// Where the game to play back comes from
+#[derive(Clone, PartialEq, Debug)]
+pub enum ReplaySource {
+    Save(PathBuf),
+    // In the move notation, played with whatever rules and board size are set up
+    Moves(String),
+}
+
+#[derive(Event)]
+pub struct StartReplayEvent {
+    pub source: ReplaySource,
+}
+
+// A replay to start as soon as the game does, from `--replay <save or moves>`
+#[derive(Resource, Default)]
+pub struct StartupReplay(pub Option<ReplaySource>);
+
+impl StartupReplay {
+    // Anything that is a file is taken as a save, and anything else as moves
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--replay") else {
+            return Ok(StartupReplay(None));
+        };
+
+        match args.get(index + 1) {
+            Some(arg) if Path::new(arg).is_file() => {
+                Ok(StartupReplay(Some(ReplaySource::Save(arg.into()))))
+            }
+            Some(arg) => Ok(StartupReplay(Some(ReplaySource::Moves(arg.clone())))),
+            None => Err("--replay needs a save or a move sequence after it, eg 4453".into()),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Replay {
+    // Every move, even the ones that havent been shown yet
+    game: Game,
+    // How many moves in the board should be, which it catches up to once any falling chip has landed
+    shown: usize,
+    playing: bool,
+    speed: usize,
+    // Since the last move went in, while playing
+    waited: f32,
+    // The length the board was when the next move was sent, until it goes in
+    sent: Option<usize>,
+    // A move number being typed in, to jump to
+    typed: String,
+}
+
+impl Replay {
+    pub fn total(&self) -> usize {
+        self.game.moves().len()
+    }
+
+    fn speed(&self) -> f32 {
+        REPLAY_SPEEDS[self.speed]
+    }
+
+    fn go_to(&mut self, moves: usize) {
+        self.shown = moves.min(self.total());
+        self.playing = false;
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+enum ReplayButton {
+    First,
+    Back,
+    PlayPause,
+    Forward,
+    Last,
+    Slower,
+    Faster,
+    PlayFromHere,
+}
+
+#[derive(Component)]
+struct ReplayText;
+
+pub fn plugin_replay(app: &mut App) {
+    app.add_event::<StartReplayEvent>();
+    app.init_resource::<StartupReplay>();
+    app.init_resource::<Replay>();
+
+    // The players have to exist before their names can be set
+    app.add_systems(PostStartup, start_startup_replay);
+    app.add_systems(OnEnter(AppState::Replay), spawn_replay_controls);
+
+    app.add_systems(Update, update_start_replay);
+    app.add_systems(
+        Update,
+        (
+            update_replay_keys,
+            update_replay_buttons,
+            update_replay_playing,
+            update_replay_board,
+            update_replay_text,
+        )
+            .chain()
+            .run_if(in_state(AppState::Replay)),
+    );
+}
+
+fn start_startup_replay(
+    startup_replay: Res<StartupReplay>,
+    mut start_replay_event: EventWriter<StartReplayEvent>,
+) {
+    if let Some(source) = &startup_replay.0 {
+        start_replay_event.send(StartReplayEvent {
+            source: source.clone(),
+        });
+    }
+}
+
+// The game to play back, and the players from the save if it came from one
+fn read_replay(
+    source: &ReplaySource,
+    config: BoardConfig,
+    rules: Rules,
+) -> Result<(Game, Option<CheckedSave>), String> {
+    match source {
+        ReplaySource::Save(path) => {
+            let save = CheckedSave::read(path)?;
+            // Saves of boards that were set up by hand dont have the moves to play back
+            if save.game.to_notation().is_none() {
+                return Err(format!(
+                    "Cant replay {}, it doesnt have the moves that were played",
+                    path.display()
+                ));
+            }
+            Ok((save.game.clone(), Some(save)))
+        }
+        ReplaySource::Moves(moves) => match Game::from_notation_with_rules(config, rules, moves) {
+            Ok(game) => Ok((game, None)),
+            Err(error) => Err(format!("Cant replay '{moves}': {error}")),
+        },
+    }
+}
+
+// Sets everything up at the start of the game, then plays it back from there
+#[allow(clippy::too_many_arguments)]
+fn update_start_replay(
+    mut command: Commands,
+    mut events: EventReader<StartReplayEvent>,
+    config: Res<BoardConfig>,
+    rules: Res<Rules>,
+    network: Option<Res<NetworkSettings>>,
+    app_state: Res<State<AppState>>,
+    mut players: Query<&mut Player>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for event in events.read() {
+        // Both sides have to be playing the same game
+        let result = if network.is_some() {
+            Err("Replays cant be watched during a network game".to_string())
+        } else {
+            read_replay(&event.source, *config, *rules)
+        };
+
+        let (game, save) = match result {
+            Ok(replay) => replay,
+            Err(error) => {
+                error!("{error}");
+                // Otherwise a bad `--replay` would be stuck on an empty replay
+                if *app_state.get() == AppState::Replay {
+                    next_state.set(AppState::Menu);
+                }
+                continue;
+            }
+        };
+
+        let board = Board::new(*game.config(), game.rules());
+        let to_move: PlayerNum = board.to_move().into();
+
+        for mut player in &mut players {
+            if let Some(save) = &save {
+                save.apply_to(&mut player);
+            }
+
+            if player.num == to_move {
+                command.insert_resource(CurrentPlayer(player.clone()));
+            }
+        }
+
+        info!("Replaying {} moves", game.moves().len());
+
+        command.insert_resource(*board.config());
+        command.insert_resource(board.rules());
+        command.insert_resource(MoveHistory::default());
+        command.insert_resource(board);
+        command.insert_resource(Replay {
+            game,
+            playing: true,
+            speed: NORMAL_SPEED,
+            ..default()
+        });
+        next_state.set(AppState::Replay);
+    }
+}
+
+fn spawn_replay_controls(mut command: Commands) {
+    command
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    bottom: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(5.0),
+                    ..default()
+                },
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StateScoped(AppState::Replay),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", TextStyle::default())
+                    .with_text_justify(JustifyText::Center),
+                ReplayText,
+            ));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(5.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (button, label) in [
+                        (ReplayButton::First, "|<"),
+                        (ReplayButton::Back, "<"),
+                        (ReplayButton::PlayPause, ""),
+                        (ReplayButton::Forward, ">"),
+                        (ReplayButton::Last, ">|"),
+                        (ReplayButton::Slower, "Slower"),
+                        (ReplayButton::Faster, "Faster"),
+                        (ReplayButton::PlayFromHere, "Play From Here"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    background_color: Color::BLACK.into(),
+                                    ..default()
+                                },
+                                button,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(label, TextStyle::default()));
+                            });
+                    }
+                });
+        });
+}
+
+fn press(
+    button: ReplayButton,
+    replay: &mut Replay,
+    board: &Board,
+    next_state: &mut NextState<AppState>,
+) {
+    let at = replay.shown;
+
+    match button {
+        ReplayButton::First => replay.go_to(0),
+        ReplayButton::Back => replay.go_to(at.saturating_sub(1)),
+        ReplayButton::Forward => replay.go_to(at + 1),
+        ReplayButton::Last => replay.go_to(replay.total()),
+        // Playing from the end starts again from the beginning
+        ReplayButton::PlayPause if !replay.playing && at == replay.total() => {
+            replay.go_to(0);
+            replay.playing = true;
+        }
+        ReplayButton::PlayPause => replay.playing = !replay.playing,
+        ReplayButton::Slower => replay.speed = replay.speed.saturating_sub(1),
+        ReplayButton::Faster => replay.speed = (replay.speed + 1).min(REPLAY_SPEEDS.len() - 1),
+        // Not until the board has caught up, or the new game would start from the wrong place
+        ReplayButton::PlayFromHere if board.moves().len() == at && replay.sent.is_none() => {
+            replay.playing = false;
+            next_state.set(if board.is_over() {
+                AppState::GameOver
+            } else {
+                AppState::InGame
+            });
+        }
+        ReplayButton::PlayFromHere => {}
+    }
+}
+
+fn update_replay_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    board: Res<Board>,
+    mut replay: ResMut<Replay>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (digit, key) in DIGIT_KEYS.into_iter().enumerate() {
+        if keys.just_pressed(key) && replay.typed.len() < 4 {
+            replay.typed.push_str(&digit.to_string());
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        replay.typed.pop();
+    }
+
+    if keys.just_pressed(JUMP_KEY) {
+        if let Ok(moves) = std::mem::take(&mut replay.typed).parse() {
+            replay.go_to(moves);
+        }
+    }
+
+    for (key, button) in [
+        (PLAY_KEY, ReplayButton::PlayPause),
+        (FIRST_KEY, ReplayButton::First),
+        (BACK_KEY, ReplayButton::Back),
+        (FORWARD_KEY, ReplayButton::Forward),
+        (LAST_KEY, ReplayButton::Last),
+        (SLOWER_KEY, ReplayButton::Slower),
+        (FASTER_KEY, ReplayButton::Faster),
+        (PLAY_FROM_HERE_KEY, ReplayButton::PlayFromHere),
+    ] {
+        if keys.just_pressed(key) {
+            press(button, &mut replay, &board, &mut next_state);
+        }
+    }
+}
+
+fn update_replay_buttons(
+    buttons: Query<(&Interaction, &ReplayButton), Changed<Interaction>>,
+    board: Res<Board>,
+    mut replay: ResMut<Replay>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, &button) in &buttons {
+        if *interaction == Interaction::Pressed {
+            press(button, &mut replay, &board, &mut next_state);
+        }
+    }
+}
+
+// The next move goes in once the last one has been up for long enough, counting from when its chip landed
+fn update_replay_playing(
+    time: Res<Time>,
+    board: Res<Board>,
+    falling: Query<(), With<FallingChip>>,
+    mut replay: ResMut<Replay>,
+) {
+    if !replay.playing {
+        return;
+    }
+
+    if replay.shown >= replay.total() {
+        replay.playing = false;
+        return;
+    }
+
+    if board.moves().len() != replay.shown || is_animating(&falling) {
+        replay.waited = 0.;
+        return;
+    }
+
+    replay.waited += time.delta_seconds();
+    if replay.waited >= SECONDS_PER_MOVE / replay.speed() {
+        replay.waited = 0.;
+        replay.shown += 1;
+    }
+}
+
+// Catches the board up to the move the replay is on
+fn update_replay_board(
+    mut board: ResMut<Board>,
+    players: Query<&Player>,
+    falling: Query<(), With<FallingChip>>,
+    mut replay: ResMut<Replay>,
+    mut history: ResMut<MoveHistory>,
+    mut current_player: ResMut<CurrentPlayer>,
+    mut play_move_event: EventWriter<PlayMoveEvent>,
+) {
+    let at = board.moves().len();
+
+    // Still waiting for the move that was sent to go in
+    if replay.sent == Some(at) {
+        return;
+    }
+    if replay.sent.is_some() {
+        replay.sent = None;
+    }
+
+    if at == replay.shown || is_animating(&falling) {
+        return;
+    }
+
+    // The next move is played like any other, so its chip drops in
+    if replay.shown == at + 1 {
+        play_move_event.send(PlayMoveEvent {
+            played: replay.game.moves()[at],
+        });
+        replay.sent = Some(at);
+        return;
+    }
+
+    let mut game = Game::with_rules(*replay.game.config(), replay.game.rules());
+    for &next in &replay.game.moves()[..replay.shown] {
+        if let Err(error) = game.play_move(next) {
+            panic!("Tried to replay a move that was already played once: {error}")
+        }
+    }
+
+    *history = MoveHistory::from_game(&game);
+    **board = game;
+
+    let to_move: PlayerNum = board.to_move().into();
+    if let Some(player) = players.iter().find(|p| p.num == to_move) {
+        **current_player = player.clone();
+    }
+}
+
+fn update_replay_text(
+    replay: Res<Replay>,
+    mut texts: Query<&mut Text, With<ReplayText>>,
+    buttons: Query<(&ReplayButton, &Children)>,
+    mut labels: Query<&mut Text, Without<ReplayText>>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+
+    let mut line = format!("Speed: {}x", replay.speed());
+    if !replay.typed.is_empty() {
+        line = format!("{line}    Go to move: {}_", replay.typed);
+    }
+
+    for mut text in &mut texts {
+        text.sections[0].value = line.clone();
+    }
+
+    let label = if replay.playing { "Pause" } else { "Play" };
+    for (button, children) in &buttons {
+        if *button != ReplayButton::PlayPause {
+            continue;
+        }
+
+        for &child in children {
+            if let Ok(mut text) = labels.get_mut(child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.to_string();
+                }
+            }
+        }
+    }
+}
+
+// For the status text, which move the board is on and who played it
+pub fn replay_status(replay: &Replay, history: &MoveHistory, players: &Query<&Player>) -> String {
+    let Some(last) = history.played.last() else {
+        return format!("Replay: {} moves to go", replay.total());
+    };
+
+    let name = players
+        .iter()
+        .find(|player| player.num == last.player)
+        .map_or(String::new(), |player| player.name.clone());
+
+    format!(
+        "Move {} of {}: {name} played {}",
+        history.played.len(),
+        replay.total(),
+        move_notation(last.played)
+    )
+}
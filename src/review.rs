@@ -0,0 +1,555 @@
+// Looking back over a game once its over, in place of just the buttons along the bottom
+// Step through it a move at a time with the arrow keys (or Home and End to jump to either end), and see
+// what the solver makes of each move: a blunder that threw away a win or a draw, one that kept things
+// as they were, or the only move that wins. The graph shows who was winning after every move, the taller
+// the bar the sooner they win, and clicking a bar jumps to that move
+// Like hints, only the standard rules on boards the solver can handle get analysed, but any game whose
+// moves are known can be stepped through
+
+use crate::ai_player::{LoadedBook, SharedSolver};
+use crate::board::{player_colour, AppState, Board, ShownBoard};
+use crate::notation::{move_notation, Game};
+use crate::players::{Player, PlayerNum};
+use crate::rules::{Move, Position, Side};
+use crate::solver::Solver;
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, futures_lite::future, Task};
+
+const FIRST_KEY: KeyCode = KeyCode::Home;
+const PREVIOUS_KEY: KeyCode = KeyCode::ArrowLeft;
+const NEXT_KEY: KeyCode = KeyCode::ArrowRight;
+const LAST_KEY: KeyCode = KeyCode::End;
+
+const GRAPH_WIDTH: f32 = 600.;
+const GRAPH_HEIGHT: f32 = 100.;
+const MAX_BAR_WIDTH: f32 = 16.;
+const GRAPH_COLOUR: Color = Color::srgba(0., 0., 0., 0.6);
+const SHOWN_BAR_COLOUR: Color = Color::srgba(1., 1., 1., 0.3);
+const BLUNDER_COLOUR: Color = Color::srgba(1., 0., 0., 0.3);
+const DRAW_COLOUR: Color = Color::srgb(0.6, 0.6, 0.6);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Verdict {
+    // Made the result worse for whoever played it, eg a win into a draw
+    Blunder,
+    Kept,
+    // The position was won, and nothing else would have won it
+    OnlyMove,
+}
+
+// What the solver made of one move, with scores from the point of view of whoever played it (see `solver`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct MoveAnalysis {
+    mover: Side,
+    // The best anyone could have done from the position before it
+    best: i32,
+    played: i32,
+    winning_moves: usize,
+}
+
+impl MoveAnalysis {
+    fn verdict(&self) -> Verdict {
+        if self.played.signum() < self.best.signum() {
+            Verdict::Blunder
+        } else if self.best > 0 && self.winning_moves == 1 {
+            Verdict::OnlyMove
+        } else {
+            Verdict::Kept
+        }
+    }
+
+    // How things stand after the move, from player 1's point of view, for the graph
+    fn player1_score(&self) -> i32 {
+        match self.mover {
+            Side::First => self.played,
+            Side::Second => -self.played,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match (self.verdict(), self.best.signum(), self.played.signum()) {
+            (Verdict::OnlyMove, ..) => "the only winning move",
+            (Verdict::Blunder, 1, 0) => "a blunder, it threw away a win for a draw",
+            (Verdict::Blunder, 1, _) => "a blunder, it threw away a win",
+            (Verdict::Blunder, ..) => "a blunder, it lost a drawn game",
+            (Verdict::Kept, 1, _) => "it kept the win",
+            (Verdict::Kept, 0, _) => "it kept the draw",
+            (Verdict::Kept, ..) => "the game was already lost",
+        }
+    }
+}
+
+fn analyse_move(solver: &mut Solver, position: &Position, played: Move) -> MoveAnalysis {
+    let columns = solver.analyze(position);
+    let best = columns.iter().flatten().copied().max().unwrap_or(0);
+
+    MoveAnalysis {
+        mover: position.to_move(),
+        best,
+        played: columns.get(played.col()).copied().flatten().unwrap_or(best),
+        winning_moves: columns.iter().flatten().filter(|&&score| score > 0).count(),
+    }
+}
+
+#[derive(Resource, Default)]
+struct Review {
+    // Every point in the game, from before the first move to after the last
+    // Just the end when the moves that got there arent known
+    boards: Vec<Board>,
+    moves: Vec<Move>,
+    // One for each move, filled in as the solver gets to them
+    analysis: Vec<Option<MoveAnalysis>>,
+    can_analyse: bool,
+    // How many moves in the board being looked at is
+    shown: usize,
+    // Along with which move it was thinking about, so an answer for another game can be thrown away
+    // The answer only depends on the position and the move, so it still counts if the new game has both
+    thinking: Option<Task<(Position, Move, MoveAnalysis)>>,
+}
+
+impl Review {
+    fn last(&self) -> usize {
+        self.moves.len()
+    }
+
+    fn analysed(&self) -> usize {
+        self.analysis.iter().flatten().count()
+    }
+
+    fn blunders(&self, side: Side) -> usize {
+        self.analysis
+            .iter()
+            .flatten()
+            .filter(|analysis| analysis.mover == side && analysis.verdict() == Verdict::Blunder)
+            .count()
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+enum ReviewButton {
+    First,
+    Previous,
+    Next,
+    Last,
+    // A bar on the graph, for the board after that many moves
+    Bar(usize),
+}
+
+#[derive(Component)]
+struct ReviewText;
+
+// Half of a bar, either above the middle for player 1 or below it for player 2
+#[derive(Component)]
+struct BarFill {
+    moves: usize,
+    player1: bool,
+}
+
+pub fn plugin_review(app: &mut App) {
+    app.init_resource::<Review>();
+
+    app.add_systems(
+        OnEnter(AppState::GameOver),
+        (start_review, spawn_review).chain(),
+    );
+    app.add_systems(OnExit(AppState::GameOver), stop_review);
+
+    app.add_systems(
+        Update,
+        (
+            update_review_thinking,
+            update_review_keys,
+            update_review_buttons,
+            update_shown_board,
+            update_review_graph,
+            update_review_text,
+        )
+            .chain()
+            .run_if(in_state(AppState::GameOver)),
+    );
+}
+
+fn start_review(board: Res<Board>, mut review: ResMut<Review>) {
+    let game: &Game = &board;
+
+    let mut boards = Vec::new();
+    if game.to_notation().is_some() {
+        let mut replay = Game::with_rules(*game.config(), game.rules());
+        boards.push(Board(replay.clone()));
+        for &next in game.moves() {
+            if replay.play_move(next).is_err() {
+                panic!("Tried to review a game with an illegal move in it")
+            }
+            boards.push(Board(replay.clone()));
+        }
+    } else {
+        boards.push(board.clone());
+    }
+
+    let moves = if boards.len() > 1 {
+        game.moves().to_vec()
+    } else {
+        Vec::new()
+    };
+
+    review.can_analyse = !moves.is_empty() && Solver::can_solve(boards[0].position());
+    review.analysis = vec![None; moves.len()];
+    review.shown = moves.len();
+    review.boards = boards;
+    review.moves = moves;
+}
+
+fn stop_review(mut shown: ResMut<ShownBoard>) {
+    **shown = None;
+}
+
+// One move at a time, backwards from the end, since the last few are quick to solve and fill up the table
+// for the earlier ones
+fn update_review_thinking(
+    book: Res<LoadedBook>,
+    solver: Res<SharedSolver>,
+    mut review: ResMut<Review>,
+) {
+    // Without counting as a change, or everything showing the review would be redone every frame
+    if let Some(thinking) = &mut review.bypass_change_detection().thinking {
+        let Some((position, played, analysis)) = block_on(future::poll_once(thinking)) else {
+            return;
+        };
+
+        review.thinking = None;
+
+        let index = (0..review.last()).find(|&index| {
+            *review.boards[index].position() == position && review.moves[index] == played
+        });
+        if let Some(index) = index {
+            review.analysis[index] = Some(analysis);
+        }
+    }
+
+    if !review.can_analyse {
+        return;
+    }
+
+    let Some(index) = (0..review.last())
+        .rev()
+        .find(|&index| review.analysis[index].is_none())
+    else {
+        return;
+    };
+
+    let position = *review.boards[index].position();
+    let played = review.moves[index];
+    review.thinking = Some(solver.spawn(&position, &book, move |solver, _| {
+        (position, played, analyse_move(solver, &position, played))
+    }));
+}
+
+fn spawn_review(mut command: Commands, review: Res<Review>) {
+    let bar_width = (GRAPH_WIDTH / review.last().max(1) as f32).min(MAX_BAR_WIDTH);
+
+    command
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    bottom: Val::Px(60.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(5.0),
+                    ..default()
+                },
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StateScoped(AppState::GameOver),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", TextStyle::default())
+                    .with_text_justify(JustifyText::Center),
+                ReviewText,
+            ));
+
+            if review.can_analyse {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            height: Val::Px(GRAPH_HEIGHT),
+                            ..default()
+                        },
+                        background_color: GRAPH_COLOUR.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        for moves in 1..=review.last() {
+                            spawn_graph_bar(parent, moves, bar_width);
+                        }
+                    });
+            }
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(5.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (button, label) in [
+                        (ReviewButton::First, "|<"),
+                        (ReviewButton::Previous, "<"),
+                        (ReviewButton::Next, ">"),
+                        (ReviewButton::Last, ">|"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    background_color: Color::BLACK.into(),
+                                    ..default()
+                                },
+                                button,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(label, TextStyle::default()));
+                            });
+                    }
+                });
+        });
+}
+
+// The top half fills up from the middle for player 1, and the bottom half down from it for player 2
+fn spawn_graph_bar(parent: &mut ChildBuilder, moves: usize, width: f32) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::NONE.into(),
+                ..default()
+            },
+            ReviewButton::Bar(moves),
+        ))
+        .with_children(|parent| {
+            for (player1, justify_content) in [
+                (true, JustifyContent::FlexEnd),
+                (false, JustifyContent::FlexStart),
+            ] {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            height: Val::Percent(50.0),
+                            flex_direction: FlexDirection::Column,
+                            justify_content,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(100.0),
+                                    height: Val::Percent(0.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            BarFill { moves, player1 },
+                        ));
+                    });
+            }
+        });
+}
+
+fn update_review_keys(keys: Res<ButtonInput<KeyCode>>, mut review: ResMut<Review>) {
+    let shown = if keys.just_pressed(FIRST_KEY) {
+        0
+    } else if keys.just_pressed(PREVIOUS_KEY) {
+        review.shown.saturating_sub(1)
+    } else if keys.just_pressed(NEXT_KEY) {
+        (review.shown + 1).min(review.last())
+    } else if keys.just_pressed(LAST_KEY) {
+        review.last()
+    } else {
+        return;
+    };
+
+    if review.shown != shown {
+        review.shown = shown;
+    }
+}
+
+fn update_review_buttons(
+    buttons: Query<(&Interaction, &ReviewButton), Changed<Interaction>>,
+    mut review: ResMut<Review>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        review.shown = match *button {
+            ReviewButton::First => 0,
+            ReviewButton::Previous => review.shown.saturating_sub(1),
+            ReviewButton::Next => (review.shown + 1).min(review.last()),
+            ReviewButton::Last => review.last(),
+            ReviewButton::Bar(moves) => moves,
+        };
+    }
+}
+
+// The end of the game is just the real board
+fn update_shown_board(review: Res<Review>, mut shown: ResMut<ShownBoard>) {
+    if !review.is_changed() {
+        return;
+    }
+
+    let board = if review.shown < review.last() {
+        review.boards.get(review.shown).cloned()
+    } else {
+        None
+    };
+
+    let showing = shown.0.as_ref().map(|board| *board.position());
+    if showing != board.as_ref().map(|board| *board.position()) {
+        **shown = board;
+    }
+}
+
+fn update_review_graph(
+    review: Res<Review>,
+    mut bars: Query<(&ReviewButton, &mut BackgroundColor), Without<BarFill>>,
+    mut fills: Query<(&BarFill, &mut Style, &mut BackgroundColor), Without<ReviewButton>>,
+) {
+    if !review.is_changed() {
+        return;
+    }
+
+    for (button, mut colour) in &mut bars {
+        let ReviewButton::Bar(moves) = *button else {
+            continue;
+        };
+
+        let analysis = review.analysis.get(moves - 1).copied().flatten();
+        colour.0 = if moves == review.shown {
+            SHOWN_BAR_COLOUR
+        } else if analysis.map(|analysis| analysis.verdict()) == Some(Verdict::Blunder) {
+            BLUNDER_COLOUR
+        } else {
+            Color::NONE
+        };
+    }
+
+    // The quickest possible win fills the whole half
+    let max_score = review
+        .boards
+        .first()
+        .map_or(1, |board| board.config().cells() / 2) as f32;
+
+    for (fill, mut style, mut colour) in &mut fills {
+        let score = match review.analysis.get(fill.moves - 1).copied().flatten() {
+            Some(analysis) => analysis.player1_score(),
+            None => continue,
+        };
+
+        let (height, fill_colour) = match (score.signum(), fill.player1) {
+            (1, true) => (score as f32 / max_score, player_colour(&PlayerNum::Player1)),
+            (-1, false) => (
+                -score as f32 / max_score,
+                player_colour(&PlayerNum::Player2),
+            ),
+            // A thin line across the middle
+            (0, _) => (0.03, DRAW_COLOUR),
+            _ => (0., Color::NONE),
+        };
+
+        style.height = Val::Percent(height.clamp(0., 1.) * 100.);
+        colour.0 = fill_colour;
+    }
+}
+
+fn default_name(num: &PlayerNum) -> String {
+    match num {
+        PlayerNum::Player1 => "Player 1".to_string(),
+        PlayerNum::Player2 => "Player 2".to_string(),
+    }
+}
+
+fn update_review_text(
+    review: Res<Review>,
+    players: Query<&Player>,
+    mut texts: Query<&mut Text, With<ReviewText>>,
+) {
+    if !review.is_changed() {
+        return;
+    }
+
+    let name = |side: Side| {
+        let num = PlayerNum::from(side);
+        players
+            .iter()
+            .find(|player| player.num == num)
+            .map_or_else(|| default_name(&num), |player| player.name.clone())
+    };
+
+    let mut lines = Vec::new();
+
+    if review.last() == 0 {
+        lines
+            .push("The moves of this game arent known, so it cant be looked back over".to_string());
+    } else if review.shown == 0 {
+        lines.push(format!(
+            "The start of the game, {} moves to go",
+            review.last()
+        ));
+    } else {
+        let index = review.shown - 1;
+        let mover = review.boards[index].to_move();
+        let mut line = format!(
+            "Move {} of {}: {} played {}",
+            review.shown,
+            review.last(),
+            name(mover),
+            move_notation(review.moves[index])
+        );
+        if let Some(analysis) = review.analysis[index] {
+            line = format!("{line}, {}", analysis.describe());
+        }
+        lines.push(line);
+    }
+
+    if review.can_analyse {
+        if review.analysed() < review.last() {
+            lines.push(format!(
+                "Analysing, {} of {} moves done...",
+                review.analysed(),
+                review.last()
+            ));
+        } else {
+            lines.push(format!(
+                "Blunders: {} {}, {} {}",
+                name(Side::First),
+                review.blunders(Side::First),
+                name(Side::Second),
+                review.blunders(Side::Second)
+            ));
+        }
+    } else if review.last() > 0 {
+        lines.push("Only standard games the solver can handle get analysed".to_string());
+    }
+
+    for mut text in &mut texts {
+        text.sections[0].value = lines.join("\n");
+    }
+}
@@ -0,0 +1,1676 @@
+// The rules of connect 4, without any bevy in sight
+// Everything that needs to know what a legal move is, or who has won, should go through here
+// (the bevy board, bots, tools...)
+
+pub use crate::variant::*;
+
+use std::fmt;
+use std::ops::{
+    Add, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr, Sub,
+};
+use std::str::FromStr;
+
+// Moves are written down as a single digit per column, so there cant be more than 9
+pub const MAX_COLUMNS: usize = 9;
+pub const MAX_ROWS: usize = 12;
+
+// The longest a line could possibly need to be
+const MAX_CONNECT: usize = if MAX_ROWS > MAX_COLUMNS {
+    MAX_ROWS
+} else {
+    MAX_COLUMNS
+};
+
+/// How big the board is, and how many chips in a line you need to win
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BoardConfig {
+    pub rows: usize,
+    pub columns: usize,
+    pub connect: usize,
+}
+
+impl BoardConfig {
+    pub const STANDARD: BoardConfig = BoardConfig {
+        rows: 6,
+        columns: 7,
+        connect: 4,
+    };
+
+    pub fn new(rows: usize, columns: usize, connect: usize) -> Result<Self, InvalidConfig> {
+        if rows == 0 || rows > MAX_ROWS || columns == 0 || columns > MAX_COLUMNS {
+            return Err(InvalidConfig::Size { rows, columns });
+        }
+
+        // A line has to fit on the board somehow, and one chip on its own isnt a line
+        if connect < 2 || connect > rows.max(columns) {
+            return Err(InvalidConfig::Connect { connect });
+        }
+
+        Ok(BoardConfig {
+            rows,
+            columns,
+            connect,
+        })
+    }
+
+    pub fn cells(&self) -> usize {
+        self.rows * self.columns
+    }
+
+    // Each column takes up a bit more than its rows, see `Layout`
+    fn col_bits(&self) -> usize {
+        self.rows + 1
+    }
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        BoardConfig::STANDARD
+    }
+}
+
+// Shows as eg "7x6 connect 4"
+impl fmt::Display for BoardConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} connect {}", self.columns, self.rows, self.connect)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InvalidConfig {
+    Size { rows: usize, columns: usize },
+    Connect { connect: usize },
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidConfig::Size { rows, columns } => write!(
+                f,
+                "a board cant be {columns}x{rows}, it has to be between 1x1 and {MAX_COLUMNS}x{MAX_ROWS}"
+            ),
+            InvalidConfig::Connect { connect } => write!(
+                f,
+                "connect {connect} doesnt work, it has to be at least 2 and fit on the board"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// Which set of rules a game is played by, the rules themselves are in `variant`
+/// This is what gets saved, so every variant needs one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Rules {
+    #[default]
+    Standard,
+    PopOut,
+    FiveInARow,
+    PopTen,
+    PowerUp,
+    NoGravity,
+}
+
+impl Rules {
+    pub const ALL: [Rules; 6] = [
+        Rules::Standard,
+        Rules::PopOut,
+        Rules::FiveInARow,
+        Rules::PopTen,
+        Rules::PowerUp,
+        Rules::NoGravity,
+    ];
+
+    pub fn variant(&self) -> &'static dyn Variant {
+        match self {
+            Rules::Standard => &Standard,
+            Rules::PopOut => &PopOut,
+            Rules::FiveInARow => &FiveInARow,
+            Rules::PopTen => &PopTen,
+            Rules::PowerUp => &PowerUp,
+            Rules::NoGravity => &NoGravity,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.variant().name()
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rules::ALL
+            .into_iter()
+            .find(|rules| rules.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Rules::ALL.iter().map(|rules| rules.name()).collect();
+                format!("unknown rules '{s}', expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// A chip that does something more than sit there, for `Rules::PowerUp`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Special {
+    // Lands on the bottom, and takes every chip that was in the column with it
+    Anvil,
+    // Blows up the opponents chip at the top of a column, and itself
+    Bomb,
+    // A normal chip, but you get another turn straight after
+    Double,
+}
+
+impl Special {
+    pub const ALL: [Special; 3] = [Special::Anvil, Special::Bomb, Special::Double];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Special::Anvil => "anvil",
+            Special::Bomb => "bomb",
+            Special::Double => "double",
+        }
+    }
+
+    // What it is written as in move notation, and the key to press for it
+    pub fn letter(&self) -> char {
+        match self {
+            Special::Anvil => 'a',
+            Special::Bomb => 'b',
+            Special::Double => 'd',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Special> {
+        Special::ALL
+            .into_iter()
+            .find(|special| special.letter() == letter)
+    }
+
+    // Each side can use each special once, tracked as one bit each
+    pub(crate) fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+/// Something a player can do on their turn, in a 0-based column
+/// Which ones are allowed is up to the variant
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Move {
+    Drop(usize),
+    // Take your own chip out of the bottom of a column
+    Pop(usize),
+    // Put a chip straight into any hole, without it falling
+    Place { row: usize, col: usize },
+    Special(Special, usize),
+}
+
+impl Move {
+    pub fn col(&self) -> usize {
+        match *self {
+            Move::Drop(col) => col,
+            Move::Pop(col) => col,
+            Move::Place { col, .. } => col,
+            Move::Special(_, col) => col,
+        }
+    }
+}
+
+/// Which side a chip belongs to. `First` always makes the first move
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Side {
+    First,
+    Second,
+}
+
+impl Side {
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Side::First => 0,
+            Side::Second => 1,
+        }
+    }
+
+    pub fn other(self) -> Side {
+        match self {
+            Side::First => Side::Second,
+            Side::Second => Side::First,
+        }
+    }
+}
+
+/// What happened after a move, `row` is where it happened (always 0 for pops)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveOutcome {
+    Placed { row: usize },
+    Won { row: usize, winner: Side },
+    Draw { row: usize },
+}
+
+impl MoveOutcome {
+    pub fn row(&self) -> usize {
+        match *self {
+            MoveOutcome::Placed { row } => row,
+            MoveOutcome::Won { row, .. } => row,
+            MoveOutcome::Draw { row } => row,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    ColumnOutOfRange(usize),
+    ColumnFull(usize),
+    GameOver,
+    // Either the rules dont allow popping, or the bottom chip isnt theirs
+    CantPop(usize),
+    // The rules allow the column, just not right now
+    Restricted(usize),
+    RowOutOfRange(usize),
+    CellTaken { row: usize, col: usize },
+    // Each special can only be used once
+    SpecialUsed(Special),
+    // There isnt an opponents chip at the top of the column to blow up
+    CantBomb(usize),
+    // These rules dont have that kind of move at all
+    NotAllowed,
+}
+
+impl fmt::Display for MoveError {
+    // Columns and rows the way they are written in the notation, so from 1 and from A
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = |col: usize| col.wrapping_add(1);
+        let row = |row: usize| (b'A' + row.min(25) as u8) as char;
+
+        match *self {
+            MoveError::ColumnOutOfRange(col) => write!(f, "column {} does not exist", column(col)),
+            MoveError::ColumnFull(col) => write!(f, "column {} is already full", column(col)),
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::CantPop(col) => write!(
+                f,
+                "column {} doesnt have your chip at the bottom to pop",
+                column(col)
+            ),
+            MoveError::Restricted(col) => {
+                write!(f, "column {} cant be played in right now", column(col))
+            }
+            MoveError::RowOutOfRange(r) => write!(f, "row {} does not exist", row(r)),
+            MoveError::CellTaken { row: r, col } => write!(
+                f,
+                "row {}, column {} already has a chip in it",
+                row(r),
+                column(col)
+            ),
+            MoveError::SpecialUsed(special) => {
+                write!(f, "the {} has already been used", special.name())
+            }
+            MoveError::CantBomb(col) => write!(
+                f,
+                "column {} doesnt have an opponents chip on top to bomb",
+                column(col)
+            ),
+            MoveError::NotAllowed => write!(f, "these rules dont allow that move"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InvalidPosition {
+    // The grid of cells isnt the size the config says it should be
+    WrongSize { rows: usize, columns: usize },
+    // A chip with an empty hole underneath it
+    FloatingChip { row: usize, col: usize },
+    // The first player must have the same number of chips as the second, or exactly one more
+    PieceCount { first: usize, second: usize },
+    // Someone kept playing after a line was made
+    PlayedAfterWin,
+}
+
+impl fmt::Display for InvalidPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidPosition::WrongSize { rows, columns } => {
+                write!(
+                    f,
+                    "the board is {columns}x{rows}, which isnt the right size"
+                )
+            }
+            InvalidPosition::FloatingChip { row, col } => {
+                write!(f, "the chip at row {row}, column {col} is floating")
+            }
+            InvalidPosition::PieceCount { first, second } => write!(
+                f,
+                "the first player has {first} chips and the second has {second}"
+            ),
+            InvalidPosition::PlayedAfterWin => {
+                write!(f, "chips were played after someone had already won")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidPosition {}
+
+// A set of cells, one bit each. Gameplay uses u128 so every board size fits,
+// but the solver only ever deals with smaller boards, and u64 is a lot quicker for it
+pub(crate) trait Bitboard:
+    Copy
+    + Eq
+    + Ord
+    + std::hash::Hash
+    + fmt::Debug
+    + Not<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + BitAndAssign
+    + BitOrAssign
+    + BitXorAssign
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Shl<usize, Output = Self>
+    + Shr<usize, Output = Self>
+{
+    const BITS: usize;
+    const ZERO: Self;
+    const ONE: Self;
+    const ALL: Self;
+
+    fn count_ones(self) -> u32;
+}
+
+macro_rules! impl_bitboard {
+    ($($bits:ty),*) => {$(
+        impl Bitboard for $bits {
+            const BITS: usize = <$bits>::BITS as usize;
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const ALL: Self = <$bits>::MAX;
+
+            fn count_ones(self) -> u32 {
+                self.count_ones()
+            }
+        }
+    )*};
+}
+
+impl_bitboard!(u64, u128);
+
+// Each column takes up rows + 1 bits, the extra one is a sentinel on top of the column
+// so lines can never wrap round from one column into the next. For a standard board:
+//
+//   6 13 20 27 34 41 48   <- sentinel row
+//  ---------------------
+//   5 12 19 26 33 40 47
+//   4 11 18 25 32 39 46
+//   3 10 17 24 31 38 45
+//   2  9 16 23 30 37 44
+//   1  8 15 22 29 36 43
+//   0  7 14 21 28 35 42   <- row 0, the bottom
+//
+// The masks that only depend on the config are worked out once, and kept with the position
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Layout<B> {
+    config: BoardConfig,
+    // The bottom cell of every column
+    bottom: B,
+    // Every playable cell, with the sentinel row left out
+    board: B,
+}
+
+impl<B: Bitboard> Layout<B> {
+    fn new(config: BoardConfig) -> Self {
+        let mut bottom = B::ZERO;
+        let mut board = B::ZERO;
+        for col in 0..config.columns {
+            bottom |= bottom_mask(&config, col);
+            board |= column_mask(&config, col);
+        }
+
+        Layout {
+            config,
+            bottom,
+            board,
+        }
+    }
+}
+
+// Whether every cell of a board (and the sentinels) fits in `B`
+fn fits<B: Bitboard>(config: &BoardConfig) -> bool {
+    config.columns * config.col_bits() <= B::BITS
+}
+
+// Shifts that go off the end of the board just give nothing, rather than overflowing
+fn shift_up<B: Bitboard>(bits: B, by: usize) -> B {
+    if by >= B::BITS {
+        B::ZERO
+    } else {
+        bits << by
+    }
+}
+
+fn shift_down<B: Bitboard>(bits: B, by: usize) -> B {
+    if by >= B::BITS {
+        B::ZERO
+    } else {
+        bits >> by
+    }
+}
+
+fn bottom_mask<B: Bitboard>(config: &BoardConfig, col: usize) -> B {
+    B::ONE << (col * config.col_bits())
+}
+
+fn top_mask<B: Bitboard>(config: &BoardConfig, col: usize) -> B {
+    B::ONE << (config.rows - 1 + col * config.col_bits())
+}
+
+pub(crate) fn column_mask<B: Bitboard>(config: &BoardConfig, col: usize) -> B {
+    ((B::ONE << config.rows) - B::ONE) << (col * config.col_bits())
+}
+
+fn cell_mask<B: Bitboard>(config: &BoardConfig, row: usize, col: usize) -> B {
+    B::ONE << (row + col * config.col_bits())
+}
+
+// The bit shifts that move one cell along a line: vertical, horizontal, and the two diagonals
+fn line_shifts(config: &BoardConfig) -> [usize; 4] {
+    let col_bits = config.col_bits();
+    [1, col_bits, col_bits + 1, col_bits - 1]
+}
+
+// Every empty cell that would complete a line for `chips`
+// (whether or not a chip could actually be dropped there yet)
+fn winning_cells<B: Bitboard>(config: &BoardConfig, chips: B, empty: B) -> B {
+    // The solver spends most of its time in here, so normal connect 4 gets its own quicker version
+    if config.connect == 4 {
+        return winning_cells_of_four(config, chips, empty);
+    }
+
+    let connect = config.connect;
+    let mut cells = B::ZERO;
+
+    for shift in line_shifts(config) {
+        // behind[k] is every cell with k chips in a row just behind it, ahead[k] the same the other way
+        let mut behind = [B::ALL; MAX_CONNECT];
+        let mut ahead = [B::ALL; MAX_CONNECT];
+        for k in 1..connect {
+            behind[k] = behind[k - 1] & shift_up(chips, k * shift);
+            ahead[k] = ahead[k - 1] & shift_down(chips, k * shift);
+        }
+
+        // The new chip can go anywhere in the line
+        for k in 0..connect {
+            cells |= behind[k] & ahead[connect - 1 - k];
+        }
+    }
+
+    cells & empty
+}
+
+// Boards are never more than 13 bits a column, so shifting by 3 columns always fits
+fn winning_cells_of_four<B: Bitboard>(config: &BoardConfig, chips: B, empty: B) -> B {
+    let col_bits = config.col_bits();
+
+    // Vertical, only ever upwards
+    let mut cells = (chips << 1) & (chips << 2) & (chips << 3);
+
+    // Horizontal, then the two diagonals
+    for shift in [col_bits, col_bits - 1, col_bits + 1] {
+        // Two in a row on one side, and either one or two more on the other
+        let pair = (chips << shift) & (chips << (2 * shift));
+        cells |= pair & (chips << (3 * shift));
+        cells |= pair & (chips >> shift);
+
+        let pair = (chips >> shift) & (chips >> (2 * shift));
+        cells |= pair & (chips << shift);
+        cells |= pair & (chips >> (3 * shift));
+    }
+
+    cells & empty
+}
+
+// The first cell of every line in `chips`
+fn line_starts<B: Bitboard>(config: &BoardConfig, chips: B, shift: usize) -> B {
+    let mut starts = chips;
+    for i in 1..config.connect {
+        starts &= shift_down(chips, i * shift);
+    }
+    starts
+}
+
+// Whether a set of chips contains a whole line anywhere
+fn has_line<B: Bitboard>(config: &BoardConfig, chips: B) -> bool {
+    line_shifts(config)
+        .into_iter()
+        .any(|shift| line_starts(config, chips, shift) != B::ZERO)
+}
+
+// Every chip that is part of a line, which can be more than one line at once
+fn line_cells<B: Bitboard>(config: &BoardConfig, chips: B) -> B {
+    let mut cells = B::ZERO;
+
+    for shift in line_shifts(config) {
+        // Where each line starts, then the rest of the line from there
+        let starts = line_starts(config, chips, shift);
+        for i in 0..config.connect {
+            cells |= shift_up(starts, i * shift);
+        }
+    }
+
+    cells
+}
+
+// The chips on the board as bitboards, without any of the checks `Position` does
+// Moves here are a single bit (the cell the chip lands in) instead of a column
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Bits<B> {
+    layout: Layout<B>,
+    // The chips of whoever is about to move
+    current: B,
+    // Every chip on the board
+    mask: B,
+    moves: usize,
+}
+
+impl<B: Bitboard> Bits<B> {
+    fn new(config: BoardConfig) -> Self {
+        Bits {
+            layout: Layout::new(config),
+            current: B::ZERO,
+            mask: B::ZERO,
+            moves: 0,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &BoardConfig {
+        &self.layout.config
+    }
+
+    pub(crate) fn moves_played(&self) -> usize {
+        self.moves
+    }
+
+    // Unique for every position on the same size board, and never more than columns * (rows + 1) bits
+    pub(crate) fn key(&self) -> B {
+        self.current + self.mask
+    }
+
+    // The same position, flipped left to right
+    pub(crate) fn mirror(&self) -> Self {
+        let config = self.config();
+        let col_bits = config.col_bits();
+        let whole_column = (B::ONE << col_bits) - B::ONE;
+        let mut current = B::ZERO;
+        let mut mask = B::ZERO;
+
+        for col in 0..config.columns {
+            let from = col * col_bits;
+            let to = (config.columns - 1 - col) * col_bits;
+            current |= ((self.current >> from) & whole_column) << to;
+            mask |= ((self.mask >> from) & whole_column) << to;
+        }
+
+        Bits {
+            current,
+            mask,
+            ..*self
+        }
+    }
+
+    // The same for a position and its mirror image, since they are worth exactly the same
+    pub(crate) fn symmetric_key(&self) -> B {
+        self.key().min(self.mirror().key())
+    }
+
+    fn is_full(&self) -> bool {
+        self.mask == self.layout.board
+    }
+
+    fn empty(&self) -> B {
+        self.layout.board ^ self.mask
+    }
+
+    // The cell each non-full column would take a chip in
+    pub(crate) fn possible(&self) -> B {
+        (self.mask + self.layout.bottom) & self.layout.board
+    }
+
+    pub(crate) fn can_win_next(&self) -> bool {
+        winning_cells(self.config(), self.current, self.empty()) & self.possible() != B::ZERO
+    }
+
+    // Moves that dont hand the opponent a win straight away
+    // Assumes whoever is to move cant win next
+    pub(crate) fn possible_non_losing_moves(&self) -> B {
+        let mut possible = self.possible();
+        let opponent_wins = winning_cells(self.config(), self.current ^ self.mask, self.empty());
+
+        // If the opponent is about to win, we have to block them, and if there are two, we cant
+        let forced = possible & opponent_wins;
+        if forced != B::ZERO {
+            if forced & (forced - B::ONE) != B::ZERO {
+                return B::ZERO;
+            }
+            possible = forced;
+        }
+
+        // Never play right underneath a cell the opponent wants
+        possible & !(opponent_wins >> 1)
+    }
+
+    // How many new winning cells a move would make, which makes for a good move ordering
+    pub(crate) fn move_score(&self, cell: B) -> u32 {
+        winning_cells(self.config(), self.current | cell, self.empty()).count_ones()
+    }
+
+    // Play a single bit move, without checking if it won
+    // Only for moves from `possible`, that arent winning moves
+    pub(crate) fn play_cell(&mut self, cell: B) {
+        self.current ^= self.mask;
+        self.mask |= cell;
+        self.moves += 1;
+    }
+}
+
+// Who won, and every chip in their winning line (or lines)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Win {
+    pub winner: Side,
+    // (row, col), bottom row first
+    pub cells: Vec<(usize, usize)>,
+}
+
+/// A connect 4 position, stored as a pair of bitboards.
+/// Rows are counted from the bottom, so row 0 is where chips land first
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Position {
+    // Big enough for any board
+    bits: Bits<u128>,
+    rules: Rules,
+    // Not just down to how many moves there have been, since some variants give out extra turns
+    to_move: Side,
+    // Anything else the variant needs to keep track of
+    state: VariantState,
+    winner: Option<Side>,
+    // Drawn in a way the chips on the board dont show, like the same position coming up three times
+    drawn: bool,
+}
+
+// An empty standard board
+impl Default for Position {
+    fn default() -> Self {
+        Position::with_config(BoardConfig::STANDARD)
+    }
+}
+
+impl Position {
+    pub fn new() -> Self {
+        Position::default()
+    }
+
+    pub fn with_config(config: BoardConfig) -> Self {
+        Position::with_rules(config, Rules::Standard)
+    }
+
+    pub fn with_rules(config: BoardConfig, rules: Rules) -> Self {
+        let mut position = Position {
+            bits: Bits::new(config),
+            rules,
+            to_move: Side::First,
+            state: VariantState::default(),
+            winner: None,
+            drawn: false,
+        };
+        rules.variant().setup(&mut position);
+        position
+    }
+
+    // Build a position from a grid of cells, indexed [row][col] with row 0 at the bottom
+    // Only for standard rules, since after a pop the chips dont say whos turn it is any more
+    pub fn from_cells(
+        config: BoardConfig,
+        cells: &[Vec<Option<Side>>],
+    ) -> Result<Self, InvalidPosition> {
+        let wrong_size = cells.len() != config.rows
+            || cells
+                .iter()
+                .any(|cells_row| cells_row.len() != config.columns);
+        if wrong_size {
+            return Err(InvalidPosition::WrongSize {
+                rows: cells.len(),
+                columns: cells.first().map_or(0, |cells_row| cells_row.len()),
+            });
+        }
+
+        let mut first: u128 = 0;
+        let mut second: u128 = 0;
+
+        for (row, cells_row) in cells.iter().enumerate() {
+            for (col, cell) in cells_row.iter().enumerate() {
+                let Some(side) = cell else {
+                    continue;
+                };
+
+                if row > 0 && cells[row - 1][col].is_none() {
+                    return Err(InvalidPosition::FloatingChip { row, col });
+                }
+
+                match side {
+                    Side::First => first |= cell_mask::<u128>(&config, row, col),
+                    Side::Second => second |= cell_mask::<u128>(&config, row, col),
+                }
+            }
+        }
+
+        let first_count = first.count_ones() as usize;
+        let second_count = second.count_ones() as usize;
+        if first_count != second_count && first_count != second_count + 1 {
+            return Err(InvalidPosition::PieceCount {
+                first: first_count,
+                second: second_count,
+            });
+        }
+
+        // The winner has to have been the last one to move
+        let winner = match (has_line(&config, first), has_line(&config, second)) {
+            (true, false) if first_count > second_count => Some(Side::First),
+            (false, true) if first_count == second_count => Some(Side::Second),
+            (false, false) => None,
+            _ => return Err(InvalidPosition::PlayedAfterWin),
+        };
+
+        let moves = first_count + second_count;
+        let to_move = if moves % 2 == 0 {
+            Side::First
+        } else {
+            Side::Second
+        };
+        let current = if to_move == Side::First {
+            first
+        } else {
+            second
+        };
+
+        Ok(Position {
+            bits: Bits {
+                layout: Layout::new(config),
+                current,
+                mask: first | second,
+                moves,
+            },
+            rules: Rules::Standard,
+            to_move,
+            state: VariantState::default(),
+            winner,
+            drawn: false,
+        })
+    }
+
+    pub fn to_cells(&self) -> Vec<Vec<Option<Side>>> {
+        (0..self.config().rows)
+            .map(|row| {
+                (0..self.config().columns)
+                    .map(|col| self.get(row, col))
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn config(&self) -> &BoardConfig {
+        self.bits.config()
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn variant(&self) -> &'static dyn Variant {
+        self.rules.variant()
+    }
+
+    // Every chip belonging to one side
+    pub(crate) fn chips(&self, side: Side) -> u128 {
+        if side == self.to_move() {
+            self.bits.current
+        } else {
+            self.bits.current ^ self.bits.mask
+        }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<Side> {
+        if row >= self.config().rows || col >= self.config().columns {
+            return None;
+        }
+
+        let cell: u128 = cell_mask(self.config(), row, col);
+        if self.bits.mask & cell == 0 {
+            None
+        } else if self.bits.current & cell != 0 {
+            Some(self.to_move())
+        } else {
+            Some(self.to_move().other())
+        }
+    }
+
+    // Whos turn it is
+    pub fn to_move(&self) -> Side {
+        self.to_move
+    }
+
+    pub fn moves_played(&self) -> usize {
+        self.bits.moves
+    }
+
+    // The row a chip would land in, or None if the column is full (or doesnt exist)
+    pub fn lowest_free_row(&self, col: usize) -> Option<usize> {
+        let config = self.config();
+        if col >= config.columns || self.bits.mask & top_mask::<u128>(config, col) != 0 {
+            return None;
+        }
+
+        Some(self.height(col))
+    }
+
+    // How many chips are in a column
+    pub fn height(&self, col: usize) -> usize {
+        (self.bits.mask & column_mask::<u128>(self.config(), col)).count_ones() as usize
+    }
+
+    // Columns that can be dropped into
+    pub fn legal_moves(&self) -> Vec<usize> {
+        self.all_legal_moves()
+            .into_iter()
+            .filter_map(|next| match next {
+                Move::Drop(col) => Some(col),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Whether whoever is to move could pop the bottom chip out of a column, if the game wasnt over
+    pub fn can_pop(&self, col: usize) -> bool {
+        self.variant().moves(self).contains(&Move::Pop(col))
+    }
+
+    // Columns that can be popped from, always empty unless the variant has pops
+    pub fn legal_pops(&self) -> Vec<usize> {
+        self.all_legal_moves()
+            .into_iter()
+            .filter_map(|next| match next {
+                Move::Pop(col) => Some(col),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Every move the variant allows right now
+    pub fn all_legal_moves(&self) -> Vec<Move> {
+        if self.is_over() {
+            return Vec::new();
+        }
+
+        self.variant().moves(self)
+    }
+
+    pub fn play_move(&mut self, next: Move) -> Result<MoveOutcome, MoveError> {
+        if next.col() >= self.config().columns {
+            return Err(MoveError::ColumnOutOfRange(next.col()));
+        }
+        if self.is_over() {
+            return Err(MoveError::GameOver);
+        }
+
+        // The variant sorts out whos turn it is next, and whether anyone won
+        let row = self.variant().play(self, next)?;
+        self.bits.moves += 1;
+
+        if let Some(winner) = self.winner {
+            return Ok(MoveOutcome::Won { row, winner });
+        }
+
+        if self.is_draw() {
+            return Ok(MoveOutcome::Draw { row });
+        }
+
+        Ok(MoveOutcome::Placed { row })
+    }
+
+    pub fn play(&mut self, col: usize) -> Result<MoveOutcome, MoveError> {
+        self.play_move(Move::Drop(col))
+    }
+
+    pub fn pop(&mut self, col: usize) -> Result<MoveOutcome, MoveError> {
+        self.play_move(Move::Pop(col))
+    }
+
+    // The building blocks variants play their moves with. None of these check whos turn it is,
+    // or if anyone has won, thats up to the variant
+
+    pub(crate) fn state(&self) -> &VariantState {
+        &self.state
+    }
+
+    pub(crate) fn state_mut(&mut self) -> &mut VariantState {
+        &mut self.state
+    }
+
+    pub(crate) fn set_to_move(&mut self, side: Side) {
+        let first = self.chips(Side::First);
+        let second = self.chips(Side::Second);
+        self.to_move = side;
+        self.set_chips(first, second);
+    }
+
+    pub(crate) fn set_winner(&mut self, side: Side) {
+        self.winner = Some(side);
+    }
+
+    fn set_chips(&mut self, first: u128, second: u128) {
+        self.bits.mask = first | second;
+        self.bits.current = match self.to_move {
+            Side::First => first,
+            Side::Second => second,
+        };
+    }
+
+    // Changes the chips of one side, leaving the other alone
+    fn update_chips(&mut self, side: Side, update: impl Fn(u128) -> u128) {
+        let mut chips = [self.chips(Side::First), self.chips(Side::Second)];
+        chips[side.index()] = update(chips[side.index()]);
+        self.set_chips(chips[0], chips[1]);
+    }
+
+    // Drops a chip for whoever is to move, returning the row it landed in
+    pub(crate) fn drop_chip(&mut self, col: usize) -> Result<usize, MoveError> {
+        let Some(row) = self.lowest_free_row(col) else {
+            return Err(MoveError::ColumnFull(col));
+        };
+
+        self.place_chip(row, col);
+        Ok(row)
+    }
+
+    // Puts a chip for whoever is to move straight into a cell, which had better be empty
+    pub(crate) fn place_chip(&mut self, row: usize, col: usize) {
+        let cell: u128 = cell_mask(self.config(), row, col);
+        self.update_chips(self.to_move, |chips| chips | cell);
+    }
+
+    // Takes a chip out, and everything above it falls down a row
+    pub(crate) fn remove_chip(&mut self, row: usize, col: usize) {
+        let config = *self.config();
+        let column = column_mask::<u128>(&config, col);
+        let cell = cell_mask::<u128>(&config, row, col);
+        let below = column & (cell - 1);
+        let above = column & !below & !cell;
+        let fall = |chips: u128| (chips & !column) | (chips & below) | ((chips & above) >> 1);
+
+        self.update_chips(Side::First, fall);
+        self.update_chips(Side::Second, fall);
+    }
+
+    // Empties a whole column
+    pub(crate) fn clear_column(&mut self, col: usize) {
+        let column = column_mask::<u128>(self.config(), col);
+        self.update_chips(Side::First, |chips| chips & !column);
+        self.update_chips(Side::Second, |chips| chips & !column);
+    }
+
+    pub(crate) fn has_line(&self, side: Side) -> bool {
+        has_line(self.config(), self.chips(side))
+    }
+
+    // Every chip of `side` thats part of a line
+    pub(crate) fn line_chips(&self, side: Side) -> u128 {
+        line_cells(self.config(), self.chips(side))
+    }
+
+    pub(crate) fn cell(&self, row: usize, col: usize) -> u128 {
+        cell_mask(self.config(), row, col)
+    }
+
+    pub fn winner(&self) -> Option<Side> {
+        self.winner
+    }
+
+    pub fn win(&self) -> Option<Win> {
+        let winner = self.winner?;
+        let config = self.config();
+
+        let lines = self.variant().winning_chips(self, winner);
+
+        let mut cells = Vec::new();
+        for row in 0..config.rows {
+            for col in 0..config.columns {
+                if lines & cell_mask::<u128>(config, row, col) != 0 {
+                    cells.push((row, col));
+                }
+            }
+        }
+
+        Some(Win { winner, cells })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.bits.is_full()
+    }
+
+    pub fn is_draw(&self) -> bool {
+        self.winner.is_none() && (self.drawn || self.is_stuck())
+    }
+
+    // Nobody can move
+    fn is_stuck(&self) -> bool {
+        self.variant().is_stuck(self)
+    }
+
+    // For draws the position cant see for itself, see `Game`
+    pub(crate) fn set_drawn(&mut self) {
+        if self.winner.is_none() {
+            self.drawn = true;
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner.is_some() || self.is_draw()
+    }
+
+    // Unique for every position on the same size board
+    pub fn key(&self) -> u128 {
+        self.bits.key()
+    }
+
+    pub fn mirror(&self) -> Position {
+        Position {
+            bits: self.bits.mirror(),
+            ..*self
+        }
+    }
+
+    pub fn symmetric_key(&self) -> u128 {
+        self.bits.symmetric_key()
+    }
+
+    // The same chips in a u64, for the solver, if the board is small enough
+    // The solver only knows the standard rules, so nothing else gets any
+    pub(crate) fn small_bits(&self) -> Option<Bits<u64>> {
+        if self.rules != Rules::Standard || !fits::<u64>(self.config()) {
+            return None;
+        }
+
+        // The bits are laid out the same either way, so they only need cutting down
+        Some(Bits {
+            layout: Layout::new(*self.config()),
+            current: self.bits.current as u64,
+            mask: self.bits.mask as u64,
+            moves: self.bits.moves,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Square, wide, tall, tiny, and the biggest boards there can be
+    const CONFIGS: [(usize, usize, usize); 10] = [
+        (6, 7, 4),
+        (7, 6, 4),
+        (7, 8, 4),
+        (7, 9, 4),
+        (4, 5, 3),
+        (7, 9, 5),
+        (8, 3, 3),
+        (12, 9, 4),
+        (12, 9, 9),
+        (3, 9, 2),
+    ];
+
+    fn configs() -> impl Iterator<Item = BoardConfig> {
+        CONFIGS
+            .into_iter()
+            .map(|(rows, columns, connect)| BoardConfig::new(rows, columns, connect).unwrap())
+    }
+
+    // xorshift, so the tests dont need a rand crate and always see the same boards
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+    // Every line on the board, as a list of (row, col)
+    fn every_line(config: &BoardConfig) -> Vec<Vec<(usize, usize)>> {
+        let mut lines = Vec::new();
+        for row in 0..config.rows as isize {
+            for col in 0..config.columns as isize {
+                for (d_row, d_col) in DIRECTIONS {
+                    let line: Vec<(isize, isize)> = (0..config.connect as isize)
+                        .map(|i| (row + d_row * i, col + d_col * i))
+                        .collect();
+                    let fits = line.iter().all(|&(row, col)| {
+                        (0..config.rows as isize).contains(&row)
+                            && (0..config.columns as isize).contains(&col)
+                    });
+                    if fits {
+                        lines.push(
+                            line.into_iter()
+                                .map(|(row, col)| (row as usize, col as usize))
+                                .collect(),
+                        );
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    fn to_bits(config: &BoardConfig, cells: &[(usize, usize)]) -> u128 {
+        cells.iter().fold(0, |bits, &(row, col)| {
+            bits | cell_mask::<u128>(config, row, col)
+        })
+    }
+
+    // The slow and obvious way of finding lines, to check the bit tricks against
+    fn naive_line_cells(lines: &[u128], chips: u128) -> u128 {
+        lines
+            .iter()
+            .filter(|&&line| chips & line == line)
+            .fold(0, |cells, line| cells | line)
+    }
+
+    fn naive_winning_cells(config: &BoardConfig, lines: &[u128], chips: u128, empty: u128) -> u128 {
+        let mut cells = 0;
+        for row in 0..config.rows {
+            for col in 0..config.columns {
+                let cell = cell_mask::<u128>(config, row, col);
+                if empty & cell != 0 && naive_line_cells(lines, chips | cell) & cell != 0 {
+                    cells |= cell;
+                }
+            }
+        }
+        cells
+    }
+
+    fn naive_winner(lines: &[Vec<(usize, usize)>], cells: &[Vec<Option<Side>>]) -> Option<Side> {
+        [Side::First, Side::Second].into_iter().find(|&side| {
+            lines
+                .iter()
+                .any(|line| line.iter().all(|&(row, col)| cells[row][col] == Some(side)))
+        })
+    }
+
+    #[test]
+    fn every_line_is_found() {
+        for config in configs() {
+            let board = Layout::<u128>::new(config).board;
+
+            for line in every_line(&config) {
+                let chips = to_bits(&config, &line);
+                assert!(has_line(&config, chips), "{config}: {line:?}");
+                assert_eq!(line_cells(&config, chips), chips, "{config}: {line:?}");
+
+                // Take any one chip away and it isnt a line, but that hole wins
+                for &(row, col) in &line {
+                    let cell = cell_mask::<u128>(&config, row, col);
+                    let rest = chips & !cell;
+                    assert!(!has_line(&config, rest), "{config}: {line:?}");
+
+                    // Unless there are still chips above it, which could never happen in a game
+                    if rest & cell_mask::<u128>(&config, row + 1, col) != 0 {
+                        continue;
+                    }
+                    assert_ne!(
+                        winning_cells(&config, rest, board & !rest) & cell,
+                        0,
+                        "{config}: {line:?} without ({row}, {col})"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lines_dont_wrap_around_the_edges() {
+        for config in configs() {
+            let board = Layout::<u128>::new(config).board;
+            let lines: Vec<u128> = every_line(&config)
+                .iter()
+                .map(|line| to_bits(&config, line))
+                .collect();
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+            // Random sets of chips, from nearly empty to nearly full
+            for _ in 0..300 {
+                let density = 1 + rng.below(9);
+                let mut chips = 0;
+                for row in 0..config.rows {
+                    for col in 0..config.columns {
+                        if rng.below(10) < density {
+                            chips |= cell_mask::<u128>(&config, row, col);
+                        }
+                    }
+                }
+
+                let expected = naive_line_cells(&lines, chips);
+                assert_eq!(has_line(&config, chips), expected != 0, "{config}");
+                assert_eq!(line_cells(&config, chips), expected, "{config}");
+
+                // Winning cells only make sense with gravity, so stack some columns up
+                // and give each chip in them a random owner
+                let mut mask = 0;
+                for col in 0..config.columns {
+                    let height = rng.below(config.rows + 1);
+                    for row in 0..height {
+                        mask |= cell_mask::<u128>(&config, row, col);
+                    }
+                }
+                let chips = chips & mask;
+                let empty = board & !mask;
+
+                assert_eq!(
+                    winning_cells(&config, chips, empty),
+                    naive_winning_cells(&config, &lines, chips, empty),
+                    "{config}"
+                );
+
+                // The u64 version has to agree whenever the board fits
+                if fits::<u64>(&config) {
+                    let (chips, empty) = (chips as u64, empty as u64);
+                    assert_eq!(
+                        winning_cells(&config, chips, empty) as u128,
+                        naive_winning_cells(&config, &lines, chips as u128, empty as u128),
+                        "{config}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_games_match_a_naive_winner() {
+        for config in configs() {
+            let lines = every_line(&config);
+            let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+            for _ in 0..100 {
+                let mut position = Position::with_config(config);
+
+                while !position.is_over() {
+                    let moves = position.legal_moves();
+                    let col = moves[rng.below(moves.len())];
+                    let side = position.to_move();
+                    let row = position.lowest_free_row(col).unwrap();
+
+                    let outcome = position.play(col).unwrap();
+                    assert_eq!(outcome.row(), row);
+                    assert_eq!(position.get(row, col), Some(side));
+
+                    let cells = position.to_cells();
+                    assert_eq!(position.winner(), naive_winner(&lines, &cells), "{config}");
+
+                    // Going through the cells again has to give back the same position
+                    let rebuilt = Position::from_cells(config, &cells).unwrap();
+                    assert_eq!(rebuilt, position, "{config}");
+                    assert_eq!(position.mirror().mirror(), position, "{config}");
+                }
+
+                assert_eq!(position.legal_moves(), Vec::<usize>::new());
+
+                if let Some(win) = position.win() {
+                    let cells = position.to_cells();
+                    assert!(win.cells.len() >= config.connect);
+                    assert!(win
+                        .cells
+                        .iter()
+                        .all(|&(row, col)| cells[row][col] == Some(win.winner)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn small_bits_only_when_the_board_fits() {
+        for config in configs() {
+            let position = Position::with_config(config);
+            let fits = config.columns * (config.rows + 1) <= 64;
+            assert_eq!(position.small_bits().is_some(), fits, "{config}");
+        }
+    }
+
+    #[test]
+    fn moves_off_the_board_are_rejected() {
+        for config in configs() {
+            let mut position = Position::with_config(config);
+            assert_eq!(
+                position.play(config.columns),
+                Err(MoveError::ColumnOutOfRange(config.columns))
+            );
+            assert_eq!(position.lowest_free_row(config.columns), None);
+            assert_eq!(position.get(config.rows, 0), None);
+        }
+    }
+
+    #[test]
+    fn from_cells_checks_the_size() {
+        let config = BoardConfig::new(5, 4, 3).unwrap();
+        let cells = vec![vec![None; 5]; 4];
+        assert_eq!(
+            Position::from_cells(config, &cells),
+            Err(InvalidPosition::WrongSize {
+                rows: 4,
+                columns: 5
+            })
+        );
+    }
+
+    #[test]
+    fn board_config_limits() {
+        assert_eq!(BoardConfig::default(), BoardConfig::STANDARD);
+        assert_eq!(BoardConfig::STANDARD.to_string(), "7x6 connect 4");
+
+        assert!(BoardConfig::new(MAX_ROWS, MAX_COLUMNS, 4).is_ok());
+        assert!(BoardConfig::new(1, 9, 9).is_ok());
+        assert!(BoardConfig::new(12, 1, 12).is_ok());
+
+        assert!(BoardConfig::new(0, 7, 4).is_err());
+        assert!(BoardConfig::new(6, 0, 4).is_err());
+        assert!(BoardConfig::new(MAX_ROWS + 1, 7, 4).is_err());
+        assert!(BoardConfig::new(6, MAX_COLUMNS + 1, 4).is_err());
+        assert!(BoardConfig::new(6, 7, 1).is_err());
+        assert!(BoardConfig::new(6, 7, 8).is_err());
+    }
+
+    // Rows are written top down here, so they look like the board
+    fn popout_position(config: BoardConfig, rows: &[&str]) -> Position {
+        let cells: Vec<Vec<Option<Side>>> = rows
+            .iter()
+            .rev()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        '1' => Some(Side::First),
+                        '2' => Some(Side::Second),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Position {
+            rules: Rules::PopOut,
+            ..Position::from_cells(config, &cells).unwrap()
+        }
+    }
+
+    #[test]
+    fn popping_drops_the_column() {
+        let mut position = Position::with_rules(BoardConfig::STANDARD, Rules::PopOut);
+        for col in [0, 0, 1, 2] {
+            position.play(col).unwrap();
+        }
+
+        // Only your own chips can be popped
+        assert_eq!(position.pop(2), Err(MoveError::CantPop(2)));
+        assert_eq!(position.pop(3), Err(MoveError::CantPop(3)));
+        assert_eq!(position.legal_pops(), vec![0, 1]);
+
+        assert_eq!(position.pop(0), Ok(MoveOutcome::Placed { row: 0 }));
+        assert_eq!(position.get(0, 0), Some(Side::Second));
+        assert_eq!(position.get(1, 0), None);
+        assert_eq!(position.to_move(), Side::Second);
+        assert_eq!(position.moves_played(), 5);
+
+        // And not at all with standard rules
+        let mut position = Position::new();
+        position.play(0).unwrap();
+        position.play(1).unwrap();
+        assert_eq!(position.pop(0), Err(MoveError::CantPop(0)));
+        assert!(position
+            .all_legal_moves()
+            .iter()
+            .all(|m| matches!(m, Move::Drop(_))));
+    }
+
+    #[test]
+    fn popping_a_line_for_both_players_wins_for_the_popper() {
+        let mut position = popout_position(
+            BoardConfig::STANDARD,
+            &[
+                ".......", //
+                ".......", "2......", "1222...", "2111...", "11212..",
+            ],
+        );
+        assert_eq!(position.to_move(), Side::First);
+
+        assert_eq!(
+            position.pop(0),
+            Ok(MoveOutcome::Won {
+                row: 0,
+                winner: Side::First
+            })
+        );
+        let win = position.win().unwrap();
+        assert_eq!(win.winner, Side::First);
+        assert_eq!(win.cells, vec![(1, 0), (1, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn popping_a_line_for_the_other_player_loses() {
+        let mut position = popout_position(
+            BoardConfig::STANDARD,
+            &[
+                ".......", //
+                ".......", ".......", "2......", "1222...", "12111..",
+            ],
+        );
+        assert_eq!(position.to_move(), Side::First);
+
+        assert_eq!(
+            position.pop(0),
+            Ok(MoveOutcome::Won {
+                row: 0,
+                winner: Side::Second
+            })
+        );
+        assert_eq!(
+            position.win().unwrap().cells,
+            vec![(1, 0), (1, 1), (1, 2), (1, 3)]
+        );
+    }
+
+    #[test]
+    fn full_boards_arent_a_draw_while_there_is_a_pop() {
+        let config = BoardConfig::new(1, 3, 3).unwrap();
+
+        let standard = Position::from_cells(
+            config,
+            &[vec![
+                Some(Side::First),
+                Some(Side::Second),
+                Some(Side::First),
+            ]],
+        )
+        .unwrap();
+        assert!(standard.is_draw());
+
+        let mut position = popout_position(config, &["121"]);
+        assert!(!position.is_over());
+        assert_eq!(position.all_legal_moves(), vec![Move::Pop(1)]);
+
+        position.pop(1).unwrap();
+        assert_eq!(
+            position.play(1),
+            Ok(MoveOutcome::Won {
+                row: 0,
+                winner: Side::First
+            })
+        );
+    }
+
+    #[test]
+    fn five_in_a_row_starts_with_the_sides_filled() {
+        let rules = Rules::FiveInARow;
+        let config = rules.variant().default_config();
+        let position = Position::with_rules(config, rules);
+
+        assert_eq!(position.get(0, 0), Some(Side::First));
+        assert_eq!(position.get(1, 0), Some(Side::Second));
+        assert_eq!(position.get(0, 8), Some(Side::Second));
+        assert_eq!(position.get(5, 8), Some(Side::First));
+        assert_eq!(position.to_move(), Side::First);
+        assert_eq!(position.moves_played(), 0);
+        assert_eq!(position.legal_moves(), (1..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pop_ten_keeps_chips_from_lines() {
+        // Columns 0 and 2 fill up with the first players chips, 1 and 3 with the seconds
+        let config = BoardConfig::new(4, 4, 4).unwrap();
+        let mut position = Position::with_rules(config, Rules::PopTen);
+
+        // The board fills one row at a time, and nobody can pop until its full
+        position.play(0).unwrap();
+        assert_eq!(position.play(0), Err(MoveError::Restricted(0)));
+        for col in [1, 2, 3].into_iter().chain([0, 1, 2, 3].repeat(3)) {
+            position.play(col).unwrap();
+        }
+        assert!(position.is_full());
+        assert_eq!(position.to_move(), Side::First);
+        assert_eq!(position.legal_pops(), vec![0, 2]);
+
+        // A chip from a line is kept, and the same player goes again
+        assert_eq!(position.pop(0), Ok(MoveOutcome::Placed { row: 0 }));
+        assert_eq!(position.state().kept, [1, 0]);
+        assert_eq!(position.to_move(), Side::First);
+
+        // Anything else goes back in, somewhere else if it can
+        position.pop(0).unwrap();
+        assert_eq!(position.state().kept, [1, 0]);
+        assert_eq!(position.to_move(), Side::First);
+        assert_eq!(position.all_legal_moves(), vec![Move::Drop(0)]);
+        position.play(0).unwrap();
+        assert_eq!(position.to_move(), Side::Second);
+
+        position.pop(1).unwrap();
+        position.pop(3).unwrap();
+        assert_eq!(position.state().kept, [1, 2]);
+        position.pop(1).unwrap();
+        assert_eq!(
+            position.all_legal_moves(),
+            vec![Move::Drop(0), Move::Drop(3)]
+        );
+
+        // Only one chip needs keeping on a tiny board
+        let config = BoardConfig::new(2, 2, 2).unwrap();
+        let mut position = Position::with_rules(config, Rules::PopTen);
+        for col in [0, 1, 0, 1] {
+            position.play(col).unwrap();
+        }
+        assert_eq!(
+            position.pop(0),
+            Ok(MoveOutcome::Won {
+                row: 0,
+                winner: Side::First
+            })
+        );
+        assert!(position.win().unwrap().cells.is_empty());
+    }
+
+    #[test]
+    fn power_up_specials_can_each_be_used_once() {
+        let mut position = Position::with_rules(BoardConfig::STANDARD, Rules::PowerUp);
+        for col in [3, 3, 3, 4] {
+            position.play(col).unwrap();
+        }
+
+        // The anvil takes everything in the column with it
+        let anvil = Move::Special(Special::Anvil, 3);
+        assert_eq!(
+            position.play_move(anvil),
+            Ok(MoveOutcome::Placed { row: 0 })
+        );
+        assert_eq!(position.height(3), 1);
+        assert_eq!(position.get(0, 3), Some(Side::First));
+        assert_eq!(position.to_move(), Side::Second);
+
+        // The bomb only works on an opponents chip
+        let bomb = Move::Special(Special::Bomb, 4);
+        assert_eq!(position.play_move(bomb), Err(MoveError::CantBomb(4)));
+        let bomb = Move::Special(Special::Bomb, 3);
+        assert_eq!(position.play_move(bomb), Ok(MoveOutcome::Placed { row: 0 }));
+        assert_eq!(position.height(3), 0);
+
+        // The double is a normal chip, but the same player goes again
+        let double = Move::Special(Special::Double, 0);
+        position.play_move(double).unwrap();
+        assert_eq!(position.to_move(), Side::First);
+        position.play(1).unwrap();
+        position.play(2).unwrap();
+        assert_eq!(position.to_move(), Side::First);
+        assert_eq!(
+            position.play_move(double),
+            Err(MoveError::SpecialUsed(Special::Double))
+        );
+        assert_eq!(
+            position.play_move(anvil),
+            Err(MoveError::SpecialUsed(Special::Anvil))
+        );
+    }
+
+    #[test]
+    fn no_gravity_places_chips_anywhere() {
+        let rules = Rules::NoGravity;
+        let mut position = Position::with_rules(rules.variant().default_config(), rules);
+        assert_eq!(position.play(0), Err(MoveError::NotAllowed));
+        assert_eq!(position.all_legal_moves().len(), 9);
+
+        let place = |row, col| Move::Place { row, col };
+        for (row, col) in [(2, 2), (0, 0), (1, 1)] {
+            position.play_move(place(row, col)).unwrap();
+        }
+        assert_eq!(
+            position.play_move(place(1, 1)),
+            Err(MoveError::CellTaken { row: 1, col: 1 })
+        );
+        assert_eq!(
+            position.play_move(place(3, 1)),
+            Err(MoveError::RowOutOfRange(3))
+        );
+
+        position.play_move(place(2, 0)).unwrap();
+        assert_eq!(
+            position.play_move(place(0, 2)),
+            Ok(MoveOutcome::Placed { row: 0 })
+        );
+        assert_eq!(
+            position.play_move(place(1, 0)),
+            Ok(MoveOutcome::Won {
+                row: 1,
+                winner: Side::Second
+            })
+        );
+    }
+}
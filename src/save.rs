@@ -0,0 +1,551 @@
+// Saved games, as JSON
+// A save has both the board and the moves that got there, and they have to agree with each other
+// (and with the rules) before a save will load
+
+use crate::notation::{ForfeitReason, Game, NotationError};
+use crate::rules::*;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+pub const SAVE_VERSION: u32 = 1;
+pub const DEFAULT_SAVE_PATH: &str = "savegame.json";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SavedState {
+    Playing,
+    Won,
+    Draw,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub name: String,
+    // eg "human" or "ai:6"
+    pub kind: String,
+}
+
+// A game that was lost without a line being made, which replaying the moves cant tell
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SavedForfeit {
+    // 1 or 2, whoever lost
+    pub player: u8,
+    pub reason: SavedForfeitReason,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SavedForfeitReason {
+    Time,
+    Engine,
+}
+
+impl From<ForfeitReason> for SavedForfeitReason {
+    fn from(reason: ForfeitReason) -> Self {
+        match reason {
+            ForfeitReason::Time => SavedForfeitReason::Time,
+            ForfeitReason::Engine => SavedForfeitReason::Engine,
+        }
+    }
+}
+
+impl From<SavedForfeitReason> for ForfeitReason {
+    fn from(reason: SavedForfeitReason) -> Self {
+        match reason {
+            SavedForfeitReason::Time => ForfeitReason::Time,
+            SavedForfeitReason::Engine => ForfeitReason::Engine,
+        }
+    }
+}
+
+// Saves from before boards could change size dont have this, so they get a standard board
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SavedConfig {
+    pub rows: usize,
+    pub columns: usize,
+    pub connect: usize,
+}
+
+impl Default for SavedConfig {
+    fn default() -> Self {
+        SavedConfig::from(BoardConfig::STANDARD)
+    }
+}
+
+impl From<BoardConfig> for SavedConfig {
+    fn from(config: BoardConfig) -> Self {
+        SavedConfig {
+            rows: config.rows,
+            columns: config.columns,
+            connect: config.connect,
+        }
+    }
+}
+
+// Saves from before popout are all standard
+fn standard_rules() -> String {
+    Rules::Standard.name().to_string()
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    #[serde(default)]
+    pub config: SavedConfig,
+    // The name of the variant, eg "popout"
+    #[serde(default = "standard_rules")]
+    pub rules: String,
+    // Player 1 first
+    pub players: [SavedPlayer; 2],
+    // 1 or 2
+    pub current_player: u8,
+    pub state: SavedState,
+    // One string per row, from the top down, with '.' for empty holes, and '1' or '2' for chips
+    pub board: Vec<String>,
+    pub moves: String,
+    // Only there for games that were forfeited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forfeit: Option<SavedForfeit>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    InvalidConfig(InvalidConfig),
+    UnknownRules(String),
+    BoardSize {
+        rows: usize,
+        expected: usize,
+    },
+    BoardRow {
+        row: usize,
+        length: usize,
+        expected: usize,
+    },
+    BoardCharacter {
+        row: usize,
+        character: char,
+    },
+    InvalidBoard(InvalidPosition),
+    InvalidMoves(NotationError),
+    MovesDontMatchBoard,
+    UnknownPlayer(u8),
+    // A forfeit in a game that was already over without it
+    ForfeitAfterGameOver,
+    WrongPlayerToMove {
+        saved: u8,
+        expected: u8,
+    },
+    WrongState {
+        saved: SavedState,
+        expected: SavedState,
+    },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "could not read or write the save: {error}"),
+            SaveError::Json(error) => write!(f, "the save is not valid: {error}"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "save version {version} is not supported (expected {SAVE_VERSION})"
+            ),
+            SaveError::InvalidConfig(error) => write!(f, "the board size is invalid: {error}"),
+            SaveError::UnknownRules(error) => write!(f, "the rules are invalid: {error}"),
+            SaveError::BoardSize { rows, expected } => {
+                write!(f, "the board has {rows} rows, but should have {expected}")
+            }
+            SaveError::BoardRow {
+                row,
+                length,
+                expected,
+            } => write!(
+                f,
+                "row {row} of the board has {length} holes, but should have {expected}"
+            ),
+            SaveError::BoardCharacter { row, character } => write!(
+                f,
+                "row {row} of the board has '{character}' in it, which isnt '.', '1' or '2'"
+            ),
+            SaveError::InvalidBoard(error) => write!(f, "the board is impossible: {error}"),
+            SaveError::InvalidMoves(error) => write!(f, "the moves are invalid: {error}"),
+            SaveError::MovesDontMatchBoard => {
+                write!(
+                    f,
+                    "playing the moves doesnt give the same board as the save"
+                )
+            }
+            SaveError::UnknownPlayer(player) => {
+                write!(f, "there is no player {player}, only 1 and 2")
+            }
+            SaveError::ForfeitAfterGameOver => {
+                write!(
+                    f,
+                    "the save says someone forfeited a game that was already over"
+                )
+            }
+            SaveError::WrongPlayerToMove { saved, expected } => write!(
+                f,
+                "the save says its player {saved}s turn, but it should be player {expected}s"
+            ),
+            SaveError::WrongState { saved, expected } => write!(
+                f,
+                "the save says the game is {saved:?}, but the board says it is {expected:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(error: serde_json::Error) -> Self {
+        SaveError::Json(error)
+    }
+}
+
+fn side_number(side: Side) -> u8 {
+    match side {
+        Side::First => 1,
+        Side::Second => 2,
+    }
+}
+
+fn state_of(position: &Position) -> SavedState {
+    if position.winner().is_some() {
+        SavedState::Won
+    } else if position.is_draw() {
+        SavedState::Draw
+    } else {
+        SavedState::Playing
+    }
+}
+
+// Top row first, like the board is drawn
+fn board_rows(position: &Position) -> Vec<String> {
+    (0..position.config().rows)
+        .rev()
+        .map(|row| {
+            (0..position.config().columns)
+                .map(|col| match position.get(row, col) {
+                    Some(Side::First) => '1',
+                    Some(Side::Second) => '2',
+                    None => '.',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Indexed [row][col], with row 0 at the bottom like `Position::from_cells`
+fn parse_board(config: BoardConfig, rows: &[String]) -> Result<Vec<Vec<Option<Side>>>, SaveError> {
+    if rows.len() != config.rows {
+        return Err(SaveError::BoardSize {
+            rows: rows.len(),
+            expected: config.rows,
+        });
+    }
+
+    let mut cells = vec![vec![None; config.columns]; config.rows];
+
+    // The first row in the save is the top one
+    for (row_from_top, text) in rows.iter().enumerate() {
+        let row = config.rows - 1 - row_from_top;
+
+        let length = text.chars().count();
+        if length != config.columns {
+            return Err(SaveError::BoardRow {
+                row,
+                length,
+                expected: config.columns,
+            });
+        }
+
+        for (col, character) in text.chars().enumerate() {
+            cells[row][col] = match character {
+                '.' => None,
+                '1' => Some(Side::First),
+                '2' => Some(Side::Second),
+                _ => return Err(SaveError::BoardCharacter { row, character }),
+            };
+        }
+    }
+
+    Ok(cells)
+}
+
+impl SaveFile {
+    // The game has to have its full move history, or it cant be loaded again
+    pub fn new(game: &Game, players: [SavedPlayer; 2], current_player: u8) -> Self {
+        SaveFile {
+            version: SAVE_VERSION,
+            config: SavedConfig::from(*game.config()),
+            rules: game.rules().name().to_string(),
+            players,
+            current_player,
+            state: state_of(game),
+            board: board_rows(game),
+            moves: game.to_notation().unwrap_or_default(),
+            forfeit: game.forfeited().map(|(side, reason)| SavedForfeit {
+                player: side_number(side),
+                reason: reason.into(),
+            }),
+        }
+    }
+
+    // Checks everything in the save agrees with the rules, and gives back the game
+    pub fn validate(&self) -> Result<Game, SaveError> {
+        if self.version != SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(self.version));
+        }
+
+        let SavedConfig {
+            rows,
+            columns,
+            connect,
+        } = self.config;
+        let config = BoardConfig::new(rows, columns, connect).map_err(SaveError::InvalidConfig)?;
+
+        let rules: Rules = self.rules.parse().map_err(SaveError::UnknownRules)?;
+
+        // Checking the board on its own first gives better errors for hand edited saves
+        // (other variants can be in almost any state, so only the moves can say if they are right)
+        let cells = parse_board(config, &self.board)?;
+        if rules == Rules::Standard {
+            Position::from_cells(config, &cells).map_err(SaveError::InvalidBoard)?;
+        }
+
+        let mut game = Game::from_notation_with_rules(config, rules, &self.moves)
+            .map_err(SaveError::InvalidMoves)?;
+        if game.to_cells() != cells {
+            return Err(SaveError::MovesDontMatchBoard);
+        }
+
+        if self.current_player != 1 && self.current_player != 2 {
+            return Err(SaveError::UnknownPlayer(self.current_player));
+        }
+
+        // Losing on time (or to a broken engine) leaves nothing on the board to say so
+        if let Some(forfeit) = self.forfeit {
+            let side = match forfeit.player {
+                1 => Side::First,
+                2 => Side::Second,
+                player => return Err(SaveError::UnknownPlayer(player)),
+            };
+            if game.is_over() {
+                return Err(SaveError::ForfeitAfterGameOver);
+            }
+            game.forfeit(side, forfeit.reason.into());
+        }
+        let position = game.position();
+
+        let expected = side_number(position.to_move());
+        if self.current_player != expected {
+            return Err(SaveError::WrongPlayerToMove {
+                saved: self.current_player,
+                expected,
+            });
+        }
+
+        let expected = state_of(position);
+        if self.state != expected {
+            return Err(SaveError::WrongState {
+                saved: self.state,
+                expected,
+            });
+        }
+
+        Ok(game)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to turn a save into json")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SaveError> {
+        // Check the version before anything else, since other versions could be laid out differently
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if let Some(version) = value.get("version").and_then(|v| v.as_u64()) {
+            if version != SAVE_VERSION as u64 {
+                return Err(SaveError::UnsupportedVersion(version as u32));
+            }
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        std::fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    // Only checks the file is a save, use `validate` to check the game in it
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        SaveFile::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save_of(notation: &str) -> SaveFile {
+        let game = Game::from_notation(notation).unwrap();
+        let player = |name: &str| SavedPlayer {
+            name: name.into(),
+            kind: "human".into(),
+        };
+        SaveFile::new(
+            &game,
+            [player("One"), player("Two")],
+            side_number(game.to_move()),
+        )
+    }
+
+    #[test]
+    fn good_saves_load() {
+        let save = save_of("4453");
+        assert_eq!(
+            save.validate().unwrap().to_notation().as_deref(),
+            Some("4453")
+        );
+        assert_eq!(SaveFile::from_json(&save.to_json()).unwrap(), save);
+    }
+
+    #[test]
+    fn wrong_versions_are_turned_down() {
+        let mut save = save_of("4453");
+        save.version = SAVE_VERSION + 1;
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+        assert!(matches!(
+            SaveFile::from_json(&save.to_json()),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn floating_chips_are_turned_down() {
+        let mut save = save_of("");
+        save.board[0] = "1......".into();
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::InvalidBoard(InvalidPosition::FloatingChip {
+                row: 5,
+                col: 0
+            }))
+        ));
+    }
+
+    #[test]
+    fn piece_counts_have_to_add_up() {
+        let mut save = save_of("");
+        save.board[5] = "22.....".into();
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::InvalidBoard(InvalidPosition::PieceCount {
+                first: 0,
+                second: 2
+            }))
+        ));
+    }
+
+    #[test]
+    fn the_right_player_has_to_be_next() {
+        let mut save = save_of("445");
+        save.current_player = 1;
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::WrongPlayerToMove {
+                saved: 1,
+                expected: 2
+            })
+        ));
+
+        save.current_player = 3;
+        assert!(matches!(save.validate(), Err(SaveError::UnknownPlayer(3))));
+    }
+
+    #[test]
+    fn moves_have_to_match_the_board() {
+        let mut save = save_of("4453");
+        save.moves = "4435".into();
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::MovesDontMatchBoard)
+        ));
+
+        save.moves = "448".into();
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::InvalidMoves(NotationError::ColumnOutOfRange {
+                index: 2,
+                column: 8
+            }))
+        ));
+    }
+
+    #[test]
+    fn forfeited_games_load() {
+        let mut game = Game::from_notation("4453").unwrap();
+        game.forfeit(Side::First, ForfeitReason::Time);
+        let save = SaveFile::new(&game, save_of("").players, 1);
+        assert_eq!(save.state, SavedState::Won);
+        assert_eq!(
+            save.forfeit,
+            Some(SavedForfeit {
+                player: 1,
+                reason: SavedForfeitReason::Time
+            })
+        );
+
+        let loaded = SaveFile::from_json(&save.to_json())
+            .unwrap()
+            .validate()
+            .unwrap();
+        assert_eq!(loaded.winner(), Some(Side::Second));
+        assert_eq!(loaded.forfeited(), Some((Side::First, ForfeitReason::Time)));
+        assert_eq!(loaded, game);
+
+        // Forfeiting after the game was already won isnt possible
+        let mut save = save_of("1212121");
+        save.forfeit = Some(SavedForfeit {
+            player: 2,
+            reason: SavedForfeitReason::Engine,
+        });
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::ForfeitAfterGameOver)
+        ));
+
+        // And saves without a forfeit dont mention one at all
+        assert!(!save_of("4453").to_json().contains("forfeit"));
+    }
+
+    #[test]
+    fn the_state_has_to_match_the_board() {
+        let mut save = save_of("1212121");
+        assert_eq!(save.state, SavedState::Won);
+        save.state = SavedState::Playing;
+        assert!(matches!(
+            save.validate(),
+            Err(SaveError::WrongState {
+                saved: SavedState::Playing,
+                expected: SavedState::Won
+            })
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::board::{AppState, Board};
+use crate::history::MoveHistory;
+use crate::notation::Game;
+use crate::players::{CurrentPlayer, Player, PlayerKind, PlayerNum};
+use crate::save::{SaveFile, SavedPlayer, DEFAULT_SAVE_PATH};
+
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+
+// F5 to save, F9 to load
+const SAVE_KEY: KeyCode = KeyCode::F5;
+const LOAD_KEY: KeyCode = KeyCode::F9;
+
+#[derive(Event)]
+pub struct SaveGameEvent {
+    pub path: PathBuf,
+}
+
+#[derive(Event)]
+pub struct LoadGameEvent {
+    pub path: PathBuf,
+}
+
+// A save to load as soon as the game starts, from `--load <path>`
+#[derive(Resource, Default)]
+pub struct StartupSave(pub Option<PathBuf>);
+
+impl StartupSave {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--load") else {
+            return Ok(StartupSave(None));
+        };
+
+        match args.get(index + 1) {
+            Some(path) => Ok(StartupSave(Some(path.into()))),
+            None => Err("--load needs the path of a save after it".into()),
+        }
+    }
+}
+
+pub fn plugin_save_load(app: &mut App) {
+    app.add_event::<SaveGameEvent>();
+    app.add_event::<LoadGameEvent>();
+    app.init_resource::<StartupSave>();
+
+    // The players have to exist before they can be loaded over
+    app.add_systems(PostStartup, load_startup_save);
+
+    // Saving from the menus would save whatever board was left over from the last game
+    // A save given with --load still goes through the events, so it isnt held back by this
+    app.add_systems(
+        Update,
+        update_save_load_keys
+            .run_if(in_state(AppState::InGame).or_else(in_state(AppState::GameOver))),
+    );
+    app.add_systems(Update, update_save_game);
+    app.add_systems(Update, update_load_game);
+}
+
+fn load_startup_save(startup_save: Res<StartupSave>, mut load_event: EventWriter<LoadGameEvent>) {
+    if let Some(path) = &startup_save.0 {
+        load_event.send(LoadGameEvent { path: path.clone() });
+    }
+}
+
+fn update_save_load_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut save_event: EventWriter<SaveGameEvent>,
+    mut load_event: EventWriter<LoadGameEvent>,
+) {
+    if keys.just_pressed(SAVE_KEY) {
+        save_event.send(SaveGameEvent {
+            path: DEFAULT_SAVE_PATH.into(),
+        });
+    }
+
+    if keys.just_pressed(LOAD_KEY) {
+        load_event.send(LoadGameEvent {
+            path: DEFAULT_SAVE_PATH.into(),
+        });
+    }
+}
+
+fn saved_player(players: &Query<&Player>, num: PlayerNum) -> SavedPlayer {
+    let Some(player) = players.iter().find(|p| p.num == num) else {
+        panic!("Failed to fetch a player to save")
+    };
+
+    // Whoever was on the other computer is just another human when the save is loaded
+    let kind = match &player.kind {
+        PlayerKind::Remote => PlayerKind::Human,
+        kind => kind.clone(),
+    };
+
+    SavedPlayer {
+        name: player.name.clone(),
+        kind: kind.to_string(),
+    }
+}
+
+fn update_save_game(
+    mut events: EventReader<SaveGameEvent>,
+    board: Res<Board>,
+    current_player: Res<CurrentPlayer>,
+    players: Query<&Player>,
+) {
+    for event in events.read() {
+        let save = SaveFile::new(
+            &board,
+            [
+                saved_player(&players, PlayerNum::Player1),
+                saved_player(&players, PlayerNum::Player2),
+            ],
+            match current_player.num {
+                PlayerNum::Player1 => 1,
+                PlayerNum::Player2 => 2,
+            },
+        );
+
+        match save.save(&event.path) {
+            Ok(()) => info!("Saved the game to {}", event.path.display()),
+            Err(error) => error!("Failed to save the game: {error}"),
+        }
+    }
+}
+
+// A save that has been read and checked, ready to go into the game
+pub struct CheckedSave {
+    pub game: Game,
+    // Names and kinds, player 1 first
+    pub players: [(String, PlayerKind); 2],
+}
+
+impl CheckedSave {
+    // Everything is checked before anything is changed, so a bad save leaves the current game alone
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let save = SaveFile::load(path)
+            .map_err(|error| format!("Failed to load {}: {error}", path.display()))?;
+
+        let refuse = |error: String| format!("Refusing to load {}: {error}", path.display());
+
+        let game = save.validate().map_err(|error| refuse(error.to_string()))?;
+
+        let kinds: Vec<PlayerKind> = save
+            .players
+            .iter()
+            .map(|saved| saved.kind.parse())
+            .collect::<Result<_, String>>()
+            .map_err(refuse)?;
+
+        Ok(CheckedSave {
+            game,
+            players: [0, 1].map(|index| (save.players[index].name.clone(), kinds[index].clone())),
+        })
+    }
+
+    pub fn apply_to(&self, player: &mut Player) {
+        let index = match player.num {
+            PlayerNum::Player1 => 0,
+            PlayerNum::Player2 => 1,
+        };
+        player.name = self.players[index].0.clone();
+        player.kind = self.players[index].1.clone();
+    }
+}
+
+fn update_load_game(
+    mut command: Commands,
+    mut events: EventReader<LoadGameEvent>,
+    mut players: Query<&mut Player>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for event in events.read() {
+        let save = match CheckedSave::read(&event.path) {
+            Ok(save) => save,
+            Err(error) => {
+                error!("{error}");
+                continue;
+            }
+        };
+
+        let to_move: PlayerNum = save.game.to_move().into();
+
+        for mut player in &mut players {
+            save.apply_to(&mut player);
+
+            if player.num == to_move {
+                command.insert_resource(CurrentPlayer(player.clone()));
+            }
+        }
+
+        // The save's state has already been checked against the board, which also knows the winning line
+        // (and moves on to the game over screen by itself, if the game in it is finished)
+        let board = Board(save.game);
+        command.insert_resource(*board.config());
+        command.insert_resource(board.rules());
+        command.insert_resource(MoveHistory::from_game(&board));
+        command.insert_resource(board);
+        next_state.set(AppState::InGame);
+
+        info!("Loaded the game from {}", event.path.display());
+    }
+}
@@ -0,0 +1,342 @@
+use crate::ai::DEFAULT_DEPTH;
+use crate::board::AppState;
+use crate::chess_clock::ClockSettings;
+use crate::clock::TimeControl;
+use crate::main_menu::{spawn_button, spawn_screen, spawn_title};
+use crate::menu::start_new_game;
+use crate::net::MAX_NAME_LENGTH;
+use crate::players::{Player, PlayerKind, PlayerKinds, PlayerNum};
+use crate::rules::Rules;
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+
+const SETUP_COLOUR: Color = Color::srgb(0.05, 0.05, 0.2);
+const FOCUSED_COLOUR: Color = Color::srgb(0.1, 0.4, 0.1);
+
+// What the AI depths are called on the setup screen, clicking the player type goes through these in order
+const DIFFICULTIES: [(&str, u32); 4] = [
+    ("Easy", 2),
+    ("Medium", 4),
+    ("Hard", DEFAULT_DEPTH),
+    ("Expert", 8),
+];
+
+// Picking names, who is human or AI, who goes first, the rules and the clocks, before a new game
+pub fn plugin_setup(app: &mut App) {
+    app.init_resource::<GameSetup>();
+    app.init_resource::<FocusedName>();
+
+    app.add_systems(Startup, create_game_setup);
+    app.add_systems(OnEnter(AppState::Setup), spawn_setup);
+    app.add_systems(OnExit(AppState::Setup), clear_focused_name);
+
+    app.add_systems(
+        Update,
+        (
+            update_setup_buttons,
+            update_name_typing,
+            update_setup_labels,
+        )
+            .chain()
+            .run_if(in_state(AppState::Setup)),
+    );
+}
+
+// Seats are the two places at the table, which become player 1 and 2 depending on who goes first
+#[derive(Resource, Clone, Debug)]
+pub struct GameSetup {
+    pub names: [String; 2],
+    pub kinds: [PlayerKind; 2],
+    // The seat that goes first
+    pub first: usize,
+    pub rules: Rules,
+    // None to play without clocks
+    pub clock: Option<TimeControl>,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        GameSetup {
+            names: ["Player 1".into(), "Player 2".into()],
+            kinds: [PlayerKind::Human, PlayerKind::Human],
+            first: 0,
+            rules: Rules::Standard,
+            clock: None,
+        }
+    }
+}
+
+impl GameSetup {
+    // Which seat ends up as which player
+    fn seat_of(&self, num: &PlayerNum) -> usize {
+        match num {
+            PlayerNum::Player1 => self.first,
+            PlayerNum::Player2 => 1 - self.first,
+        }
+    }
+
+    // An empty name would be impossible to tell apart from nothing at all, so they go back to the default once typing stops
+    fn fill_empty_names(&mut self) {
+        for (seat, name) in self.names.iter_mut().enumerate() {
+            if name.trim().is_empty() {
+                *name = format!("Player {}", seat + 1);
+            }
+        }
+    }
+}
+
+// The name currently being typed into, if any
+#[derive(Resource, Default, Deref, DerefMut)]
+struct FocusedName(Option<usize>);
+
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+enum SetupButton {
+    Name(usize),
+    Kind(usize),
+    First,
+    Rules,
+    Clock,
+    Start,
+    Back,
+}
+
+// Start with whatever was given on the command line
+fn create_game_setup(
+    mut setup: ResMut<GameSetup>,
+    kinds: Res<PlayerKinds>,
+    rules: Res<Rules>,
+    clock: Res<ClockSettings>,
+) {
+    setup.kinds = [kinds.player1.clone(), kinds.player2.clone()];
+    setup.rules = *rules;
+    setup.clock = clock.0;
+}
+
+fn clear_focused_name(mut focused: ResMut<FocusedName>, mut setup: ResMut<GameSetup>) {
+    **focused = None;
+    setup.fill_empty_names();
+}
+
+fn kind_label(kind: &PlayerKind) -> String {
+    match kind {
+        PlayerKind::Human => "Human".into(),
+        PlayerKind::Ai { depth } => match DIFFICULTIES.iter().find(|(_, d)| d == depth) {
+            Some((name, _)) => format!("AI ({name})"),
+            None => format!("AI (depth {depth})"),
+        },
+        PlayerKind::Engine { .. } => "Engine".into(),
+        PlayerKind::Remote => "Remote".into(),
+    }
+}
+
+// Human, then each difficulty of AI, then back to human
+fn next_kind(kind: &PlayerKind) -> PlayerKind {
+    let next_depth = match kind {
+        PlayerKind::Human => Some(DIFFICULTIES[0].1),
+        PlayerKind::Ai { depth } => DIFFICULTIES.iter().map(|&(_, d)| d).find(|d| d > depth),
+        // Engines and remote players cant be picked here, so clicking goes back to the start
+        PlayerKind::Engine { .. } | PlayerKind::Remote => None,
+    };
+
+    match next_depth {
+        Some(depth) => PlayerKind::Ai { depth },
+        None => PlayerKind::Human,
+    }
+}
+
+// No clocks, then each of the presets, then back to no clocks
+// Anything else from the command line goes back to no clocks too
+fn next_clock(clock: Option<TimeControl>) -> Option<TimeControl> {
+    let Some(clock) = clock else {
+        return Some(TimeControl::PRESETS[0]);
+    };
+
+    let index = TimeControl::PRESETS
+        .iter()
+        .position(|&preset| preset == clock)?;
+    TimeControl::PRESETS.get(index + 1).copied()
+}
+
+fn button_label(setup: &GameSetup, focused: Option<usize>, button: SetupButton) -> String {
+    match button {
+        // A cursor on the end of the one being typed in
+        SetupButton::Name(seat) if focused == Some(seat) => format!("{}_", setup.names[seat]),
+        SetupButton::Name(seat) => setup.names[seat].clone(),
+        SetupButton::Kind(seat) => kind_label(&setup.kinds[seat]),
+        SetupButton::First => format!("{} goes first", setup.names[setup.first]),
+        SetupButton::Rules => format!("Rules: {}", setup.rules.variant().title()),
+        SetupButton::Clock => match setup.clock {
+            Some(clock) => format!("Clock: {}", clock.title()),
+            None => "Clock: None".into(),
+        },
+        SetupButton::Start => "Start".into(),
+        SetupButton::Back => "Back".into(),
+    }
+}
+
+fn spawn_setup(mut command: Commands, setup: Res<GameSetup>) {
+    spawn_screen(&mut command, AppState::Setup, SETUP_COLOUR, |parent| {
+        spawn_title(parent, "New Game");
+
+        for seat in 0..2 {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for button in [SetupButton::Name(seat), SetupButton::Kind(seat)] {
+                        spawn_button(parent, button_label(&setup, None, button), button);
+                    }
+                });
+        }
+
+        for button in [
+            SetupButton::First,
+            SetupButton::Rules,
+            SetupButton::Clock,
+            SetupButton::Start,
+            SetupButton::Back,
+        ] {
+            spawn_button(parent, button_label(&setup, None, button), button);
+        }
+
+        parent.spawn(TextBundle::from_section(
+            "Click a name to type a new one",
+            TextStyle {
+                font_size: 16.0,
+                ..default()
+            },
+        ));
+    });
+}
+
+fn update_setup_buttons(
+    mut command: Commands,
+    buttons: Query<(&Interaction, &SetupButton), Changed<Interaction>>,
+    mut setup: ResMut<GameSetup>,
+    mut focused: ResMut<FocusedName>,
+    mut players: Query<&mut Player>,
+    mut kinds: ResMut<PlayerKinds>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Clicking anywhere else stops typing, before Start gets to read the names
+        **focused = None;
+        setup.fill_empty_names();
+
+        match *button {
+            SetupButton::Name(seat) => **focused = Some(seat),
+            SetupButton::Kind(seat) => setup.kinds[seat] = next_kind(&setup.kinds[seat]),
+            SetupButton::First => setup.first = 1 - setup.first,
+            SetupButton::Rules => {
+                let index = Rules::ALL.iter().position(|rules| *rules == setup.rules);
+                let next = index.map_or(0, |index| (index + 1) % Rules::ALL.len());
+                setup.rules = Rules::ALL[next];
+            }
+            SetupButton::Clock => setup.clock = next_clock(setup.clock),
+            SetupButton::Start => {
+                // Whoever goes first is player 1, since they always get the first move
+                let mut player1 = None;
+                for mut player in &mut players {
+                    let seat = setup.seat_of(&player.num);
+                    player.name = setup.names[seat].clone();
+                    player.kind = setup.kinds[seat].clone();
+
+                    if player.num == PlayerNum::Player1 {
+                        player1 = Some(player.clone());
+                    }
+                }
+                let Some(player1) = player1 else {
+                    panic!("Failed to fetch player 1")
+                };
+
+                kinds.player1 = setup.kinds[setup.first].clone();
+                kinds.player2 = setup.kinds[1 - setup.first].clone();
+
+                let rules = setup.rules;
+                let config = rules.variant().default_config();
+                command.insert_resource(config);
+                command.insert_resource(rules);
+                command.insert_resource(ClockSettings(setup.clock));
+                start_new_game(&mut command, &mut next_state, config, rules, player1);
+            }
+            SetupButton::Back => next_state.set(AppState::Menu),
+        }
+    }
+}
+
+fn update_name_typing(
+    mut events: EventReader<KeyboardInput>,
+    mut setup: ResMut<GameSetup>,
+    mut focused: ResMut<FocusedName>,
+) {
+    let Some(seat) = **focused else {
+        events.clear();
+        return;
+    };
+
+    for event in events.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+
+        let name = &mut setup.names[seat];
+        match &event.logical_key {
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < MAX_NAME_LENGTH {
+                        name.push(c);
+                    }
+                }
+            }
+            Key::Space if name.chars().count() < MAX_NAME_LENGTH => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            // Anything typed after this isnt for the name, and gets cleared out next frame
+            Key::Enter | Key::Tab => {
+                **focused = None;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if focused.is_none() {
+        setup.fill_empty_names();
+    }
+}
+
+fn update_setup_labels(
+    setup: Res<GameSetup>,
+    focused: Res<FocusedName>,
+    mut buttons: Query<(&SetupButton, &Children, &mut BackgroundColor)>,
+    mut texts: Query<&mut Text>,
+) {
+    if !setup.is_changed() && !focused.is_changed() {
+        return;
+    }
+
+    for (&button, children, mut colour) in &mut buttons {
+        let label = button_label(&setup, **focused, button);
+        for &child in children {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+
+        colour.0 = match button {
+            SetupButton::Name(seat) if **focused == Some(seat) => FOCUSED_COLOUR,
+            _ => Color::BLACK,
+        };
+    }
+}
@@ -0,0 +1,554 @@
+// A perfect play solver, following Pascal Pons' blog series
+// http://blog.gamesolver.org/
+//
+// Scores are from the point of view of whoever is to move:
+//  - positive if they can win, 1 if they win with their very last chip, 2 with their second to last...
+//  - negative if they will lose, in the same way
+//  - 0 for a draw
+
+use crate::ai::center_first_order;
+use crate::book::OpeningBook;
+use crate::rules::*;
+
+use std::sync::Arc;
+
+// A prime just over 2^24 entries, about 80MB.
+// Being prime (and bigger than 2^(bits - 32)) means the low 32 bits of a key are enough to tell keys apart
+const TABLE_SIZE: usize = 16_777_259;
+
+// Which is only true for keys of up to 56 bits, so bigger boards cant be solved
+const MAX_KEY_BITS: usize = 56;
+
+// The best and worst scores a position could have, on a board with this many cells
+// Nobody can win until they have played `connect` chips
+fn score_range(config: &BoardConfig) -> (i32, i32) {
+    let cells = config.cells() as i32;
+    let first_win = config.connect as i32 - 1;
+    (-cells / 2 + first_win, (cells + 1) / 2 - first_win)
+}
+
+// Keys are at most 56 bits, but only the bottom 32 need storing
+struct TranspositionTable {
+    keys: Vec<u32>,
+    values: Vec<u8>,
+}
+
+impl TranspositionTable {
+    fn new() -> Self {
+        TranspositionTable {
+            keys: vec![0; TABLE_SIZE],
+            values: vec![0; TABLE_SIZE],
+        }
+    }
+
+    fn index(key: u64) -> usize {
+        (key % TABLE_SIZE as u64) as usize
+    }
+
+    // Always replace whatever was there before
+    fn put(&mut self, key: u64, value: u8) {
+        let index = Self::index(key);
+        self.keys[index] = key as u32;
+        self.values[index] = value;
+    }
+
+    // 0 if the key isnt stored
+    fn get(&self, key: u64) -> u8 {
+        let index = Self::index(key);
+        if self.keys[index] == key as u32 {
+            self.values[index]
+        } else {
+            0
+        }
+    }
+
+    fn clear(&mut self) {
+        self.keys.fill(0);
+        self.values.fill(0);
+    }
+}
+
+// Keeps up to a board's worth of columns of moves, and hands them back best first
+struct MoveSorter {
+    entries: [(u64, u32); MAX_COLUMNS],
+    len: usize,
+}
+
+impl MoveSorter {
+    fn new() -> Self {
+        MoveSorter {
+            entries: [(0, 0); MAX_COLUMNS],
+            len: 0,
+        }
+    }
+
+    // Insertion sort, moves with equal scores come out in the order they went in
+    fn add(&mut self, cell: u64, score: u32) {
+        let mut pos = self.len;
+        while pos > 0 && self.entries[pos - 1].1 > score {
+            self.entries[pos] = self.entries[pos - 1];
+            pos -= 1;
+        }
+        self.entries[pos] = (cell, score);
+        self.len += 1;
+    }
+
+    fn next(&mut self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.entries[self.len].0)
+    }
+}
+
+/// The exact result of a position with perfect play, from the point of view of whoever is to move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Evaluation {
+    // `moves` is how many more chips get played (by both players) up to and including the winning one
+    Win { moves: usize },
+    Loss { moves: usize },
+    // The board fills up in `moves` more chips
+    Draw { moves: usize },
+}
+
+impl Evaluation {
+    pub fn from_score(score: i32, config: &BoardConfig, moves_played: usize) -> Self {
+        let moves_left = config.cells() - moves_played;
+        if score == 0 {
+            return Evaluation::Draw { moves: moves_left };
+        }
+
+        let mover_is_first = moves_played % 2 == 0;
+        let winner_is_first = mover_is_first == (score > 0);
+
+        // Which of their own chips the winner wins with (1 for their first)
+        // On a board with an odd number of cells, player 1 gets one more chip than player 2
+        let chips = if winner_is_first {
+            config.cells().div_ceil(2)
+        } else {
+            config.cells() / 2
+        };
+        let winning_chip = chips + 1 - score.unsigned_abs() as usize;
+
+        // And when that chip gets played, counting from 1 for the first move of the game
+        let winning_move = if winner_is_first {
+            2 * winning_chip - 1
+        } else {
+            2 * winning_chip
+        };
+
+        let moves = winning_move - moves_played;
+        if score > 0 {
+            Evaluation::Win { moves }
+        } else {
+            Evaluation::Loss { moves }
+        }
+    }
+}
+
+pub struct Solver {
+    table: TranspositionTable,
+    book: Option<Arc<OpeningBook>>,
+    // Worked out again whenever the board size changes
+    config: BoardConfig,
+    column_order: Vec<usize>,
+    min_score: i32,
+    max_score: i32,
+    // How many positions were looked at, handy for benchmarking
+    pub node_count: u64,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        let config = BoardConfig::STANDARD;
+        let (min_score, max_score) = score_range(&config);
+
+        Solver {
+            table: TranspositionTable::new(),
+            book: None,
+            config,
+            column_order: center_first_order(&config),
+            min_score,
+            max_score,
+            node_count: 0,
+        }
+    }
+
+    // Whether a board is small enough for its positions to fit in the transposition table
+    pub fn supports(config: &BoardConfig) -> bool {
+        config.columns * (config.rows + 1) <= MAX_KEY_BITS
+    }
+
+    // Whether `solve` works on a position at all, which also needs it to be using the standard rules
+    pub fn can_solve(position: &Position) -> bool {
+        position.small_bits().is_some() && Solver::supports(position.config())
+    }
+
+    // Anything in the table is for the old board size, so it has to go
+    fn set_config(&mut self, config: &BoardConfig) {
+        if self.config == *config {
+            return;
+        }
+
+        self.config = *config;
+        self.column_order = center_first_order(config);
+        (self.min_score, self.max_score) = score_range(config);
+        self.table.clear();
+    }
+
+    // Positions in the book dont need searching at all
+    pub fn set_book(&mut self, book: Option<Arc<OpeningBook>>) {
+        self.book = book;
+    }
+
+    fn book_score(&self, position: &Bits<u64>) -> Option<i32> {
+        self.book.as_ref()?.get_bits(position)
+    }
+
+    pub fn reset(&mut self) {
+        self.table.clear();
+        self.node_count = 0;
+    }
+
+    // The exact score of a position that isnt over yet (see the top of the file)
+    // With `weak`, only the sign is right: -1 for a loss, 0 for a draw, 1 for a win
+    pub fn solve(&mut self, position: &Position, weak: bool) -> i32 {
+        assert!(
+            !position.is_over(),
+            "Tried to solve a position where the game is already over"
+        );
+        let bits = match position.small_bits() {
+            Some(bits) if Solver::supports(position.config()) => bits,
+            _ => panic!("Tried to solve a position the solver doesnt support (too big, or not standard rules)"),
+        };
+        self.set_config(position.config());
+
+        let moves = position.moves_played() as i32;
+        let cells = position.config().cells() as i32;
+
+        if bits.can_win_next() {
+            return (cells + 1 - moves) / 2;
+        }
+
+        if let Some(score) = self.book_score(&bits) {
+            return if weak { score.signum() } else { score };
+        }
+
+        let mut min = -(cells - moves) / 2;
+        let mut max = (cells + 1 - moves) / 2;
+        if weak {
+            min = -1;
+            max = 1;
+        }
+
+        // Keep narrowing the window down with null window searches, until we know the exact score
+        while min < max {
+            let mut med = min + (max - min) / 2;
+
+            // Look closer to 0 first, since those searches are a lot quicker
+            if med <= 0 && min / 2 < med {
+                med = min / 2;
+            } else if med >= 0 && max / 2 > med {
+                med = max / 2;
+            }
+
+            let score = self.negamax(&bits, med, med + 1);
+            if score <= med {
+                max = score;
+            } else {
+                min = score;
+            }
+        }
+
+        min
+    }
+
+    pub fn evaluate(&mut self, position: &Position) -> Option<Evaluation> {
+        if position.is_over() {
+            return None;
+        }
+
+        let score = self.solve(position, false);
+        Some(Evaluation::from_score(
+            score,
+            position.config(),
+            position.moves_played(),
+        ))
+    }
+
+    // The score of playing in each column, from the point of view of whoever is to move now
+    // Full columns are None
+    pub fn analyze(&mut self, position: &Position) -> Vec<Option<i32>> {
+        let mut scores = vec![None; position.config().columns];
+        if position.is_over() {
+            return scores;
+        }
+
+        let cells = position.config().cells() as i32;
+
+        for (col, score) in scores.iter_mut().enumerate() {
+            let mut next = *position;
+            *score = match next.play(col) {
+                Ok(MoveOutcome::Won { .. }) => {
+                    Some((cells + 1 - position.moves_played() as i32) / 2)
+                }
+                Ok(MoveOutcome::Draw { .. }) => Some(0),
+                Ok(MoveOutcome::Placed { .. }) => Some(-self.solve(&next, false)),
+                Err(_) => None,
+            };
+        }
+
+        scores
+    }
+
+    // The column with the best score, going for the middle when its a tie
+    pub fn best_move(&mut self, position: &Position) -> Option<usize> {
+        let scores = self.analyze(position);
+
+        let mut best: Option<(usize, i32)> = None;
+        for col in center_first_order(position.config()) {
+            if let Some(score) = scores[col] {
+                if best.map_or(true, |(_, best_score)| score > best_score) {
+                    best = Some((col, score));
+                }
+            }
+        }
+
+        best.map(|(col, _)| col)
+    }
+
+    // Assumes whoever is to move cant win straight away
+    fn negamax(&mut self, position: &Bits<u64>, mut alpha: i32, mut beta: i32) -> i32 {
+        self.node_count += 1;
+
+        let moves = position.moves_played() as i32;
+        let cells = self.config.cells() as i32;
+        let (min_score, max_score) = (self.min_score, self.max_score);
+
+        let next = position.possible_non_losing_moves();
+
+        // Every move loses
+        if next == 0 {
+            return -(cells - moves) / 2;
+        }
+
+        // Nobody can win with 2 or fewer chips left
+        if moves >= cells - 2 {
+            return 0;
+        }
+
+        // The opponent cant win with their next chip, so the worst we can do is lose after that
+        let min = -(cells - 2 - moves) / 2;
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        // We cant win with our next chip either
+        let mut max = (cells - 1 - moves) / 2;
+
+        if let Some(score) = self.book_score(position) {
+            return score;
+        }
+
+        let key = position.key();
+        let stored = self.table.get(key) as i32;
+        if stored != 0 {
+            if stored > max_score - min_score + 1 {
+                // A lower bound
+                let min = stored + 2 * min_score - max_score - 2;
+                if alpha < min {
+                    alpha = min;
+                    if alpha >= beta {
+                        return alpha;
+                    }
+                }
+            } else {
+                // An upper bound
+                max = stored + min_score - 1;
+            }
+        }
+
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        let mut sorter = MoveSorter::new();
+        for &col in self.column_order.iter().rev() {
+            let cell = next & column_mask::<u64>(&self.config, col);
+            if cell != 0 {
+                sorter.add(cell, position.move_score(cell));
+            }
+        }
+
+        while let Some(cell) = sorter.next() {
+            let mut child = *position;
+            child.play_cell(cell);
+
+            let score = -self.negamax(&child, -beta, -alpha);
+
+            if score >= beta {
+                // Store the lower bound
+                self.table
+                    .put(key, (score + max_score - 2 * min_score + 2) as u8);
+                return score;
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        // Store the upper bound
+        self.table.put(key, (alpha - min_score + 1) as u8);
+        alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays columns counting from 1, eg "4453"
+    fn position(config: BoardConfig, moves: &str) -> Position {
+        let mut position = Position::with_rules(config, Rules::Standard);
+        for c in moves.chars() {
+            let col = c
+                .to_digit(10)
+                .expect("Tried to play a column that isnt a digit") as usize;
+            position
+                .play(col - 1)
+                .expect("Tried to play a move that isnt allowed");
+        }
+        position
+    }
+
+    #[test]
+    fn evaluations_count_the_moves_on_odd_and_even_boards() {
+        let odd = BoardConfig::new(5, 7, 4).unwrap();
+        let seven = BoardConfig::new(7, 7, 4).unwrap();
+        let mut solver = Solver::new();
+
+        // Player 1 wins with their next chip, whatever the size of the board
+        for config in [BoardConfig::STANDARD, odd, seven] {
+            let position = position(config, "121212");
+            assert_eq!(
+                solver.evaluate(&position),
+                Some(Evaluation::Win { moves: 1 }),
+                "{config:?}"
+            );
+        }
+
+        // Player 2 wins with their next chip, which on an odd board isnt the same as player 1 doing it
+        for config in [BoardConfig::STANDARD, odd, seven] {
+            let position = position(config, "1212324");
+            assert_eq!(
+                solver.evaluate(&position),
+                Some(Evaluation::Win { moves: 1 }),
+                "{config:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn solves_wins_and_losses_next_move() {
+        let mut solver = Solver::new();
+
+        // Player 1 wins with their 4th chip, so has all but 3 of their 21 left
+        let win = position(BoardConfig::STANDARD, "121212");
+        assert_eq!(solver.solve(&win, false), 18);
+        assert_eq!(solver.solve(&win, true).signum(), 1);
+
+        // Player 1 has three in a row open at both ends, so player 2 cant stop it
+        let loss = position(BoardConfig::STANDARD, "44556");
+        assert_eq!(solver.solve(&loss, false), -18);
+        assert_eq!(solver.solve(&loss, true).signum(), -1);
+    }
+
+    // Small boards where the result is known (from John Tromp's table), so they solve quickly from the start
+    #[test]
+    fn solves_known_openings() {
+        let mut solver = Solver::new();
+
+        let draw = BoardConfig::new(4, 4, 4).unwrap();
+        assert_eq!(solver.solve(&position(draw, ""), false), 0);
+
+        // 6 columns and 4 rows is a win for player 2, with the very last chip
+        let second_wins = BoardConfig::new(4, 6, 4).unwrap();
+        assert_eq!(solver.solve(&position(second_wins, ""), false), -1);
+
+        let draw = BoardConfig::new(5, 5, 4).unwrap();
+        assert_eq!(solver.solve(&position(draw, ""), false), 0);
+    }
+
+    #[test]
+    fn analyze_agrees_with_solve() {
+        let mut solver = Solver::new();
+        let config = BoardConfig::new(5, 5, 4).unwrap();
+
+        for moves in ["", "3", "33", "3324", "12345", "33333"] {
+            let position = position(config, moves);
+            let scores = solver.analyze(&position);
+
+            for (col, score) in scores.iter().enumerate() {
+                let mut next = position;
+                match next.play(col) {
+                    Ok(MoveOutcome::Placed { .. }) => {
+                        assert_eq!(
+                            *score,
+                            Some(-solver.solve(&next, false)),
+                            "{moves} then {col}"
+                        )
+                    }
+                    Ok(_) => assert!(score.is_some()),
+                    Err(_) => assert_eq!(*score, None),
+                }
+            }
+
+            // The best move is as good as the position
+            let best = scores.iter().flatten().max().copied();
+            assert_eq!(best, Some(solver.solve(&position, false)), "{moves}");
+        }
+    }
+
+    // Counted out by hand: player 1 gets three along the bottom with both ends open, and wins with their
+    // 4th chip on move 7
+    #[test]
+    fn from_score_matches_a_counted_game() {
+        for (config, moves, score) in [
+            (BoardConfig::STANDARD, "44556", -18),
+            (BoardConfig::new(5, 5, 4).unwrap(), "22334", -10),
+            (BoardConfig::new(7, 7, 4).unwrap(), "22334", -22),
+        ] {
+            let position = position(config, moves);
+            assert_eq!(Solver::new().solve(&position, false), score, "{config:?}");
+            assert_eq!(
+                Evaluation::from_score(score, &config, 5),
+                Evaluation::Loss { moves: 2 }
+            );
+
+            // And from player 1's side, one move later
+            assert_eq!(
+                Evaluation::from_score(-score, &config, 6),
+                Evaluation::Win { moves: 1 }
+            );
+        }
+
+        assert_eq!(
+            Evaluation::from_score(0, &BoardConfig::STANDARD, 10),
+            Evaluation::Draw { moves: 32 }
+        );
+    }
+}
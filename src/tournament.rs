@@ -0,0 +1,858 @@
+// `tournament`: bots playing each other lots of times, to find out which is stronger and by how much
+// Anything that can be a player except a human can take part, so built in AIs and engines (see `engine`)
+//
+// Every opening gets played twice in each pairing, once with each bot going first, so neither gets an easier time
+// Openings are either read from a file (one move sequence per line) or a few random moves
+
+use crate::ai::best_move_in_time;
+use crate::book::{OpeningBook, DEFAULT_BOOK_PATH};
+use crate::engine::{EngineError, EngineProcess, EngineSettings};
+use crate::notation::Game;
+use crate::players::PlayerKind;
+use crate::rules::*;
+
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+// Games that are still going after this many moves are called a draw, for variants that can go on forever
+const MOVE_LIMIT: usize = 1000;
+
+// How sure the SPRT has to be, both ways
+const SPRT_ALPHA: f64 = 0.05;
+const SPRT_BETA: f64 = 0.05;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    RoundRobin,
+    // The first bot plays everyone else, but they dont play each other
+    Gauntlet,
+}
+
+struct Options {
+    bots: Vec<(String, PlayerKind)>,
+    mode: Mode,
+    // For each pairing, rounded up to an even number so the colours come out the same
+    games: usize,
+    openings: Option<String>,
+    random_plies: usize,
+    seed: u64,
+    movetime: Duration,
+    // The SPRT checks whether the elo difference is more like `elo0` or `elo1`
+    elo0: f64,
+    elo1: f64,
+    csv: Option<String>,
+    json: Option<String>,
+}
+
+impl Options {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            bots: Vec::new(),
+            mode: Mode::RoundRobin,
+            games: 10,
+            openings: None,
+            random_plies: 2,
+            seed: 1,
+            movetime: EngineSettings::from_args(args)?.movetime,
+            elo0: 0.,
+            elo1: 10.,
+            csv: None,
+            json: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            // Every option has a value
+            let Some(value) = args.next() else {
+                return Err(format!("{arg} needs a value after it"));
+            };
+
+            let number = |what: &str| -> Result<usize, String> {
+                value
+                    .parse()
+                    .map_err(|error| format!("invalid {what} '{value}': {error}"))
+            };
+
+            match arg.as_str() {
+                "--bot" => options.bots.push(parse_bot(value)?),
+                "--mode" => {
+                    options.mode = match value.as_str() {
+                        "round-robin" => Mode::RoundRobin,
+                        "gauntlet" => Mode::Gauntlet,
+                        _ => {
+                            return Err(format!(
+                                "unknown mode '{value}', expected round-robin or gauntlet"
+                            ))
+                        }
+                    }
+                }
+                "--games" => options.games = number("number of games")?.max(1),
+                "--openings" => options.openings = Some(value.clone()),
+                "--random-plies" => options.random_plies = number("number of random moves")?,
+                "--seed" => options.seed = number("seed")? as u64,
+                "--sprt" => {
+                    let bounds = value.split_once(',').and_then(|(elo0, elo1)| {
+                        Some((elo0.trim().parse().ok()?, elo1.trim().parse().ok()?))
+                    });
+                    let Some((elo0, elo1)) = bounds else {
+                        return Err(format!("invalid sprt bounds '{value}', expected eg 0,10"));
+                    };
+                    (options.elo0, options.elo1) = (elo0, elo1);
+                }
+                "--csv" => options.csv = Some(value.clone()),
+                "--json" => options.json = Some(value.clone()),
+                // Read by the things they belong to
+                "--movetime" | "--rules" | "--rows" | "--columns" | "--connect" => {}
+                _ => return Err(format!("unknown option '{arg}'")),
+            }
+        }
+
+        if options.bots.len() < 2 {
+            return Err("a tournament needs at least two bots, eg --bot ai:4 --bot ai:6".into());
+        }
+
+        Ok(options)
+    }
+}
+
+// Either just a player type, or a name for it then `=`, eg `fast=ai:4`
+fn parse_bot(value: &str) -> Result<(String, PlayerKind), String> {
+    let (name, kind) = match value.split_once('=') {
+        // The = could be part of an engine's command instead
+        Some((name, kind)) if !name.contains(':') => (name.to_string(), kind),
+        _ => (value.to_string(), value),
+    };
+
+    match kind.parse()? {
+        PlayerKind::Human => Err("humans cant play in tournaments".into()),
+        kind => Ok((name, kind)),
+    }
+}
+
+// Small and predictable, so a tournament can be run again with the same openings
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        // xorshift64, which needs a seed that isnt 0
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn random_opening(
+    config: BoardConfig,
+    rules: Rules,
+    plies: usize,
+    rng: &mut Rng,
+) -> Result<String, String> {
+    // A few goes, in case the random moves end the game straight away
+    for _ in 0..100 {
+        let mut game = Game::with_rules(config, rules);
+        for _ in 0..plies {
+            let moves = game.all_legal_moves();
+            if game.is_over() || moves.is_empty() {
+                break;
+            }
+            game.play_move(moves[rng.below(moves.len())])
+                .expect("Tried to play a legal move that wasnt allowed");
+        }
+
+        if !game.is_over() {
+            return Ok(game.to_notation().unwrap_or_default());
+        }
+    }
+
+    Err(format!(
+        "could not find {plies} random moves that dont end the game"
+    ))
+}
+
+fn read_openings(path: &str, config: BoardConfig, rules: Rules) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("could not read openings from {path}: {error}"))?;
+
+    let mut openings = Vec::new();
+    // Lines starting with # are comments
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match Game::from_notation_with_rules(config, rules, line) {
+            Ok(game) if !game.is_over() => openings.push(line.to_string()),
+            Ok(_) => {
+                return Err(format!(
+                    "opening '{line}' on line {} of {path} is already over",
+                    line_number + 1
+                ))
+            }
+            Err(error) => {
+                return Err(format!(
+                    "invalid opening '{line}' on line {} of {path}: {error}",
+                    line_number + 1
+                ))
+            }
+        }
+    }
+
+    if openings.is_empty() {
+        return Err(format!("{path} doesnt have any openings in it"));
+    }
+    Ok(openings)
+}
+
+// One of the players, kept between games so engines only start once
+struct Bot {
+    name: String,
+    kind: PlayerKind,
+    engine: Option<EngineProcess>,
+}
+
+// Why a game ended
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Ending {
+    Win,
+    Draw,
+    MoveLimit,
+    Time,
+    Forfeit,
+}
+
+impl Ending {
+    fn name(&self) -> &'static str {
+        match self {
+            Ending::Win => "win",
+            Ending::Draw => "draw",
+            Ending::MoveLimit => "move-limit",
+            Ending::Time => "time",
+            Ending::Forfeit => "forfeit",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+struct GameRecord {
+    // Counting every game in the tournament from 1
+    game: usize,
+    // Which bots played, by their index in the list
+    first: usize,
+    second: usize,
+    opening: String,
+    // 1.0 if the first player won, 0.5 for a draw
+    first_score: f64,
+    ending: Ending,
+    // Why an engine forfeited, if one did
+    detail: Option<String>,
+    moves: String,
+}
+
+impl Bot {
+    // The error is which way the bot lost by not moving
+    fn choose_move(
+        &mut self,
+        game: &Game,
+        movetime: Duration,
+        book: Option<&OpeningBook>,
+    ) -> Result<Move, (Ending, String)> {
+        match &self.kind {
+            // Built in AIs stop at the deadline, and play the deepest search they finished
+            PlayerKind::Ai { depth } => {
+                let deadline = Instant::now() + movetime;
+                best_move_in_time(game.position(), *depth, deadline, book)
+                    .ok_or_else(|| (Ending::Forfeit, "the AI couldnt find a move".into()))
+            }
+            PlayerKind::Engine { command } => {
+                let mut engine = match self.engine.take() {
+                    Some(engine) => engine,
+                    None => EngineProcess::start(command).map_err(engine_loss)?,
+                };
+
+                let next = engine.best_move(game, movetime).map_err(engine_loss)?;
+                // Anything that went wrong gets it restarted for the next game
+                self.engine = Some(engine);
+                Ok(next)
+            }
+            PlayerKind::Human | PlayerKind::Remote => {
+                panic!("Tried to get a move from a human in a tournament")
+            }
+        }
+    }
+}
+
+fn engine_loss(error: EngineError) -> (Ending, String) {
+    match error {
+        EngineError::Timeout(_) => (Ending::Time, error.to_string()),
+        _ => (Ending::Forfeit, error.to_string()),
+    }
+}
+
+fn play_game(
+    bots: &mut [Bot],
+    (first, second): (usize, usize),
+    start: &Game,
+    movetime: Duration,
+    book: Option<&OpeningBook>,
+) -> (f64, Ending, Option<String>, Game) {
+    let mut game = start.clone();
+
+    while !game.is_over() {
+        if game.moves_played() >= MOVE_LIMIT {
+            return (0.5, Ending::MoveLimit, None, game);
+        }
+
+        let side = game.to_move();
+        let bot = match side {
+            Side::First => first,
+            Side::Second => second,
+        };
+
+        match bots[bot].choose_move(&game, movetime, book) {
+            Ok(next) => {
+                game.play_move(next)
+                    .expect("Tried to play a move that was already checked");
+            }
+            Err((ending, detail)) => {
+                let first_score = if side == Side::First { 0. } else { 1. };
+                return (first_score, ending, Some(detail), game);
+            }
+        }
+    }
+
+    match game.winner() {
+        Some(Side::First) => (1., Ending::Win, None, game),
+        Some(Side::Second) => (0., Ending::Win, None, game),
+        None => (0.5, Ending::Draw, None, game),
+    }
+}
+
+// Wins, draws and losses from one side's point of view, with everything worked out from them
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize)]
+struct Score {
+    wins: usize,
+    draws: usize,
+    losses: usize,
+}
+
+impl Score {
+    fn add(&mut self, score: f64) {
+        match score {
+            s if s > 0.5 => self.wins += 1,
+            s if s < 0.5 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // As a fraction of the most it could have been
+    fn fraction(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.) / self.games() as f64
+    }
+
+    // How much the score of a single game varies
+    fn variance(&self) -> f64 {
+        let n = self.games() as f64;
+        let p = self.fraction();
+        (self.wins as f64 * (1. - p).powi(2)
+            + self.draws as f64 * (0.5 - p).powi(2)
+            + self.losses as f64 * p.powi(2))
+            / n
+    }
+
+    // The elo difference this score suggests, and how far off that could be (95% of the time)
+    // None if the score is all wins or all losses, since that could be any difference at all
+    fn elo(&self) -> Option<(f64, f64)> {
+        if self.games() == 0 {
+            return None;
+        }
+
+        let p = self.fraction();
+        if p <= 0. || p >= 1. {
+            return None;
+        }
+
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let (low, high) = (p - margin, p + margin);
+
+        // Clamped, since the ends of the range can go past what elo can show
+        let clamp = |p: f64| p.clamp(1e-6, 1. - 1e-6);
+        let error = (elo_from_fraction(clamp(high)) - elo_from_fraction(clamp(low))) / 2.;
+        Some((elo_from_fraction(p), error))
+    }
+
+    // The log likelihood ratio of the difference being `elo1` rather than `elo0`
+    // Uses the normal approximation, which is close enough once there are a few games
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let variance = self.variance();
+        if self.games() == 0 || variance == 0. {
+            return 0.;
+        }
+
+        let (s0, s1) = (fraction_from_elo(elo0), fraction_from_elo(elo1));
+        (s1 - s0) * (2. * self.fraction() - s0 - s1) / (2. * variance) * self.games() as f64
+    }
+}
+
+fn elo_from_fraction(p: f64) -> f64 {
+    -400. * (1. / p - 1.).log10()
+}
+
+fn fraction_from_elo(elo: f64) -> f64 {
+    1. / (1. + 10f64.powf(-elo / 400.))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum SprtResult {
+    // More like elo1
+    H1,
+    // More like elo0
+    H0,
+    Inconclusive,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+struct Sprt {
+    elo0: f64,
+    elo1: f64,
+    llr: f64,
+    lower: f64,
+    upper: f64,
+    result: SprtResult,
+}
+
+fn sprt(score: &Score, elo0: f64, elo1: f64) -> Sprt {
+    let llr = score.llr(elo0, elo1);
+    let lower = (SPRT_BETA / (1. - SPRT_ALPHA)).ln();
+    let upper = ((1. - SPRT_BETA) / SPRT_ALPHA).ln();
+
+    let result = if llr >= upper {
+        SprtResult::H1
+    } else if llr <= lower {
+        SprtResult::H0
+    } else {
+        SprtResult::Inconclusive
+    };
+
+    Sprt {
+        elo0,
+        elo1,
+        llr,
+        lower,
+        upper,
+        result,
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+struct Standing {
+    bot: String,
+    #[serde(flatten)]
+    score: Score,
+    elo: Option<f64>,
+    elo_error: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+struct Pairing {
+    // From `first`'s point of view
+    first: String,
+    second: String,
+    #[serde(flatten)]
+    score: Score,
+    elo: Option<f64>,
+    elo_error: Option<f64>,
+    sprt: Sprt,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+struct Report {
+    rules: String,
+    board: String,
+    mode: Mode,
+    movetime_ms: u128,
+    bots: Vec<String>,
+    games: Vec<GameRecord>,
+    standings: Vec<Standing>,
+    pairings: Vec<Pairing>,
+}
+
+pub fn run_tournament(args: &[String]) -> Result<(), String> {
+    let options = Options::from_args(args)?;
+    let rules = Rules::from_args(args)?;
+    let config = BoardConfig::from_args(args, rules.variant().default_config())?;
+
+    let mut rng = Rng(options.seed.max(1));
+    let rounds = options.games.div_ceil(2);
+    let openings = match &options.openings {
+        Some(path) => read_openings(path, config, rules)?,
+        None => (0..rounds)
+            .map(|_| random_opening(config, rules, options.random_plies, &mut rng))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let book = OpeningBook::load_if_present(DEFAULT_BOOK_PATH).unwrap_or_else(|error| {
+        eprintln!("warning: not using the opening book at {DEFAULT_BOOK_PATH}: {error}");
+        None
+    });
+
+    let mut bots: Vec<Bot> = options
+        .bots
+        .iter()
+        .map(|(name, kind)| Bot {
+            name: name.clone(),
+            kind: kind.clone(),
+            engine: None,
+        })
+        .collect();
+
+    let pairs: Vec<(usize, usize)> = match options.mode {
+        Mode::RoundRobin => (0..bots.len())
+            .flat_map(|a| (a + 1..bots.len()).map(move |b| (a, b)))
+            .collect(),
+        Mode::Gauntlet => (1..bots.len()).map(|b| (0, b)).collect(),
+    };
+
+    println!(
+        "{} bots, {} pairings, {} games each, {}ms a move",
+        bots.len(),
+        pairs.len(),
+        rounds * 2,
+        options.movetime.as_millis()
+    );
+
+    let mut records = Vec::new();
+    for &(a, b) in &pairs {
+        for round in 0..rounds {
+            let opening = &openings[round % openings.len()];
+            let start = Game::from_notation_with_rules(config, rules, opening)
+                .expect("Tried to start from an opening that was already checked");
+
+            // The same opening both ways round
+            for (first, second) in [(a, b), (b, a)] {
+                let (first_score, ending, detail, game) = play_game(
+                    &mut bots,
+                    (first, second),
+                    &start,
+                    options.movetime,
+                    book.as_ref(),
+                );
+
+                let record = GameRecord {
+                    game: records.len() + 1,
+                    first,
+                    second,
+                    opening: opening.clone(),
+                    first_score,
+                    ending,
+                    detail,
+                    moves: game.to_notation().unwrap_or_default(),
+                };
+                println!("{}", game_line(&record, &bots));
+                records.push(record);
+            }
+        }
+    }
+
+    let report = make_report(&options, rules, config, &bots, &pairs, records);
+    print!("{}", report_text(&report));
+
+    if let Some(path) = &options.csv {
+        std::fs::write(path, report_csv(&report))
+            .map_err(|error| format!("could not write {path}: {error}"))?;
+        println!("Wrote the games to {path}");
+    }
+
+    if let Some(path) = &options.json {
+        let json =
+            serde_json::to_string_pretty(&report).expect("Failed to turn a report into json");
+        std::fs::write(path, json).map_err(|error| format!("could not write {path}: {error}"))?;
+        println!("Wrote the results to {path}");
+    }
+
+    Ok(())
+}
+
+fn result_text(first_score: f64) -> &'static str {
+    match first_score {
+        s if s > 0.5 => "1-0",
+        s if s < 0.5 => "0-1",
+        _ => "1/2-1/2",
+    }
+}
+
+fn game_line(record: &GameRecord, bots: &[Bot]) -> String {
+    let mut line = format!(
+        "Game {}: {} vs {}, opening '{}': {} ({})",
+        record.game,
+        bots[record.first].name,
+        bots[record.second].name,
+        record.opening,
+        result_text(record.first_score),
+        record.ending.name()
+    );
+    if let Some(detail) = &record.detail {
+        line += &format!(", {detail}");
+    }
+    line
+}
+
+fn make_report(
+    options: &Options,
+    rules: Rules,
+    config: BoardConfig,
+    bots: &[Bot],
+    pairs: &[(usize, usize)],
+    games: Vec<GameRecord>,
+) -> Report {
+    let mut totals = vec![Score::default(); bots.len()];
+    for game in &games {
+        totals[game.first].add(game.first_score);
+        totals[game.second].add(1. - game.first_score);
+    }
+
+    let mut standings: Vec<Standing> = bots
+        .iter()
+        .zip(totals)
+        .map(|(bot, score)| Standing {
+            bot: bot.name.clone(),
+            elo: score.elo().map(|(elo, _)| elo),
+            elo_error: score.elo().map(|(_, error)| error),
+            score,
+        })
+        .collect();
+    standings.sort_by(|a, b| b.score.fraction().total_cmp(&a.score.fraction()));
+
+    let pairings = pairs
+        .iter()
+        .map(|&(a, b)| {
+            let mut score = Score::default();
+            for game in &games {
+                if (game.first, game.second) == (a, b) {
+                    score.add(game.first_score);
+                } else if (game.first, game.second) == (b, a) {
+                    score.add(1. - game.first_score);
+                }
+            }
+
+            Pairing {
+                first: bots[a].name.clone(),
+                second: bots[b].name.clone(),
+                elo: score.elo().map(|(elo, _)| elo),
+                elo_error: score.elo().map(|(_, error)| error),
+                sprt: sprt(&score, options.elo0, options.elo1),
+                score,
+            }
+        })
+        .collect();
+
+    Report {
+        rules: rules.name().to_string(),
+        board: config.to_string(),
+        mode: options.mode,
+        movetime_ms: options.movetime.as_millis(),
+        bots: bots.iter().map(|bot| bot.name.clone()).collect(),
+        games,
+        standings,
+        pairings,
+    }
+}
+
+fn elo_text(elo: Option<f64>, error: Option<f64>) -> String {
+    match (elo, error) {
+        (Some(elo), Some(error)) => {
+            // Tiny negative numbers would show as -0 otherwise
+            let elo = if elo.round() == 0. { 0. } else { elo };
+            format!("{elo:+.0} +/- {error:.0}")
+        }
+        _ => "-".to_string(),
+    }
+}
+
+fn report_text(report: &Report) -> String {
+    let width = report
+        .bots
+        .iter()
+        .map(|bot| bot.len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    let mut text = String::new();
+
+    writeln!(text).unwrap();
+    writeln!(
+        text,
+        "{:<4}  {:<width$}  {:>5}  {:>4}  {:>5}  {:>6}  {:>6}  Elo",
+        "Rank", "Bot", "Games", "Wins", "Draws", "Losses", "Score"
+    )
+    .unwrap();
+    for (rank, standing) in report.standings.iter().enumerate() {
+        let score = &standing.score;
+        writeln!(
+            text,
+            "{:<4}  {:<width$}  {:>5}  {:>4}  {:>5}  {:>6}  {:>5.1}%  {}",
+            rank + 1,
+            standing.bot,
+            score.games(),
+            score.wins,
+            score.draws,
+            score.losses,
+            score.fraction() * 100.,
+            elo_text(standing.elo, standing.elo_error)
+        )
+        .unwrap();
+    }
+
+    writeln!(text).unwrap();
+    for pairing in &report.pairings {
+        let score = &pairing.score;
+        let sprt = &pairing.sprt;
+        writeln!(
+            text,
+            "{} vs {}: +{} ={} -{}, elo {}",
+            pairing.first,
+            pairing.second,
+            score.wins,
+            score.draws,
+            score.losses,
+            elo_text(pairing.elo, pairing.elo_error)
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "  SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2}), {}",
+            sprt.elo0,
+            sprt.elo1,
+            sprt.llr,
+            sprt.lower,
+            sprt.upper,
+            match sprt.result {
+                SprtResult::H1 =>
+                    format!("{} is stronger by at least {}", pairing.first, sprt.elo1),
+                SprtResult::H0 => format!("{} is not stronger by {}", pairing.first, sprt.elo1),
+                SprtResult::Inconclusive => "not enough games to tell yet".to_string(),
+            }
+        )
+        .unwrap();
+    }
+
+    text
+}
+
+// Quotes anything with a comma or a quote in it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// One line per game
+fn report_csv(report: &Report) -> String {
+    let mut csv = String::from("game,first,second,opening,result,ending,detail,moves\n");
+    for game in &report.games {
+        let fields = [
+            game.game.to_string(),
+            report.bots[game.first].clone(),
+            report.bots[game.second].clone(),
+            game.opening.clone(),
+            result_text(game.first_score).to_string(),
+            game.ending.name().to_string(),
+            game.detail.clone().unwrap_or_default(),
+            game.moves.clone(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        writeln!(csv, "{}", fields.join(",")).unwrap();
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(wins: usize, draws: usize, losses: usize) -> Score {
+        Score {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn assert_close(found: f64, expected: f64) {
+        assert!(
+            (found - expected).abs() < 0.01,
+            "expected {expected}, found {found}"
+        );
+    }
+
+    #[test]
+    fn elo_from_scores() {
+        let (elo, error) = score(10, 10, 10).elo().unwrap();
+        assert_close(elo, 0.);
+        assert_close(error, 104.56);
+
+        // Two thirds of the points is 120 elo either way
+        let (elo, error) = score(15, 10, 5).elo().unwrap();
+        assert_close(elo, 120.41);
+        assert_close(error, 108.84);
+        let (elo, _) = score(5, 10, 15).elo().unwrap();
+        assert_close(elo, -120.41);
+
+        // More games, smaller error bars
+        let (elo, error) = score(300, 100, 100).elo().unwrap();
+        assert_close(elo, 147.19);
+        assert_close(error, 29.10);
+
+        assert_eq!(score(10, 0, 0).elo(), None);
+        assert_eq!(score(0, 0, 10).elo(), None);
+        assert_eq!(score(0, 0, 0).elo(), None);
+    }
+
+    #[test]
+    fn llr_from_scores() {
+        assert_close(score(15, 10, 5).llr(0., 10.), 0.50);
+        assert_close(score(5, 10, 15).llr(0., 10.), -0.54);
+        assert_close(score(1000, 1000, 1000).llr(0., 10.), -1.86);
+
+        // Every game going the same way says nothing about how big the difference is
+        assert_eq!(score(10, 0, 0).llr(0., 10.), 0.);
+        assert_eq!(score(0, 0, 0).llr(0., 10.), 0.);
+    }
+
+    #[test]
+    fn sprt_from_scores() {
+        let result = sprt(&score(300, 100, 100), 0., 10.);
+        assert_close(result.llr, 8.67);
+        assert_close(result.lower, -2.94);
+        assert_close(result.upper, 2.94);
+        assert_eq!(result.result, SprtResult::H1);
+
+        assert_eq!(
+            sprt(&score(3000, 3000, 3000), 0., 10.).result,
+            SprtResult::H0
+        );
+        assert_eq!(
+            sprt(&score(1000, 1000, 1000), 0., 10.).result,
+            SprtResult::Inconclusive
+        );
+        assert_eq!(
+            sprt(&score(15, 10, 5), 0., 10.).result,
+            SprtResult::Inconclusive
+        );
+    }
+}
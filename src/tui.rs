@@ -0,0 +1,237 @@
+// Playing in a terminal instead of a window, for over ssh or anywhere else without a display
+// Same rules and AI as the window, the board is just drawn with text instead
+
+use crate::ai::best_move_with_book;
+use crate::book::{OpeningBook, DEFAULT_BOOK_PATH};
+use crate::engine::{EngineProcess, EngineSettings};
+use crate::input_handlers::{input, yes_no_input};
+use crate::notation::{move_notation, parse_move, ForfeitReason, Game};
+use crate::players::{PlayerKind, PlayerKinds};
+use crate::rules::*;
+use crate::save::SaveFile;
+
+use inline_colorization::*;
+use std::path::Path;
+
+pub fn run_tui(
+    kinds: &PlayerKinds,
+    engine_settings: EngineSettings,
+    starting: Game,
+    load: Option<&Path>,
+) -> Result<(), String> {
+    // A save replaces whatever else the game would have started from
+    let starting = match load {
+        Some(path) => SaveFile::load(path)
+            .and_then(|save| save.validate())
+            .map_err(|error| format!("could not load {}: {error}", path.display()))?,
+        None => starting,
+    };
+
+    let book = load_book();
+
+    loop {
+        let mut game = starting.clone();
+        if !play_game(&mut game, kinds, engine_settings, book.as_ref()) {
+            return Ok(());
+        }
+
+        if !yes_no_input("Play again? (y/n) ") {
+            return Ok(());
+        }
+    }
+}
+
+fn load_book() -> Option<OpeningBook> {
+    OpeningBook::load_if_present(DEFAULT_BOOK_PATH).unwrap_or_else(|error| {
+        eprintln!("warning: not using the opening book at {DEFAULT_BOOK_PATH}: {error}");
+        None
+    })
+}
+
+fn player_name(side: Side) -> String {
+    match side {
+        Side::First => format!("{color_red}Player 1 (X){color_reset}"),
+        Side::Second => format!("{color_yellow}Player 2 (O){color_reset}"),
+    }
+}
+
+// Plays until someone wins, returns false if the players wanted to stop instead
+fn play_game(
+    game: &mut Game,
+    kinds: &PlayerKinds,
+    engine_settings: EngineSettings,
+    book: Option<&OpeningBook>,
+) -> bool {
+    // Started when theyre first needed, and stopped at the end of the game
+    let mut engines: [Option<EngineProcess>; 2] = [None, None];
+
+    println!();
+    println!(
+        "{style_bold}{}{style_reset}",
+        game.rules().variant().title()
+    );
+
+    while !game.is_over() {
+        print_board(game.position());
+        if let Some(status) = game.variant().status(game.position()) {
+            println!("{status}");
+        }
+
+        let side = game.to_move();
+        let kind = match side {
+            Side::First => &kinds.player1,
+            Side::Second => &kinds.player2,
+        };
+
+        let next = match kind {
+            PlayerKind::Human => match read_move(game, side) {
+                Some(next) => next,
+                None => return false,
+            },
+            PlayerKind::Ai { depth } => {
+                let Some(next) = best_move_with_book(game.position(), *depth, book) else {
+                    panic!("Tried to find an AI move, but there werent any")
+                };
+                println!("{} played {}", player_name(side), move_notation(next));
+                next
+            }
+            PlayerKind::Engine { command } => {
+                let index = match side {
+                    Side::First => 0,
+                    Side::Second => 1,
+                };
+
+                let engine = match engines[index].take() {
+                    Some(engine) => Ok(engine),
+                    None => EngineProcess::start(command),
+                };
+                let result = engine.and_then(|mut engine| {
+                    let next = engine.best_move(game, engine_settings.movetime)?;
+                    engines[index] = Some(engine);
+                    Ok(next)
+                });
+
+                match result {
+                    Ok(next) => {
+                        println!("{} played {}", player_name(side), move_notation(next));
+                        next
+                    }
+                    // A broken engine loses, rather than stopping everything
+                    Err(error) => {
+                        println!("{} forfeits the game: {error}", player_name(side));
+                        game.forfeit(side, ForfeitReason::Engine);
+                        continue;
+                    }
+                }
+            }
+            PlayerKind::Remote => panic!("Tried to play a remote player in the terminal"),
+        };
+
+        if let Err(error) = game.play_move(next) {
+            println!("That move isnt allowed, {error}");
+        }
+    }
+
+    print_board(game.position());
+    match game.winner() {
+        Some(side) => println!("{} wins!", player_name(side)),
+        None => println!("Draw!"),
+    }
+
+    if let Some(notation) = game.to_notation() {
+        println!("Moves: {notation}");
+    }
+
+    true
+}
+
+// Asks until it gets something that looks like a move, None if they want to quit
+// Whether the rules allow it gets checked when its played
+fn read_move(game: &Game, side: Side) -> Option<Move> {
+    let variant = game.variant();
+    let columns = game.config().columns;
+
+    let mut prompt = format!("{}, pick a column (1-{columns})", player_name(side));
+    if !variant.has_gravity() {
+        prompt += " then a row (eg 2B)";
+    }
+    if variant.has_pops() {
+        prompt += ", p<column> to pop";
+    }
+    for special in variant.specials() {
+        prompt += &format!(", {}<column> for the {}", special.letter(), special.name());
+    }
+    prompt += " or q to quit: ";
+
+    loop {
+        let text = input(&prompt);
+        if text.trim() == "q" {
+            return None;
+        }
+
+        match parse_move(&text) {
+            Ok(next) => return Some(next),
+            Err(_) => println!("'{}' isnt a move, try again", text.trim()),
+        }
+    }
+}
+
+fn print_board(position: &Position) {
+    println!();
+    println!("{}", board_text(position, true));
+}
+
+// Top row first, with the column numbers along the bottom
+// Without gravity the rows get their letters down the side too, since they have to be typed in
+// Without colour its plain text, for anywhere that wont show the colour codes
+pub fn board_text(position: &Position, colour: bool) -> String {
+    let paint = |code: &'static str| if colour { code } else { "" };
+    let (red, yellow, blue, reset) = (
+        paint(color_red),
+        paint(color_yellow),
+        paint(color_blue),
+        paint(color_reset),
+    );
+
+    let config = position.config();
+    let lettered = !position.variant().has_gravity();
+    let win_cells = position.win().map(|win| win.cells).unwrap_or_default();
+    let margin = if lettered { "  " } else { "" };
+
+    let mut lines = Vec::new();
+    for row in (0..config.rows).rev() {
+        let mut line = String::new();
+        if lettered {
+            line += &format!("{} ", (b'A' + row as u8) as char);
+        }
+        line += &format!("{blue}|{reset}");
+
+        for col in 0..config.columns {
+            let chip = match position.get(row, col) {
+                Some(Side::First) => format!("{red}X{reset}"),
+                Some(Side::Second) => format!("{yellow}O{reset}"),
+                None => ".".to_string(),
+            };
+
+            // The winning line stands out from everything else
+            if colour && win_cells.contains(&(row, col)) {
+                line += &format!(" {style_bold}{style_underline}{chip}{style_reset}");
+            } else {
+                line += &format!(" {chip}");
+            }
+        }
+
+        line += &format!(" {blue}|{reset}");
+        lines.push(line);
+    }
+
+    lines.push(format!(
+        "{margin}{blue}+{}+{reset}",
+        "-".repeat(config.columns * 2 + 1)
+    ));
+
+    let numbers: String = (1..=config.columns).map(|col| format!(" {col}")).collect();
+    lines.push(format!("{margin} {numbers}"));
+
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,581 @@
+// Every set of rules the game can be played by
+// `Position` only knows how to move chips around, what is actually allowed, whos turn it is next
+// and who has won is all decided here. Adding a new set of rules means a new `Variant`,
+// and a new entry in `Rules` so it can be picked and saved
+
+use crate::rules::{BoardConfig, Move, MoveError, Position, Side, Special};
+
+pub trait Variant: Sync {
+    // Used on the command line and in save files, so it shouldnt ever change
+    fn name(&self) -> &'static str;
+
+    // For the menu
+    fn title(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+
+    // The board it is meant to be played on, it still works on any other size
+    fn default_config(&self) -> BoardConfig {
+        BoardConfig::STANDARD
+    }
+
+    // Anything that should be on the board before the first move
+    fn setup(&self, _position: &mut Position) {}
+
+    // Every move whoever is to move could play, whether or not someone has already won
+    fn moves(&self, position: &Position) -> Vec<Move>;
+
+    // Play a move in a column that exists, in a game that isnt over yet, returning the row it happened in
+    // This is also where the turn gets handed over, and the winner worked out
+    // If it returns an error, the position has to be left how it was
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError>;
+
+    // Nobody can move, so its a draw
+    fn is_stuck(&self, position: &Position) -> bool {
+        self.moves(position).is_empty()
+    }
+
+    // The chips to highlight once `winner` has won
+    fn winning_chips(&self, position: &Position, winner: Side) -> u128 {
+        position.line_chips(winner)
+    }
+
+    // Anything the players need to know that isnt on the board, like a score
+    fn status(&self, _position: &Position) -> Option<String> {
+        None
+    }
+
+    // How much better off `side` is than the chips on the board show, for the ai
+    fn score_bonus(&self, _position: &Position, _side: Side) -> i32 {
+        0
+    }
+
+    // Whether chips fall (so you click on columns) or stay where they are put (so you click on holes)
+    fn has_gravity(&self) -> bool {
+        true
+    }
+
+    fn has_pops(&self) -> bool {
+        false
+    }
+
+    fn specials(&self) -> &'static [Special] {
+        &[]
+    }
+}
+
+/// Anything a variant has to remember that isnt on the board
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct VariantState {
+    // Pop Ten: whether the board has been filled up yet, how many chips each side has kept,
+    // and the column a chip was just popped out of, which has to be dropped back in somewhere else
+    pub filled: bool,
+    pub kept: [u8; 2],
+    pub pending: Option<usize>,
+    // Power Up: the specials each side has already used, see `Special::bit`
+    pub used: [u8; 2],
+}
+
+// Pops are the thing people are most likely to try with the wrong rules, so they get their own error
+fn not_allowed(next: Move) -> MoveError {
+    match next {
+        Move::Pop(col) => MoveError::CantPop(col),
+        _ => MoveError::NotAllowed,
+    }
+}
+
+fn drops(position: &Position) -> Vec<Move> {
+    (0..position.config().columns)
+        .filter(|&col| position.lowest_free_row(col).is_some())
+        .map(Move::Drop)
+        .collect()
+}
+
+fn owns_bottom(position: &Position, col: usize) -> bool {
+    position.get(0, col) == Some(position.to_move())
+}
+
+// A chip being added can only make a line for whoever added it
+fn check_line(position: &mut Position) {
+    let side = position.to_move();
+    if position.has_line(side) {
+        position.set_winner(side);
+    }
+}
+
+fn pass_turn(position: &mut Position) {
+    position.set_to_move(position.to_move().other());
+}
+
+// The normal move, drop a chip and hand over to the other player
+fn drop_and_pass(position: &mut Position, col: usize) -> Result<usize, MoveError> {
+    let row = position.drop_chip(col)?;
+    check_line(position);
+    pass_turn(position);
+    Ok(row)
+}
+
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+
+    fn title(&self) -> &'static str {
+        "Standard"
+    }
+
+    fn description(&self) -> &'static str {
+        "Take turns dropping chips, the first to get 4 in a row wins"
+    }
+
+    fn moves(&self, position: &Position) -> Vec<Move> {
+        drops(position)
+    }
+
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError> {
+        match next {
+            Move::Drop(col) => drop_and_pass(position, col),
+            _ => Err(not_allowed(next)),
+        }
+    }
+
+    // Quicker than working out every move, and the ai calls this a lot
+    fn is_stuck(&self, position: &Position) -> bool {
+        position.is_full()
+    }
+}
+
+pub struct PopOut;
+
+impl Variant for PopOut {
+    fn name(&self) -> &'static str {
+        "popout"
+    }
+
+    fn title(&self) -> &'static str {
+        "PopOut"
+    }
+
+    fn description(&self) -> &'static str {
+        "You can also pop one of your own chips out of the bottom row, and everything above it falls down"
+    }
+
+    fn moves(&self, position: &Position) -> Vec<Move> {
+        let pops = (0..position.config().columns)
+            .filter(|&col| owns_bottom(position, col))
+            .map(Move::Pop);
+        drops(position).into_iter().chain(pops).collect()
+    }
+
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError> {
+        match next {
+            Move::Drop(col) => drop_and_pass(position, col),
+            Move::Pop(col) => {
+                if !owns_bottom(position, col) {
+                    return Err(MoveError::CantPop(col));
+                }
+
+                let side = position.to_move();
+                position.remove_chip(0, col);
+
+                // The chips that fell can make lines for either player, or both, in which case whoever popped wins
+                if position.has_line(side) {
+                    position.set_winner(side);
+                } else if position.has_line(side.other()) {
+                    position.set_winner(side.other());
+                }
+
+                pass_turn(position);
+                Ok(0)
+            }
+            _ => Err(MoveError::NotAllowed),
+        }
+    }
+
+    fn has_pops(&self) -> bool {
+        true
+    }
+}
+
+pub struct FiveInARow;
+
+impl Variant for FiveInARow {
+    fn name(&self) -> &'static str {
+        "five-in-a-row"
+    }
+
+    fn title(&self) -> &'static str {
+        "5-in-a-Row"
+    }
+
+    fn description(&self) -> &'static str {
+        "A wider board with the outside columns already filled in, and you need 5 in a row"
+    }
+
+    fn default_config(&self) -> BoardConfig {
+        BoardConfig {
+            rows: 6,
+            columns: 9,
+            connect: 5,
+        }
+    }
+
+    // The outside columns alternate colours, the other way round on each side
+    // Too narrow a board would have nothing left to play in, so it just starts empty
+    fn setup(&self, position: &mut Position) {
+        let config = *position.config();
+        if config.columns < 3 {
+            return;
+        }
+
+        for row in 0..config.rows {
+            let side = if row % 2 == 0 {
+                Side::First
+            } else {
+                Side::Second
+            };
+
+            position.set_to_move(side);
+            position.place_chip(row, 0);
+            position.set_to_move(side.other());
+            position.place_chip(row, config.columns - 1);
+        }
+
+        position.set_to_move(Side::First);
+    }
+
+    fn moves(&self, position: &Position) -> Vec<Move> {
+        Standard.moves(position)
+    }
+
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError> {
+        Standard.play(position, next)
+    }
+
+    fn is_stuck(&self, position: &Position) -> bool {
+        Standard.is_stuck(position)
+    }
+}
+
+// Fill the board up, then take turns popping your own chips out of the bottom
+// A chip that was part of one of your own lines is kept, and you go again
+// Anything else has to be dropped back in on top of another column
+pub struct PopTen;
+
+impl PopTen {
+    // Ten on a normal board, fewer on boards too small to ever get that many
+    fn target(&self, position: &Position) -> u8 {
+        (position.config().cells() / 4).clamp(1, 10) as u8
+    }
+
+    // If `side` cant move, the other player goes instead
+    fn next_turn(&self, position: &mut Position, side: Side) {
+        position.set_to_move(side);
+        if self.moves(position).is_empty() {
+            position.set_to_move(side.other());
+        }
+    }
+}
+
+impl Variant for PopTen {
+    fn name(&self) -> &'static str {
+        "pop-ten"
+    }
+
+    fn title(&self) -> &'static str {
+        "Pop Ten"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fill the board, then pop your chips out. Chips from your own lines are kept, and the first to keep 10 wins"
+    }
+
+    fn moves(&self, position: &Position) -> Vec<Move> {
+        let columns = position.config().columns;
+        let state = position.state();
+
+        // Filling up goes one row at a time
+        if !state.filled {
+            let lowest = (0..columns).map(|col| position.height(col)).min();
+            return (0..columns)
+                .filter(|&col| Some(position.height(col)) == lowest)
+                .map(Move::Drop)
+                .collect();
+        }
+
+        // The popped chip goes back anywhere other than where it came from, unless thats the only place
+        if let Some(from) = state.pending {
+            let elsewhere: Vec<_> = drops(position)
+                .into_iter()
+                .filter(|&next| next != Move::Drop(from))
+                .collect();
+            if elsewhere.is_empty() {
+                return vec![Move::Drop(from)];
+            }
+            return elsewhere;
+        }
+
+        (0..columns)
+            .filter(|&col| owns_bottom(position, col))
+            .map(Move::Pop)
+            .collect()
+    }
+
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError> {
+        if !self.moves(position).contains(&next) {
+            return Err(match next {
+                Move::Drop(col) if position.lowest_free_row(col).is_none() => {
+                    MoveError::ColumnFull(col)
+                }
+                Move::Drop(col) => MoveError::Restricted(col),
+                _ => not_allowed(next),
+            });
+        }
+
+        let side = position.to_move();
+        match next {
+            Move::Pop(col) => {
+                let kept = position.line_chips(side) & position.cell(0, col) != 0;
+                position.remove_chip(0, col);
+
+                if kept {
+                    let target = self.target(position);
+                    let state = position.state_mut();
+                    state.kept[side.index()] += 1;
+                    if state.kept[side.index()] >= target {
+                        position.set_winner(side);
+                    }
+                    self.next_turn(position, side);
+                } else {
+                    position.state_mut().pending = Some(col);
+                }
+
+                Ok(0)
+            }
+            _ => {
+                let row = position.drop_chip(next.col())?;
+                position.state_mut().pending = None;
+                if position.is_full() {
+                    position.state_mut().filled = true;
+                }
+
+                self.next_turn(position, side.other());
+                Ok(row)
+            }
+        }
+    }
+
+    // Nobody wins with a line, so there is nothing to highlight
+    fn winning_chips(&self, _position: &Position, _winner: Side) -> u128 {
+        0
+    }
+
+    fn status(&self, position: &Position) -> Option<String> {
+        let state = position.state();
+        if !state.filled {
+            return Some("Filling the board, one row at a time".to_string());
+        }
+
+        let [first, second] = state.kept;
+        let target = self.target(position);
+        let kept = format!("Kept {first} to {second}, first to {target} wins");
+        if state.pending.is_some() {
+            return Some(format!(
+                "{kept}\nDrop the popped chip back in another column"
+            ));
+        }
+        Some(kept)
+    }
+
+    fn score_bonus(&self, position: &Position, side: Side) -> i32 {
+        let kept = position.state().kept;
+        (kept[side.index()] as i32 - kept[side.other().index()] as i32) * 50
+    }
+
+    fn has_pops(&self) -> bool {
+        true
+    }
+}
+
+// Each player gets one of each special chip, on top of the normal ones
+pub struct PowerUp;
+
+impl PowerUp {
+    fn unused(&self, position: &Position, side: Side) -> Vec<Special> {
+        let used = position.state().used[side.index()];
+        Special::ALL
+            .into_iter()
+            .filter(|special| used & special.bit() == 0)
+            .collect()
+    }
+
+    fn can_bomb(&self, position: &Position, col: usize) -> bool {
+        let height = position.height(col);
+        height > 0 && position.get(height - 1, col) == Some(position.to_move().other())
+    }
+}
+
+impl Variant for PowerUp {
+    fn name(&self) -> &'static str {
+        "power-up"
+    }
+
+    fn title(&self) -> &'static str {
+        "Power Up"
+    }
+
+    fn description(&self) -> &'static str {
+        "Each player has an anvil, a bomb and a double chip, which can each be used once"
+    }
+
+    fn moves(&self, position: &Position) -> Vec<Move> {
+        let columns = position.config().columns;
+        let mut moves = drops(position);
+
+        for special in self.unused(position, position.to_move()) {
+            for col in 0..columns {
+                let allowed = match special {
+                    Special::Anvil | Special::Double => position.lowest_free_row(col).is_some(),
+                    Special::Bomb => self.can_bomb(position, col),
+                };
+                if allowed {
+                    moves.push(Move::Special(special, col));
+                }
+            }
+        }
+
+        moves
+    }
+
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError> {
+        let (special, col) = match next {
+            Move::Drop(col) => return drop_and_pass(position, col),
+            Move::Special(special, col) => (special, col),
+            _ => return Err(not_allowed(next)),
+        };
+
+        let side = position.to_move();
+        if !self.unused(position, side).contains(&special) {
+            return Err(MoveError::SpecialUsed(special));
+        }
+
+        let row = match special {
+            // Smashes everything underneath it, then sits at the bottom
+            Special::Anvil => {
+                if position.lowest_free_row(col).is_none() {
+                    return Err(MoveError::ColumnFull(col));
+                }
+                position.clear_column(col);
+                position.place_chip(0, col);
+                check_line(position);
+                pass_turn(position);
+                0
+            }
+            // Taking chips away can never make a line
+            Special::Bomb => {
+                if !self.can_bomb(position, col) {
+                    return Err(MoveError::CantBomb(col));
+                }
+                let row = position.height(col) - 1;
+                position.remove_chip(row, col);
+                pass_turn(position);
+                row
+            }
+            // Same player goes again
+            Special::Double => {
+                let row = position.drop_chip(col)?;
+                check_line(position);
+                row
+            }
+        };
+
+        position.state_mut().used[side.index()] |= special.bit();
+        Ok(row)
+    }
+
+    fn status(&self, position: &Position) -> Option<String> {
+        let unused = self.unused(position, position.to_move());
+        if unused.is_empty() {
+            return Some("No specials left".to_string());
+        }
+
+        let names: Vec<_> = unused
+            .iter()
+            .map(|special| {
+                format!(
+                    "{} ({})",
+                    special.name(),
+                    special.letter().to_ascii_uppercase()
+                )
+            })
+            .collect();
+        Some(format!("Specials left: {}", names.join(", ")))
+    }
+
+    fn specials(&self) -> &'static [Special] {
+        &Special::ALL
+    }
+}
+
+// Chips stay wherever they are put, like noughts and crosses
+pub struct NoGravity;
+
+impl Variant for NoGravity {
+    fn name(&self) -> &'static str {
+        "no-gravity"
+    }
+
+    fn title(&self) -> &'static str {
+        "No Gravity"
+    }
+
+    fn description(&self) -> &'static str {
+        "Chips dont fall, put them in any empty hole. 3 in a row on a 3x3 board is noughts and crosses"
+    }
+
+    fn default_config(&self) -> BoardConfig {
+        BoardConfig {
+            rows: 3,
+            columns: 3,
+            connect: 3,
+        }
+    }
+
+    fn moves(&self, position: &Position) -> Vec<Move> {
+        let config = position.config();
+        (0..config.rows)
+            .flat_map(|row| (0..config.columns).map(move |col| Move::Place { row, col }))
+            .filter(|&next| match next {
+                Move::Place { row, col } => position.get(row, col).is_none(),
+                _ => false,
+            })
+            .collect()
+    }
+
+    fn play(&self, position: &mut Position, next: Move) -> Result<usize, MoveError> {
+        let Move::Place { row, col } = next else {
+            return Err(not_allowed(next));
+        };
+
+        if row >= position.config().rows {
+            return Err(MoveError::RowOutOfRange(row));
+        }
+        if position.get(row, col).is_some() {
+            return Err(MoveError::CellTaken { row, col });
+        }
+
+        position.place_chip(row, col);
+        check_line(position);
+        pass_turn(position);
+        Ok(row)
+    }
+
+    fn is_stuck(&self, position: &Position) -> bool {
+        position.is_full()
+    }
+
+    fn has_gravity(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,140 @@
+use crate::board::AppState;
+use crate::menu::start_new_game;
+use crate::players::{get_player1, Player};
+use crate::rules::Rules;
+
+use bevy::prelude::*;
+
+const ACTIVE_COLOUR: Color = Color::srgb(0.1, 0.4, 0.1);
+
+// A button down the left of the screen for every variant
+// Picking one starts a new game with those rules, on the board they are meant for
+pub fn plugin_variant_menu(app: &mut App) {
+    app.add_systems(Startup, spawn_variant_buttons);
+
+    // Only while a game is showing, the menus cover these up the rest of the time
+    app.add_systems(
+        Update,
+        update_variant_buttons
+            .run_if(in_state(AppState::InGame).or_else(in_state(AppState::GameOver))),
+    );
+    app.add_systems(Update, update_variant_button_colour);
+    app.add_systems(Update, update_variant_description);
+}
+
+#[derive(Component)]
+struct VariantButton(Rules);
+
+// What the current rules are, under the buttons
+#[derive(Component)]
+struct VariantDescription;
+
+fn spawn_variant_buttons(mut command: Commands) {
+    command
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(5.0),
+                top: Val::Px(5.0),
+                width: Val::Px(200.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(5.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for rules in Rules::ALL {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                ..default()
+                            },
+                            background_color: Color::BLACK.into(),
+                            ..default()
+                        },
+                        VariantButton(rules),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            rules.variant().title(),
+                            TextStyle::default(),
+                        ));
+                    });
+            }
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                ),
+                VariantDescription,
+            ));
+        });
+}
+
+fn update_variant_buttons(
+    mut command: Commands,
+    buttons: Query<(&Interaction, &VariantButton), Changed<Interaction>>,
+    players: Query<&Player>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, VariantButton(rules)) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Changing these respawns the board, and zooms the camera to fit it
+        let config = rules.variant().default_config();
+        command.insert_resource(config);
+        command.insert_resource(*rules);
+        start_new_game(
+            &mut command,
+            &mut next_state,
+            config,
+            *rules,
+            get_player1(&players),
+        );
+    }
+}
+
+fn update_variant_button_colour(
+    rules: Res<Rules>,
+    mut buttons: Query<(&VariantButton, &mut BackgroundColor)>,
+) {
+    if !rules.is_changed() {
+        return;
+    }
+
+    for (VariantButton(button_rules), mut colour) in &mut buttons {
+        *colour = if *button_rules == *rules {
+            ACTIVE_COLOUR
+        } else {
+            Color::BLACK
+        }
+        .into();
+    }
+}
+
+fn update_variant_description(
+    rules: Res<Rules>,
+    mut description: Query<&mut Text, With<VariantDescription>>,
+) {
+    if !rules.is_changed() {
+        return;
+    }
+
+    let mut text = description.single_mut();
+    *text = Text::from_section(
+        rules.variant().description(),
+        TextStyle {
+            font_size: 16.0,
+            ..default()
+        },
+    );
+}
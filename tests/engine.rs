@@ -0,0 +1,115 @@
+// Runs the real `connect4-engine`, and talks the engine protocol to it by hand
+
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+
+struct Engine {
+    process: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl Engine {
+    fn start(args: &[&str]) -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_connect4-engine"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start the engine");
+
+        let stdin = process.stdin.take().expect("Failed to write to the engine");
+        let stdout = process.stdout.take().expect("Failed to read the engine");
+
+        Engine {
+            process,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.stdin, "{line}").expect("Failed to send a command");
+    }
+
+    // The next line that isnt info
+    fn receive(&mut self) -> String {
+        loop {
+            let line = self
+                .stdout
+                .next()
+                .expect("The engine stopped before answering")
+                .expect("Failed to read the engine");
+
+            if !line.starts_with("info") {
+                return line;
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[test]
+fn answers_the_protocol() {
+    let mut engine = Engine::start(&[]);
+
+    engine.send("isready");
+    assert_eq!(engine.receive(), "readyok");
+
+    // Player 1 has three in column 1, and wins by playing a fourth
+    engine.send("position moves 121212");
+    engine.send("go movetime 200");
+    assert_eq!(engine.receive(), "bestmove 1");
+
+    // Other rules and sizes work too
+    engine.send("position rules popout board 5 5 4 moves 11");
+    engine.send("go movetime 200");
+    assert!(engine.receive().starts_with("bestmove "));
+
+    engine.send("quit");
+    assert_eq!(
+        engine.process.wait().map(|status| status.success()).ok(),
+        Some(true)
+    );
+}
+
+#[test]
+fn answers_in_time() {
+    let mut engine = Engine::start(&[]);
+
+    // Early in the game, where every depth takes a lot longer than the last
+    for (position, movetime) in [
+        ("position board 9 12 4 moves 55", 50),
+        ("position board 9 12 4 moves 55", 300),
+        ("position moves 4", 400),
+    ] {
+        engine.send(position);
+        let start = Instant::now();
+        engine.send(&format!("go movetime {movetime}"));
+
+        assert!(engine.receive().starts_with("bestmove "));
+        // With some slack for a busy machine, since the tests run alongside each other
+        assert!(
+            start.elapsed() < Duration::from_millis(movetime) + Duration::from_millis(250),
+            "took {:?} for {movetime}ms",
+            start.elapsed()
+        );
+    }
+}
+
+#[test]
+fn bad_options_are_refused() {
+    let status = Command::new(env!("CARGO_BIN_EXE_connect4-engine"))
+        .args(["--depth", "deep"])
+        .stderr(Stdio::null())
+        .status()
+        .expect("Failed to start the engine");
+    assert!(!status.success());
+}
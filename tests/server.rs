@@ -0,0 +1,508 @@
+// Runs a real `connect4-server`, and plays games on it with scripted clients
+// The clients speak the protocol from `src/net.rs` by hand, so these also check what goes over the wire
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Server {
+    process: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start() -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_connect4-server"))
+            .args(["--port", "0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start the server");
+
+        let stdout = process.stdout.take().expect("Failed to read the server");
+        let mut lines = BufReader::new(stdout).lines();
+        let first = lines
+            .next()
+            .expect("The server stopped before saying its port")
+            .expect("Failed to read the server");
+        let Some(port) = first.strip_prefix("Listening on port ") else {
+            panic!("Expected the server to say its port, but it said '{first}'")
+        };
+        let port = port.parse().expect("Failed to read the server's port");
+
+        // Keep reading what it prints, so it never gets stuck on a full pipe
+        thread::spawn(move || lines.for_each(drop));
+
+        Server { process, port }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    // Says hello, and waits for one back
+    fn connect(server: &Server, name: &str) -> Self {
+        let mut client = Client::connect_quietly(server);
+        client.send(json!({"type": "hello", "version": 3, "name": name}));
+        client.expect("hello");
+        client
+    }
+
+    fn connect_quietly(server: &Server) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", server.port)).expect("Failed to connect");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("Failed to set a timeout");
+        let reader = BufReader::new(stream.try_clone().expect("Failed to clone a stream"));
+        Client { stream, reader }
+    }
+
+    fn send(&mut self, message: Value) {
+        writeln!(self.stream, "{message}").expect("Failed to send a message");
+    }
+
+    // The next message that isnt a ping, or None if the server hung up
+    fn receive(&mut self) -> Option<Value> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(error) => panic!("Failed to hear from the server: {error}"),
+            }
+
+            let message: Value = serde_json::from_str(&line).expect("Got a message that isnt json");
+            if message["type"] != "ping" {
+                return Some(message);
+            }
+        }
+    }
+
+    // Waits for the server to hang up, false if it goes quiet instead
+    fn hung_up(&mut self) -> bool {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return true,
+                Ok(_) => {}
+                Err(error) => {
+                    return !matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                }
+            }
+        }
+    }
+
+    fn expect(&mut self, kind: &str) -> Value {
+        let Some(message) = self.receive() else {
+            panic!("Expected a {kind} message, but the server hung up")
+        };
+        assert_eq!(message["type"], kind, "Got {message}");
+        message
+    }
+
+    // Makes a lobby for a standard game, giving back its code
+    fn create(&mut self, moves: &str) -> String {
+        self.send(json!({"type": "create", "game": standard_game(moves)}));
+        let lobby = self.expect("lobby");
+        assert_eq!(lobby["seat"], 1);
+        assert_eq!(self.expect("game")["game"]["moves"], moves);
+
+        lobby["code"]
+            .as_str()
+            .expect("Got a lobby without a code")
+            .to_string()
+    }
+
+    // Gives back the lobby message, and the game
+    fn join(&mut self, kind: &str, code: &str) -> (Value, Value) {
+        self.send(json!({"type": kind, "code": code}));
+        (self.expect("lobby"), self.expect("game"))
+    }
+
+    fn play(&mut self, index: usize, notation: &str) {
+        self.send(json!({"type": "move", "index": index, "notation": notation}));
+    }
+}
+
+fn standard_game(moves: &str) -> Value {
+    json!({
+        "config": {"rows": 6, "columns": 7, "connect": 4},
+        "rules": "standard",
+        "moves": moves,
+    })
+}
+
+fn assert_move(message: Value, index: usize, notation: &str) {
+    assert_eq!(
+        message,
+        json!({"type": "move", "index": index, "notation": notation})
+    );
+}
+
+#[test]
+fn players_and_spectators_hear_every_move() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+    let mut carol = Client::connect(&server, "carol");
+
+    let code = alice.create("");
+
+    let (lobby, game) = bob.join("join", &code);
+    assert_eq!(lobby["seat"], 2);
+    assert_eq!(lobby["players"], json!(["alice", "bob"]));
+    assert_eq!(game["game"], standard_game(""));
+    assert_eq!(alice.expect("lobby")["players"], json!(["alice", "bob"]));
+
+    // Both seats are taken, so joining just watches
+    let (lobby, _) = carol.join("join", &code);
+    assert_eq!(lobby["seat"], Value::Null);
+    assert_eq!(lobby["spectators"], 1);
+    alice.expect("lobby");
+    bob.expect("lobby");
+
+    alice.play(0, "4");
+    assert_move(bob.expect("move"), 0, "4");
+    assert_move(carol.expect("move"), 0, "4");
+
+    bob.play(1, "3");
+    assert_move(alice.expect("move"), 1, "3");
+    assert_move(carol.expect("move"), 1, "3");
+}
+
+#[test]
+fn moves_that_cant_be_played_get_the_game_sent_back() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+
+    let code = alice.create("444444");
+    bob.join("join", &code);
+    alice.expect("lobby");
+
+    // Not their turn
+    bob.play(6, "1");
+    assert_eq!(bob.expect("game")["game"]["moves"], "444444");
+
+    // A full column, a column that isnt there, and a move that isnt next
+    for (index, notation) in [(6, "4"), (6, "9"), (6, "x"), (7, "1")] {
+        alice.play(index, notation);
+        assert_eq!(alice.expect("game")["game"]["moves"], "444444");
+    }
+
+    // None of which got as far as bob
+    alice.play(6, "1");
+    assert_move(bob.expect("move"), 6, "1");
+}
+
+#[test]
+fn a_new_game_can_only_start_once_the_last_one_is_over() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+
+    let code = alice.create("121212");
+    bob.join("join", &code);
+    alice.expect("lobby");
+
+    bob.send(json!({"type": "game", "game": standard_game("")}));
+    assert_eq!(bob.expect("game")["game"]["moves"], "121212");
+
+    // Four in a row for alice
+    alice.play(6, "1");
+    bob.expect("move");
+
+    bob.send(json!({"type": "game", "game": standard_game("")}));
+    assert_eq!(bob.expect("game")["game"]["moves"], "");
+    assert_eq!(alice.expect("game")["game"]["moves"], "");
+}
+
+#[test]
+fn players_that_leave_can_come_back() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+
+    let code = alice.create("");
+    bob.join("join", &code);
+    alice.expect("lobby");
+
+    alice.play(0, "4");
+    bob.expect("move");
+    drop(bob);
+    assert_eq!(alice.expect("lobby")["players"], json!(["alice", null]));
+
+    // Codes dont care about case
+    let mut bob = Client::connect(&server, "bob");
+    let (lobby, game) = bob.join("join", &code.to_lowercase());
+    assert_eq!(lobby["seat"], 2);
+    assert_eq!(game["game"]["moves"], "4");
+    assert_eq!(alice.expect("lobby")["players"], json!(["alice", "bob"]));
+
+    bob.play(1, "4");
+    assert_move(alice.expect("move"), 1, "4");
+}
+
+#[test]
+fn spectators_cant_move() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut carol = Client::connect(&server, "carol");
+
+    let code = alice.create("");
+    carol.join("watch", &code);
+
+    carol.play(0, "4");
+    assert_eq!(carol.expect("error")["message"], "only players can move");
+    assert_eq!(carol.receive(), None);
+}
+
+#[test]
+fn bad_requests_are_turned_away() {
+    let server = Server::start();
+
+    let mut nobody = Client::connect(&server, "nobody");
+    nobody.send(json!({"type": "join", "code": "ZZZZ"}));
+    assert_eq!(
+        nobody.expect("error")["message"],
+        "there is no lobby with the code 'ZZZZ'"
+    );
+    assert_eq!(nobody.receive(), None);
+
+    let mut rude = Client::connect_quietly(&server);
+    rude.play(0, "4");
+    assert_eq!(
+        rude.expect("error")["message"],
+        "the first message has to be a hello"
+    );
+
+    let mut old = Client::connect_quietly(&server);
+    old.send(json!({"type": "hello", "version": 0, "name": "old"}));
+    old.expect("error");
+
+    let mut cheat = Client::connect(&server, "cheat");
+    cheat.send(json!({"type": "create", "game": standard_game("44444444")}));
+    cheat.expect("error");
+}
+
+#[test]
+fn messages_that_never_end_are_cut_off() {
+    let server = Server::start();
+
+    let mut endless = Client::connect(&server, "endless");
+    let start = Instant::now();
+
+    // Writing can fail once the server has hung up, which is fine
+    let _ = endless.stream.write_all("a".repeat(100_000).as_bytes());
+    assert!(endless.hung_up());
+
+    // Well before the server would give up on someone who has gone quiet
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn names_are_cleaned_up() {
+    let server = Server::start();
+
+    let mut long = Client::connect(&server, "  a name that goes on and on\n");
+    let mut bob = Client::connect(&server, "bob");
+    let code = long.create("");
+    let (lobby, _) = bob.join("join", &code);
+    assert_eq!(lobby["players"], json!(["a name that goes", "bob"]));
+
+    let mut nobody = Client::connect_quietly(&server);
+    nobody.send(json!({"type": "hello", "version": 3, "name": " \t "}));
+    assert_eq!(nobody.expect("error")["message"], "a name cant be empty");
+}
+
+#[test]
+fn lobbies_close_once_everyone_has_left() {
+    let server = Server::start();
+
+    let mut alice = Client::connect(&server, "alice");
+    let code = alice.create("");
+    drop(alice);
+
+    // The server has to notice alice has gone before bob turns up
+    thread::sleep(Duration::from_millis(200));
+
+    let mut bob = Client::connect(&server, "bob");
+    bob.send(json!({"type": "join", "code": code}));
+    bob.expect("error");
+}
+
+#[test]
+fn lots_of_games_can_go_on_at_once() {
+    let server = Server::start();
+
+    let games: Vec<_> = (0..50)
+        .map(|_| {
+            let mut alice = Client::connect(&server, "alice");
+            let mut bob = Client::connect(&server, "bob");
+            let code = alice.create("");
+            bob.join("join", &code);
+            alice.expect("lobby");
+
+            thread::spawn(move || {
+                // Alice wins down the first column
+                for (index, notation) in "1212121".chars().enumerate() {
+                    let (mover, other) = if index % 2 == 0 {
+                        (&mut alice, &mut bob)
+                    } else {
+                        (&mut bob, &mut alice)
+                    };
+                    mover.play(index, &notation.to_string());
+                    assert_move(other.expect("move"), index, &notation.to_string());
+                }
+            })
+        })
+        .collect();
+
+    for game in games {
+        game.join().expect("A game went wrong");
+    }
+}
+
+#[test]
+fn chat_is_passed_on_in_order() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+    let mut carol = Client::connect(&server, "carol");
+
+    let code = alice.create("");
+    bob.join("join", &code);
+    alice.expect("lobby");
+    carol.join("watch", &code);
+    alice.expect("lobby");
+    bob.expect("lobby");
+
+    // Whoever sent it is filled in by the server, whatever they said it was
+    alice.send(json!({"type": "chat", "from": "bob", "text": "good luck"}));
+    alice.play(0, "4");
+    alice.send(json!({"type": "chat", "from": "alice", "text": "your turn"}));
+
+    for listener in [&mut bob, &mut carol] {
+        assert_eq!(
+            listener.expect("chat"),
+            json!({"type": "chat", "from": "alice", "text": "good luck"})
+        );
+        assert_move(listener.expect("move"), 0, "4");
+        assert_eq!(listener.expect("chat")["text"], "your turn");
+    }
+
+    // Spectators can talk too, and no one hears themselves
+    carol.send(json!({"type": "chat", "from": "carol", "text": "hello"}));
+    assert_eq!(alice.expect("chat")["from"], "carol");
+    assert_eq!(bob.expect("chat")["from"], "carol");
+    bob.play(1, "4");
+    assert_move(carol.expect("move"), 1, "4");
+}
+
+#[test]
+fn chat_is_cleaned_up_and_limited() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+
+    let code = alice.create("");
+    bob.join("join", &code);
+    alice.expect("lobby");
+
+    // Nothing left once the control characters are gone, so never sent on
+    alice.send(json!({"type": "chat", "from": "alice", "text": " \u{7}\t "}));
+    alice.send(json!({"type": "chat", "from": "alice", "text": "  a\u{1b}b  "}));
+    alice.send(json!({"type": "chat", "from": "alice", "text": "x".repeat(500)}));
+    assert_eq!(bob.expect("chat")["text"], "ab");
+    assert_eq!(bob.expect("chat")["text"], "x".repeat(120));
+
+    // Two of the five allowed at once have gone (dropped ones dont count), so only three of these get through
+    for n in 0..10 {
+        alice.send(json!({"type": "chat", "from": "alice", "text": format!("spam {n}")}));
+    }
+    alice.play(0, "4");
+    assert_eq!(bob.expect("chat")["text"], "spam 0");
+    assert_eq!(bob.expect("chat")["text"], "spam 1");
+    assert_eq!(bob.expect("chat")["text"], "spam 2");
+    assert_move(bob.expect("move"), 0, "4");
+}
+
+#[test]
+fn chat_needs_a_lobby() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+
+    alice.send(json!({"type": "chat", "from": "alice", "text": "anyone there?"}));
+    assert_eq!(alice.expect("error")["message"], "join a lobby first");
+}
+
+#[test]
+fn the_server_keeps_time() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+
+    alice.send(json!({"type": "create", "game": standard_game(""), "clock": "fischer:1m+5s"}));
+    let code = alice.expect("lobby")["code"].as_str().unwrap().to_string();
+    alice.expect("game");
+    assert_eq!(alice.expect("clock")["remaining"], json!([60000, 60000]));
+
+    bob.send(json!({"type": "join", "code": code}));
+    bob.expect("lobby");
+    bob.expect("game");
+    let clock = bob.expect("clock");
+    assert_eq!(clock["control"], "fischer:1m+5s");
+    assert_eq!(clock["flagged"], Value::Null);
+    alice.expect("lobby");
+
+    // A quick move gets more back from the increment than it used
+    alice.play(0, "4");
+    assert_move(bob.expect("move"), 0, "4");
+    let remaining = bob.expect("clock")["remaining"][0].as_u64().unwrap();
+    assert!(remaining > 60000 && remaining <= 65000, "{remaining}");
+    assert_eq!(alice.expect("clock")["used"], 0);
+
+    // Only the server says how long anyone has
+    bob.send(json!({"type": "clock", "control": "move:1s", "remaining": [0, 0], "used": 0, "flagged": 1}));
+    assert_eq!(bob.expect("error")["message"], "only the server keeps time");
+}
+
+#[test]
+fn running_out_of_time_loses() {
+    let server = Server::start();
+    let mut alice = Client::connect(&server, "alice");
+    let mut bob = Client::connect(&server, "bob");
+
+    alice.send(json!({"type": "create", "game": standard_game(""), "clock": "move:1s"}));
+    let code = alice.expect("lobby")["code"].as_str().unwrap().to_string();
+    alice.expect("game");
+    alice.expect("clock");
+
+    // The clock only starts once both players are there
+    thread::sleep(Duration::from_millis(1200));
+    bob.send(json!({"type": "join", "code": code}));
+    bob.expect("lobby");
+    bob.expect("game");
+    assert_eq!(bob.expect("clock")["flagged"], Value::Null);
+    alice.expect("lobby");
+
+    thread::sleep(Duration::from_millis(1500));
+    assert_eq!(alice.expect("clock")["flagged"], 1);
+    assert_eq!(bob.expect("clock")["flagged"], 1);
+
+    // So the game is over, and moves are too late
+    alice.play(0, "4");
+    assert_eq!(alice.expect("game")["game"]["moves"], "");
+}